@@ -1,3 +1,5 @@
+#![allow(non_local_definitions)]
+
 #[macro_use]
 extern crate diesel;
 
@@ -0,0 +1,75 @@
+use diesel::{
+    backend::Backend,
+    expression::{AppearsOnTable, Expression, SelectableExpression},
+    query_builder::{AstPass, QueryFragment, QueryId},
+    result::QueryResult,
+    sql_types::Foldable,
+};
+
+// The `SUM` and `AVG` expressions of diesel live in a private module, so they cannot appear in
+// the bounds of `sum_async` and `avg_async`; these are equivalent nameable ones
+macro_rules! aggregate {
+    ($(#[$meta:meta])* $name:ident, $sql:expr, $sql_type:ident) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy)]
+        pub struct $name<E>(pub(crate) E);
+
+        impl<E> Expression for $name<E>
+        where
+            E: Expression,
+            E::SqlType: Foldable,
+        {
+            type SqlType = <E::SqlType as Foldable>::$sql_type;
+        }
+
+        impl<E, QS> SelectableExpression<QS> for $name<E>
+        where
+            E: SelectableExpression<QS>,
+            Self: AppearsOnTable<QS>,
+        {
+        }
+
+        impl<E, QS> AppearsOnTable<QS> for $name<E>
+        where
+            E: AppearsOnTable<QS>,
+            Self: Expression,
+        {
+        }
+
+        impl<E> QueryId for $name<E>
+        where
+            E: QueryId,
+        {
+            type QueryId = $name<E::QueryId>;
+
+            const HAS_STATIC_QUERY_ID: bool = E::HAS_STATIC_QUERY_ID;
+        }
+
+        impl<E, DB> QueryFragment<DB> for $name<E>
+        where
+            DB: Backend,
+            E: QueryFragment<DB>,
+        {
+            fn walk_ast(&self, mut out: AstPass<DB>) -> QueryResult<()> {
+                out.push_sql(concat!($sql, "("));
+                self.0.walk_ast(out.reborrow())?;
+                out.push_sql(")");
+                Ok(())
+            }
+        }
+    };
+}
+
+aggregate!(
+    /// The `SUM` of an expression, selected by `AsyncRunQueryDsl::sum_async`.
+    Sum,
+    "SUM",
+    Sum
+);
+
+aggregate!(
+    /// The `AVG` of an expression, selected by `AsyncRunQueryDsl::avg_async`.
+    Avg,
+    "AVG",
+    Avg
+);
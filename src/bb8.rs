@@ -0,0 +1,303 @@
+use crate::{
+    rt, trace::OpSpan, transaction_mut, AsyncConnection, AsyncError, AsyncResult,
+    AsyncSimpleConnection, PoolState,
+};
+use async_trait::async_trait;
+use bb8::{Pool, RunError};
+use bb8_diesel::{DieselConnection, DieselConnectionManager};
+use diesel::{result::QueryResult, Connection};
+use std::time::{Duration, Instant};
+#[cfg(feature = "tokio-rt-threaded")]
+use tokio::task;
+
+// Error type threaded through `bb8::Pool::run`, keeping checkout failures apart from
+// errors raised by the blocking work itself
+enum RunFailure {
+    Checkout(diesel::r2d2::Error),
+    Async(AsyncError),
+}
+
+impl From<diesel::r2d2::Error> for RunFailure {
+    fn from(err: diesel::r2d2::Error) -> Self {
+        RunFailure::Checkout(err)
+    }
+}
+
+// Errors from the blocking work have already been recorded, anything else failed the checkout
+fn checkout_error(span: &OpSpan, start: Instant, err: RunError<RunFailure>) -> AsyncError {
+    let err = match err {
+        RunError::User(RunFailure::Async(err)) => return err,
+        RunError::User(RunFailure::Checkout(err)) => AsyncError::Checkout(Box::new(err)),
+        RunError::TimedOut => {
+            AsyncError::Checkout(Box::new(RunError::<diesel::r2d2::Error>::TimedOut))
+        }
+    };
+    span.checkout_failed(start, err)
+}
+
+// Check out a connection asynchronously and run `f` with it on the blocking thread pool.
+// `bb8::Pool::run` only returns the connection to the pool once its future completes, and
+// would lose it if that future were dropped, so callers go through `spawn_with_conn`.
+async fn run_with_conn<Conn, R, Func>(
+    pool: &Pool<DieselConnectionManager<Conn>>,
+    op: &'static str,
+    f: Func,
+) -> AsyncResult<R>
+where
+    Conn: 'static + Connection,
+    R: 'static + Send,
+    Func: 'static + FnOnce(&mut Conn) -> AsyncResult<R> + Send,
+{
+    let span = OpSpan::new(op);
+    let start = Instant::now();
+    let span_ = span.clone();
+    pool.run(move |mut conn: DieselConnection<Conn>| async move {
+        span_.checked_out(start);
+        let (result, conn) = rt::spawn_blocking(move || {
+            let result = span_.in_scope(|| {
+                let start = Instant::now();
+                span_.finished(start, f(&mut *conn))
+            });
+            (result, conn)
+        })
+        .await;
+
+        match result {
+            Ok(value) => Ok((value, conn)),
+            Err(err) => Err((RunFailure::Async(err), conn)),
+        }
+    })
+    .await
+    .map_err(|err| checkout_error(&span, start, err))
+}
+
+// Like `run_with_conn`, but spawned as a task of its own, so that the connection is still
+// returned to the pool if the caller stops waiting, e.g. when racing another future
+async fn spawn_with_conn<Conn, R, Func>(
+    pool: &Pool<DieselConnectionManager<Conn>>,
+    op: &'static str,
+    f: Func,
+) -> AsyncResult<R>
+where
+    Conn: 'static + Connection,
+    R: 'static + Send,
+    Func: 'static + FnOnce(&mut Conn) -> AsyncResult<R> + Send,
+{
+    let pool = pool.clone();
+    rt::spawn(async move { run_with_conn(&pool, op, f).await }).await
+}
+
+#[cfg(not(feature = "tokio-rt-threaded"))]
+async fn with_conn<Conn, R, Func>(
+    pool: &Pool<DieselConnectionManager<Conn>>,
+    op: &'static str,
+    f: Func,
+) -> AsyncResult<R>
+where
+    Conn: 'static + Connection,
+    R: 'static + Send,
+    Func: 'static + FnOnce(&mut Conn) -> AsyncResult<R> + Send,
+{
+    spawn_with_conn(pool, op, f).await
+}
+
+// The closure borrows from the caller and cannot be spawned, but the connection is only held
+// within `block_in_place`, which the caller cannot stop waiting for
+#[cfg(feature = "tokio-rt-threaded")]
+async fn with_conn<Conn, R, Func>(
+    pool: &Pool<DieselConnectionManager<Conn>>,
+    op: &'static str,
+    f: Func,
+) -> AsyncResult<R>
+where
+    Conn: 'static + Connection,
+    R: 'static + Send,
+    Func: FnOnce(&mut Conn) -> AsyncResult<R> + Send,
+{
+    let span = OpSpan::new(op);
+    let start = Instant::now();
+    pool.run(|mut conn: DieselConnection<Conn>| async {
+        span.checked_out(start);
+        let result = task::block_in_place(|| {
+            span.in_scope(|| {
+                let start = Instant::now();
+                span.finished(start, f(&mut *conn))
+            })
+        });
+
+        match result {
+            Ok(value) => Ok((value, conn)),
+            Err(err) => Err((RunFailure::Async(err), conn)),
+        }
+    })
+    .await
+    .map_err(|err| checkout_error(&span, start, err))
+}
+
+// Like `spawn_with_conn`, but gives up waiting after `timeout`. The connection is still
+// returned once the work completes.
+async fn with_conn_timeout<Conn, R, Func>(
+    pool: &Pool<DieselConnectionManager<Conn>>,
+    op: &'static str,
+    timeout: Duration,
+    f: Func,
+) -> AsyncResult<R>
+where
+    Conn: 'static + Connection,
+    R: 'static + Send,
+    Func: 'static + FnOnce(&mut Conn) -> AsyncResult<R> + Send,
+{
+    rt::timeout(timeout, spawn_with_conn(pool, op, f)).await?
+}
+
+#[async_trait]
+impl<Conn> AsyncSimpleConnection<Conn> for Pool<DieselConnectionManager<Conn>>
+where
+    Conn: 'static + Connection,
+{
+    #[inline]
+    async fn batch_execute_async(&self, query: &str) -> AsyncResult<()> {
+        let query = query.to_string();
+        spawn_with_conn(self, "batch_execute", move |conn| {
+            conn.batch_execute(&query).map_err(AsyncError::Error)
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl<Conn> AsyncConnection<Conn> for Pool<DieselConnectionManager<Conn>>
+where
+    Conn: 'static + Connection,
+{
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn run<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        with_conn(self, "run", move |conn| f(conn).map_err(AsyncError::Error)).await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn run<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        with_conn(self, "run", move |conn| f(conn).map_err(AsyncError::Error)).await
+    }
+
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn transaction<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        with_conn(self, "transaction", move |conn| {
+            conn.transaction(|| f(conn)).map_err(AsyncError::Error)
+        })
+        .await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn transaction<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        with_conn(self, "transaction", move |conn| {
+            conn.transaction(|| f(conn)).map_err(AsyncError::Error)
+        })
+        .await
+    }
+
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn run_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        with_conn(self, "run_mut", move |conn| {
+            f(conn).map_err(AsyncError::Error)
+        })
+        .await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn run_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        with_conn(self, "run_mut", move |conn| {
+            f(conn).map_err(AsyncError::Error)
+        })
+        .await
+    }
+
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn transaction_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        with_conn(self, "transaction_mut", move |conn| {
+            transaction_mut(conn, f).map_err(AsyncError::Error)
+        })
+        .await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn transaction_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        with_conn(self, "transaction_mut", move |conn| {
+            transaction_mut(conn, f).map_err(AsyncError::Error)
+        })
+        .await
+    }
+
+    #[inline]
+    async fn run_timeout<R, Func>(&self, timeout: Duration, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        with_conn_timeout(self, "run_timeout", timeout, move |conn| {
+            f(conn).map_err(AsyncError::Error)
+        })
+        .await
+    }
+
+    #[inline]
+    async fn transaction_timeout<R, Func>(&self, timeout: Duration, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        with_conn_timeout(self, "transaction_timeout", timeout, move |conn| {
+            conn.transaction(|| f(conn)).map_err(AsyncError::Error)
+        })
+        .await
+    }
+
+    #[inline]
+    async fn pool_state_async(&self) -> AsyncResult<PoolState> {
+        let pool = self.clone();
+        rt::spawn_blocking(move || {
+            let state = pool.state();
+            Ok(PoolState {
+                connections: state.connections,
+                idle_connections: state.idle_connections,
+            })
+        })
+        .await
+    }
+}
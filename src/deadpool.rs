@@ -0,0 +1,255 @@
+use crate::{
+    rt, trace::OpSpan, transaction_mut, AsyncConnection, AsyncError, AsyncResult,
+    AsyncSimpleConnection, PoolState,
+};
+use async_trait::async_trait;
+use deadpool_diesel::{InteractError, Manager, Pool};
+use diesel::{result::QueryResult, Connection};
+use std::{
+    ops::Deref,
+    panic,
+    time::{Duration, Instant},
+};
+#[cfg(feature = "tokio-rt-threaded")]
+use tokio::task;
+
+// Check out a connection asynchronously, recording the checkout on `span`
+async fn checkout<Conn>(
+    pool: &Pool<Manager<Conn>>,
+    span: &OpSpan,
+) -> AsyncResult<impl Deref<Target = deadpool_diesel::Connection<Conn>>>
+where
+    Conn: 'static + Connection,
+{
+    let start = Instant::now();
+    let conn = pool
+        .get()
+        .await
+        .map_err(|err| span.checkout_failed(start, AsyncError::Checkout(Box::new(err))))?;
+    span.checked_out(start);
+    Ok(conn)
+}
+
+// Check out a connection and run `f` with it through `interact`, i.e. on the blocking thread
+// pool of the runtime the deadpool manager was created with
+async fn interact<Conn, R, Func>(
+    pool: &Pool<Manager<Conn>>,
+    op: &'static str,
+    f: Func,
+) -> AsyncResult<R>
+where
+    Conn: 'static + Connection,
+    R: 'static + Send,
+    Func: 'static + FnOnce(&mut Conn) -> QueryResult<R> + Send,
+{
+    let span = OpSpan::new(op);
+    let conn = checkout(pool, &span).await?;
+    conn.interact(move |conn| {
+        span.in_scope(|| {
+            let start = Instant::now();
+            span.finished(start, f(conn).map_err(AsyncError::Error))
+        })
+    })
+    .await
+    .unwrap_or_else(|err| match err {
+        InteractError::Panic(payload) => panic::resume_unwind(payload),
+        InteractError::Aborted => Err(AsyncError::Checkout(
+            "the connection was aborted before it could be used".into(),
+        )),
+    })
+}
+
+#[cfg(not(feature = "tokio-rt-threaded"))]
+async fn with_conn<Conn, R, Func>(
+    pool: &Pool<Manager<Conn>>,
+    op: &'static str,
+    f: Func,
+) -> AsyncResult<R>
+where
+    Conn: 'static + Connection,
+    R: 'static + Send,
+    Func: 'static + FnOnce(&mut Conn) -> QueryResult<R> + Send,
+{
+    interact(pool, op, f).await
+}
+
+#[cfg(feature = "tokio-rt-threaded")]
+async fn with_conn<Conn, R, Func>(
+    pool: &Pool<Manager<Conn>>,
+    op: &'static str,
+    f: Func,
+) -> AsyncResult<R>
+where
+    Conn: 'static + Connection,
+    R: 'static + Send,
+    Func: FnOnce(&mut Conn) -> QueryResult<R> + Send,
+{
+    let span = OpSpan::new(op);
+    let conn = checkout(pool, &span).await?;
+    task::block_in_place(|| {
+        let mut conn = conn
+            .lock()
+            .map_err(|_| AsyncError::Checkout("the connection was poisoned by a panic".into()))?;
+        span.in_scope(|| {
+            let start = Instant::now();
+            span.finished(start, f(&mut *conn).map_err(AsyncError::Error))
+        })
+    })
+}
+
+// Like `interact`, but gives up waiting after `timeout`. The pool interaction is spawned as
+// its own task so the connection is still returned once the work completes.
+async fn with_conn_timeout<Conn, R, Func>(
+    pool: &Pool<Manager<Conn>>,
+    op: &'static str,
+    timeout: Duration,
+    f: Func,
+) -> AsyncResult<R>
+where
+    Conn: 'static + Connection,
+    R: 'static + Send,
+    Func: 'static + FnOnce(&mut Conn) -> QueryResult<R> + Send,
+{
+    let pool = pool.clone();
+    let handle = rt::spawn(async move { interact(&pool, op, f).await });
+    rt::timeout(timeout, handle).await?
+}
+
+#[async_trait]
+impl<Conn> AsyncSimpleConnection<Conn> for Pool<Manager<Conn>>
+where
+    Conn: 'static + Connection,
+{
+    #[inline]
+    async fn batch_execute_async(&self, query: &str) -> AsyncResult<()> {
+        let query = query.to_string();
+        interact(self, "batch_execute", move |conn| {
+            conn.batch_execute(&query)
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl<Conn> AsyncConnection<Conn> for Pool<Manager<Conn>>
+where
+    Conn: 'static + Connection,
+{
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn run<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        with_conn(self, "run", move |conn| f(conn)).await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn run<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        with_conn(self, "run", move |conn| f(conn)).await
+    }
+
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn transaction<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        with_conn(self, "transaction", move |conn| {
+            conn.transaction(|| f(conn))
+        })
+        .await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn transaction<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        with_conn(self, "transaction", move |conn| {
+            conn.transaction(|| f(conn))
+        })
+        .await
+    }
+
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn run_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        with_conn(self, "run_mut", f).await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn run_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        with_conn(self, "run_mut", f).await
+    }
+
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn transaction_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        with_conn(self, "transaction_mut", move |conn| {
+            transaction_mut(conn, f)
+        })
+        .await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn transaction_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        with_conn(self, "transaction_mut", move |conn| {
+            transaction_mut(conn, f)
+        })
+        .await
+    }
+
+    #[inline]
+    async fn run_timeout<R, Func>(&self, timeout: Duration, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        with_conn_timeout(self, "run_timeout", timeout, move |conn| f(conn)).await
+    }
+
+    #[inline]
+    async fn transaction_timeout<R, Func>(&self, timeout: Duration, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        with_conn_timeout(self, "transaction_timeout", timeout, move |conn| {
+            conn.transaction(|| f(conn))
+        })
+        .await
+    }
+
+    #[inline]
+    async fn pool_state_async(&self) -> AsyncResult<PoolState> {
+        let status = self.status();
+        Ok(PoolState {
+            connections: status.size as u32,
+            idle_connections: status.available.max(0) as u32,
+        })
+    }
+}
@@ -0,0 +1,70 @@
+use diesel::{query_builder::AsQuery, query_dsl::RunQueryDsl};
+use std::fmt;
+
+/// Build a query on the blocking thread that runs it, for the async DSL methods.
+///
+/// Boxed queries, i.e. those of `into_boxed()`, hold their clauses as `dyn QueryFragment`
+/// without a `Send` bound, so they cannot be handed to the blocking thread pool and never
+/// satisfy the bounds of `AsyncRunQueryDsl`. Moving the code that composes the query into
+/// `build` instead, with only its inputs captured, makes dynamic queries loadable all the same:
+///
+/// ```no_run
+/// # #[macro_use] extern crate diesel;
+/// # use diesel::{pg::PgConnection, prelude::*, r2d2::{ConnectionManager, Pool}};
+/// # use tokio_diesel::{defer, AsyncRunQueryDsl};
+/// # table! { users (id) { id -> Integer, name -> Text, } }
+/// # async fn users_named(
+/// #     pool: &Pool<ConnectionManager<PgConnection>>,
+/// #     name: Option<String>,
+/// # ) -> tokio_diesel::AsyncResult<Vec<(i32, String)>> {
+/// let rows = defer(move || {
+///     let mut query = users::table.into_boxed();
+///     if let Some(name) = name {
+///         query = query.filter(users::name.eq(name));
+///     }
+///     query
+/// })
+/// .load_async(pool)
+/// .await?;
+/// # Ok(rows)
+/// # }
+/// # fn main() {}
+/// ```
+///
+/// The deferred query supports the methods that load rows, like `load_async`,
+/// `get_result_async` and `get_results_async`, but not those adding clauses of their own
+/// such as `first_async`; add those in `build`.
+pub fn defer<F, Q>(build: F) -> Deferred<F>
+where
+    F: FnOnce() -> Q,
+    Q: AsQuery,
+{
+    Deferred { build }
+}
+
+/// A query built only once it runs, created by `defer`.
+#[derive(Clone, Copy)]
+pub struct Deferred<F> {
+    build: F,
+}
+
+impl<F> fmt::Debug for Deferred<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Deferred").finish()
+    }
+}
+
+impl<F, Q> AsQuery for Deferred<F>
+where
+    F: FnOnce() -> Q,
+    Q: AsQuery,
+{
+    type SqlType = Q::SqlType;
+    type Query = Q::Query;
+
+    fn as_query(self) -> Q::Query {
+        (self.build)().as_query()
+    }
+}
+
+impl<F, Conn> RunQueryDsl<Conn> for Deferred<F> {}
@@ -0,0 +1,424 @@
+#[cfg(feature = "tokio-rt-threaded")]
+use crate::AsyncTransactionBlocking;
+use crate::{
+    AsyncConnection, AsyncResult, AsyncRunWithCheckoutTimeout, AsyncSimpleConnection, AsyncTryRun,
+    PoolState,
+};
+use async_trait::async_trait;
+use diesel::{result::QueryResult, Connection};
+use std::{sync::Arc, time::Duration};
+
+// Forward the async traits through shared pointers so that e.g. `Arc<Pool<_>>` can be used
+// directly wherever a pool is accepted
+
+#[async_trait]
+impl<Conn, P> AsyncSimpleConnection<Conn> for Arc<P>
+where
+    Conn: 'static + Connection,
+    P: AsyncSimpleConnection<Conn> + Send + Sync,
+{
+    #[inline]
+    async fn batch_execute_async(&self, query: &str) -> AsyncResult<()> {
+        (**self).batch_execute_async(query).await
+    }
+}
+
+#[async_trait]
+impl<Conn, P> AsyncConnection<Conn> for Arc<P>
+where
+    Conn: 'static + Connection,
+    P: AsyncConnection<Conn> + Send + Sync,
+{
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn run<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        (**self).run(f).await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn run<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        (**self).run(f).await
+    }
+
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn transaction<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        (**self).transaction(f).await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn transaction<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        (**self).transaction(f).await
+    }
+
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn run_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        (**self).run_mut(f).await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn run_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        (**self).run_mut(f).await
+    }
+
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn transaction_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        (**self).transaction_mut(f).await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn transaction_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        (**self).transaction_mut(f).await
+    }
+
+    #[inline]
+    async fn run_timeout<R, Func>(&self, timeout: Duration, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        (**self).run_timeout(timeout, f).await
+    }
+
+    #[inline]
+    async fn transaction_timeout<R, Func>(&self, timeout: Duration, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        (**self).transaction_timeout(timeout, f).await
+    }
+
+    #[inline]
+    async fn pool_state_async(&self) -> AsyncResult<PoolState> {
+        (**self).pool_state_async().await
+    }
+
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn run_read<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        (**self).run_read(f).await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn run_read<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        (**self).run_read(f).await
+    }
+}
+
+#[async_trait]
+impl<Conn, P> AsyncTryRun<Conn> for Arc<P>
+where
+    Conn: 'static + Connection,
+    P: AsyncTryRun<Conn> + Send + Sync,
+{
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn try_run<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        (**self).try_run(f).await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn try_run<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        (**self).try_run(f).await
+    }
+}
+
+#[async_trait]
+impl<Conn, P> AsyncRunWithCheckoutTimeout<Conn> for Arc<P>
+where
+    Conn: 'static + Connection,
+    P: AsyncRunWithCheckoutTimeout<Conn> + Send + Sync,
+{
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn run_with_checkout_timeout<R, Func>(&self, timeout: Duration, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        (**self).run_with_checkout_timeout(timeout, f).await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn run_with_checkout_timeout<R, Func>(&self, timeout: Duration, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        (**self).run_with_checkout_timeout(timeout, f).await
+    }
+}
+
+#[cfg(feature = "tokio-rt-threaded")]
+#[async_trait]
+impl<Conn, P> AsyncTransactionBlocking<Conn> for Arc<P>
+where
+    Conn: 'static + Connection,
+    P: AsyncTransactionBlocking<Conn> + Send + Sync,
+{
+    #[inline]
+    async fn transaction_blocking<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        (**self).transaction_blocking(f).await
+    }
+}
+
+#[async_trait]
+impl<Conn, P> AsyncSimpleConnection<Conn> for &P
+where
+    Conn: 'static + Connection,
+    P: AsyncSimpleConnection<Conn> + Send + Sync,
+{
+    #[inline]
+    async fn batch_execute_async(&self, query: &str) -> AsyncResult<()> {
+        (**self).batch_execute_async(query).await
+    }
+}
+
+#[async_trait]
+impl<Conn, P> AsyncConnection<Conn> for &P
+where
+    Conn: 'static + Connection,
+    P: AsyncConnection<Conn> + Send + Sync,
+{
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn run<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        (**self).run(f).await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn run<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        (**self).run(f).await
+    }
+
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn transaction<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        (**self).transaction(f).await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn transaction<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        (**self).transaction(f).await
+    }
+
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn run_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        (**self).run_mut(f).await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn run_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        (**self).run_mut(f).await
+    }
+
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn transaction_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        (**self).transaction_mut(f).await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn transaction_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        (**self).transaction_mut(f).await
+    }
+
+    #[inline]
+    async fn run_timeout<R, Func>(&self, timeout: Duration, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        (**self).run_timeout(timeout, f).await
+    }
+
+    #[inline]
+    async fn transaction_timeout<R, Func>(&self, timeout: Duration, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        (**self).transaction_timeout(timeout, f).await
+    }
+
+    #[inline]
+    async fn pool_state_async(&self) -> AsyncResult<PoolState> {
+        (**self).pool_state_async().await
+    }
+
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn run_read<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        (**self).run_read(f).await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn run_read<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        (**self).run_read(f).await
+    }
+}
+
+#[async_trait]
+impl<Conn, P> AsyncTryRun<Conn> for &P
+where
+    Conn: 'static + Connection,
+    P: AsyncTryRun<Conn> + Send + Sync,
+{
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn try_run<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        (**self).try_run(f).await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn try_run<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        (**self).try_run(f).await
+    }
+}
+
+#[async_trait]
+impl<Conn, P> AsyncRunWithCheckoutTimeout<Conn> for &P
+where
+    Conn: 'static + Connection,
+    P: AsyncRunWithCheckoutTimeout<Conn> + Send + Sync,
+{
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn run_with_checkout_timeout<R, Func>(&self, timeout: Duration, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        (**self).run_with_checkout_timeout(timeout, f).await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn run_with_checkout_timeout<R, Func>(&self, timeout: Duration, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        (**self).run_with_checkout_timeout(timeout, f).await
+    }
+}
+
+#[cfg(feature = "tokio-rt-threaded")]
+#[async_trait]
+impl<Conn, P> AsyncTransactionBlocking<Conn> for &P
+where
+    Conn: 'static + Connection,
+    P: AsyncTransactionBlocking<Conn> + Send + Sync,
+{
+    #[inline]
+    async fn transaction_blocking<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        (**self).transaction_blocking(f).await
+    }
+}
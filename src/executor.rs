@@ -0,0 +1,53 @@
+use crate::rt;
+use futures::future::{BoxFuture, FutureExt};
+
+/// Runs the blocking work of an `AsyncPool`, set with `AsyncPoolBuilder::executor`.
+///
+/// ```no_run
+/// # use diesel::pg::PgConnection;
+/// # use futures::{channel::oneshot, future::{BoxFuture, FutureExt}};
+/// # use tokio_diesel::{AsyncPool, BlockingExecutor};
+/// // Runs every operation on a thread of its own
+/// struct ThreadExecutor;
+///
+/// impl BlockingExecutor for ThreadExecutor {
+///     fn spawn_blocking<F, R>(&self, f: F) -> BoxFuture<'static, R>
+///     where
+///         F: 'static + FnOnce() -> R + Send,
+///         R: 'static + Send,
+///     {
+///         let (tx, rx) = oneshot::channel();
+///         std::thread::spawn(move || {
+///             let _ = tx.send(f());
+///         });
+///         rx.map(|result| result.expect("the blocking work panicked")).boxed()
+///     }
+/// }
+///
+/// let pool = AsyncPool::<PgConnection>::builder()
+///     .executor(ThreadExecutor)
+///     .build("postgres://localhost/app")?;
+/// # Ok::<(), tokio_diesel::BuildError>(())
+/// ```
+pub trait BlockingExecutor: 'static + Send + Sync {
+    /// Run `f` away from the async tasks, resolving with its result once it has finished.
+    fn spawn_blocking<F, R>(&self, f: F) -> BoxFuture<'static, R>
+    where
+        F: 'static + FnOnce() -> R + Send,
+        R: 'static + Send;
+}
+
+/// The blocking thread pool of the runtime, the executor of an `AsyncPool` unless another one
+/// is set.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultExecutor;
+
+impl BlockingExecutor for DefaultExecutor {
+    fn spawn_blocking<F, R>(&self, f: F) -> BoxFuture<'static, R>
+    where
+        F: 'static + FnOnce() -> R + Send,
+        R: 'static + Send,
+    {
+        rt::spawn_blocking_uninstrumented(f).boxed()
+    }
+}
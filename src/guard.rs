@@ -0,0 +1,224 @@
+use crate::{rollback_to, rt, AsyncConnection, AsyncError, AsyncResult};
+#[cfg(not(feature = "tokio-rt-threaded"))]
+use crate::{transaction_mut, AsyncSimpleConnection, PoolState};
+#[cfg(not(feature = "tokio-rt-threaded"))]
+use async_trait::async_trait;
+use diesel::{connection::TransactionManager, result::QueryResult, Connection};
+use futures::channel::oneshot;
+#[cfg(not(feature = "tokio-rt-threaded"))]
+use futures::future::BoxFuture;
+#[cfg(not(feature = "tokio-rt-threaded"))]
+use std::time::Duration;
+use std::{
+    any::Any,
+    fmt,
+    panic::{self, AssertUnwindSafe},
+    sync::{mpsc, Mutex, PoisonError},
+};
+
+// A job is handed the connection, or the error that kept the worker from cleaning it up
+type Job<Conn> = Box<dyn FnOnce(QueryResult<&mut Conn>) + Send>;
+
+// Reports the state of the pool the connection of a guard was checked out from
+#[cfg(not(feature = "tokio-rt-threaded"))]
+type PoolStateFn = Box<dyn Fn() -> BoxFuture<'static, AsyncResult<PoolState>> + Send + Sync>;
+
+/// A connection checked out by `AsyncConnection::acquire_async`, on which any number of
+/// operations can run one after the other, e.g. creating a temporary table and querying it.
+///
+/// The connection is owned by a worker on the blocking thread pool that runs the operations
+/// handed to `run_on` in order. Holding the guard pins both that connection and a blocking
+/// thread, so it should be dropped as soon as it is no longer needed; the connection is then
+/// returned to the pool once the last operation has finished. A panic of an operation is
+/// resumed on the task awaiting it, and the transactions it left open on the connection are
+/// rolled back before the next one runs.
+///
+/// The guard implements `AsyncConnection` itself, so that `query.load_async(&guard)` and any
+/// other DSL method run on the held connection without another checkout, e.g. to set a session
+/// variable and read it back. This is not available with the `tokio-rt-threaded` feature,
+/// whose closures need not be `'static` and so cannot be handed to the worker.
+pub struct AsyncConnGuard<Conn> {
+    // `mpsc::Sender` is only `Sync` as of Rust 1.72
+    jobs: Mutex<mpsc::Sender<Job<Conn>>>,
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    pool_state: PoolStateFn,
+}
+
+impl<Conn> AsyncConnGuard<Conn>
+where
+    Conn: 'static + Connection,
+{
+    /// Run `f` on the connection held by this guard.
+    pub async fn run_on<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        self.run_on_mut(move |conn| f(conn)).await
+    }
+
+    /// Like `run_on`, but hands `&mut Conn` to the closure.
+    pub async fn run_on_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        let (tx, rx) = oneshot::channel::<Result<QueryResult<R>, Box<dyn Any + Send>>>();
+        let job: Job<Conn> = Box::new(move |conn| {
+            let result = match conn {
+                Ok(conn) => panic::catch_unwind(AssertUnwindSafe(|| f(conn))),
+                Err(err) => Ok(Err(err)),
+            };
+            let _ = tx.send(result);
+        });
+        self.jobs
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .send(job)
+            .expect(RUNNING);
+
+        match rx.await.expect(RUNNING) {
+            Ok(result) => result.map_err(AsyncError::Error),
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    }
+}
+
+#[cfg(not(feature = "tokio-rt-threaded"))]
+#[async_trait]
+impl<Conn> AsyncSimpleConnection<Conn> for AsyncConnGuard<Conn>
+where
+    Conn: 'static + Connection,
+{
+    #[inline]
+    async fn batch_execute_async(&self, query: &str) -> AsyncResult<()> {
+        let query = query.to_string();
+        self.run_on(move |conn| conn.batch_execute(&query)).await
+    }
+}
+
+// The operations run on the held connection in the order they were started; one that timed
+// out still runs before the next ones
+#[cfg(not(feature = "tokio-rt-threaded"))]
+#[async_trait]
+impl<Conn> AsyncConnection<Conn> for AsyncConnGuard<Conn>
+where
+    Conn: 'static + Connection,
+{
+    #[inline]
+    async fn run<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        self.run_on(f).await
+    }
+
+    #[inline]
+    async fn transaction<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        self.run_on(move |conn| conn.transaction(|| f(conn))).await
+    }
+
+    #[inline]
+    async fn run_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        self.run_on_mut(f).await
+    }
+
+    #[inline]
+    async fn transaction_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        self.run_on_mut(move |conn| transaction_mut(conn, f)).await
+    }
+
+    #[inline]
+    async fn run_timeout<R, Func>(&self, timeout: Duration, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        rt::timeout(timeout, self.run(f)).await?
+    }
+
+    #[inline]
+    async fn transaction_timeout<R, Func>(&self, timeout: Duration, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        rt::timeout(timeout, self.transaction(f)).await?
+    }
+
+    // The state of the pool the held connection belongs to, in which it counts as in use
+    #[inline]
+    async fn pool_state_async(&self) -> AsyncResult<PoolState> {
+        (self.pool_state)().await
+    }
+}
+
+impl<Conn> fmt::Debug for AsyncConnGuard<Conn> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AsyncConnGuard").finish()
+    }
+}
+
+// Once the connection is checked out, the worker runs every job until the guard is dropped
+const RUNNING: &str = "the worker of a guard stopped while the guard was held";
+
+// Check out a connection of `asc` for a worker that runs the jobs of the returned guard
+pub(crate) async fn acquire<Conn, AsyncConn>(asc: AsyncConn) -> AsyncResult<AsyncConnGuard<Conn>>
+where
+    Conn: 'static + Connection,
+    AsyncConn: 'static + AsyncConnection<Conn> + Clone + Send + Sync,
+{
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    let pool_state: PoolStateFn = {
+        let asc = asc.clone();
+        Box::new(move || {
+            let asc = asc.clone();
+            Box::pin(async move { asc.pool_state_async().await })
+        })
+    };
+
+    let (jobs, queue) = mpsc::channel::<Job<Conn>>();
+    let (ready_tx, ready_rx) = oneshot::channel();
+    let worker = rt::spawn(async move {
+        asc.run_mut(move |conn| {
+            let _ = ready_tx.send(());
+            let depth = conn.transaction_manager().get_transaction_depth();
+            for job in queue {
+                // A panic of an earlier job is resumed on the task awaiting it, the transactions
+                // it left open are rolled back before the connection is used again
+                let cleaned = rollback_to(conn, depth);
+                job(cleaned.map(|()| &mut *conn));
+            }
+            Ok(())
+        })
+        .await
+    });
+
+    match ready_rx.await {
+        // The worker keeps running until the guard is dropped, the task is detached
+        Ok(()) => {
+            drop(worker);
+            Ok(AsyncConnGuard {
+                jobs: Mutex::new(jobs),
+                #[cfg(not(feature = "tokio-rt-threaded"))]
+                pool_state,
+            })
+        }
+        Err(_) => Err(worker
+            .await
+            .expect_err("the worker only stops after the connection was checked out")),
+    }
+}
@@ -0,0 +1,43 @@
+use crate::rt;
+use futures::future::{BoxFuture, Future, FutureExt};
+use std::{
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// The result of an operation spawned onto a task of its own, e.g. by `spawn_execute_async`.
+///
+/// The operation runs whether or not the handle is awaited. Dropping the handle detaches it:
+/// the operation still completes, but its result, including any error, is lost.
+pub struct JoinHandle<R> {
+    inner: BoxFuture<'static, R>,
+}
+
+impl<R> JoinHandle<R>
+where
+    R: 'static + Send,
+{
+    pub(crate) fn spawn<Fut>(fut: Fut) -> Self
+    where
+        Fut: 'static + Future<Output = R> + Send,
+    {
+        JoinHandle {
+            inner: rt::spawn(fut).boxed(),
+        }
+    }
+}
+
+impl<R> Future for JoinHandle<R> {
+    type Output = R;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<R> {
+        self.inner.poll_unpin(cx)
+    }
+}
+
+impl<R> fmt::Debug for JoinHandle<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("JoinHandle").finish()
+    }
+}
@@ -0,0 +1,34 @@
+use crate::AsyncError;
+use diesel::result::{DatabaseErrorKind, Error};
+
+impl AsyncError {
+    /// The HTTP status code a web application would usually answer with when an operation
+    /// failed with this error:
+    ///
+    /// - 404 when the query found no row where one was expected,
+    /// - 409 when it violated a unique or foreign key constraint, or a serializable
+    ///   transaction conflicted with another one,
+    /// - 503 when no connection could be had in time, or the pool is shutting down,
+    /// - 500 for anything else.
+    ///
+    /// Diesel 1.x reports other constraint violations, such as check constraints, without a
+    /// kind of their own, so these end up as 500.
+    pub fn to_http_status(&self) -> u16 {
+        if self.is_not_found() {
+            return 404;
+        }
+        match *self.innermost() {
+            AsyncError::Error(Error::DatabaseError(
+                DatabaseErrorKind::UniqueViolation
+                | DatabaseErrorKind::ForeignKeyViolation
+                | DatabaseErrorKind::SerializationFailure,
+                _,
+            )) => 409,
+            AsyncError::Checkout(_)
+            | AsyncError::Timeout(_)
+            | AsyncError::ShuttingDown
+            | AsyncError::NoConnectionAvailable => 503,
+            _ => 500,
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use crate::{AsyncConnection, AsyncResult};
+use diesel::{
+    associations::HasTable,
+    backend::Backend,
+    dsl::Limit,
+    expression::Expression,
+    insertable::Insertable,
+    query_builder::{AstPass, InsertStatement, Query, QueryFragment, QueryId},
+    query_dsl::{
+        methods::{ExecuteDsl, LimitDsl, LoadQuery},
+        RunQueryDsl,
+    },
+    result::{DatabaseErrorKind, Error, QueryResult},
+    sql_types::HasSqlType,
+    Connection, Queryable, Table,
+};
+
+/// Insert `values` into `target` in chunks of at most `chunk_size` rows, returning the total
+/// number of rows inserted.
+///
+/// This keeps each statement below the bind parameter limit of the database (65535 for
+/// PostgreSQL). All chunks are inserted within a single transaction, so either every row is
+/// inserted or none are. An empty `values` returns 0 without touching the database.
+///
+/// # Panics
+///
+/// Panics if `chunk_size` is 0.
+pub async fn insert_chunked_async<T, V, Conn, AsyncConn>(
+    target: T,
+    values: Vec<V>,
+    chunk_size: usize,
+    asc: &AsyncConn,
+) -> AsyncResult<usize>
+where
+    T: 'static + Copy + Send,
+    V: 'static + Send,
+    Vec<V>: Insertable<T>,
+    InsertStatement<T, <Vec<V> as Insertable<T>>::Values>: ExecuteDsl<Conn>,
+    Conn: 'static + Connection,
+    AsyncConn: AsyncConnection<Conn>,
+{
+    assert!(chunk_size > 0, "chunk_size must be greater than 0");
+
+    if values.is_empty() {
+        return Ok(0);
+    }
+
+    let mut chunks = Vec::with_capacity(values.len().div_ceil(chunk_size));
+    let mut values = values.into_iter().peekable();
+    while values.peek().is_some() {
+        chunks.push(values.by_ref().take(chunk_size).collect::<Vec<_>>());
+    }
+
+    asc.transaction(move |conn| {
+        let mut inserted = 0;
+        for chunk in chunks {
+            inserted += diesel::insert_into(target).values(chunk).execute(conn)?;
+        }
+        Ok(inserted)
+    })
+    .await
+}
+
+/// Get-or-create: load the first row of `find`, or run `insert` and return the inserted row
+/// if there is none.
+///
+/// Both run in a single transaction. `insert` is loaded with `get_result`, so on PostgreSQL an
+/// `insert_into(..).values(..)` returns the new row through `RETURNING`. If a concurrent caller
+/// inserts the same row first, the resulting unique violation is rolled back to a savepoint and
+/// `find` is run again, so both callers get the same row.
+#[cfg(not(feature = "tokio-rt-threaded"))]
+pub async fn first_or_insert_async<U, Q, I, Conn, AsyncConn>(
+    find: Q,
+    insert: I,
+    asc: &AsyncConn,
+) -> AsyncResult<U>
+where
+    U: 'static + Send,
+    Q: 'static + Clone + RunQueryDsl<Conn> + LimitDsl + Send,
+    Limit<Q>: LoadQuery<Conn, U>,
+    I: 'static + RunQueryDsl<Conn> + LoadQuery<Conn, U> + Send,
+    Conn: 'static + Connection,
+    AsyncConn: AsyncConnection<Conn>,
+{
+    asc.transaction(move |conn| first_or_insert(find, insert, conn))
+        .await
+}
+
+/// Get-or-create: load the first row of `find`, or run `insert` and return the inserted row
+/// if there is none.
+///
+/// Both run in a single transaction. `insert` is loaded with `get_result`, so on PostgreSQL an
+/// `insert_into(..).values(..)` returns the new row through `RETURNING`. If a concurrent caller
+/// inserts the same row first, the resulting unique violation is rolled back to a savepoint and
+/// `find` is run again, so both callers get the same row.
+#[cfg(feature = "tokio-rt-threaded")]
+pub async fn first_or_insert_async<U, Q, I, Conn, AsyncConn>(
+    find: Q,
+    insert: I,
+    asc: &AsyncConn,
+) -> AsyncResult<U>
+where
+    U: 'static + Send,
+    Q: Clone + RunQueryDsl<Conn> + LimitDsl + Send,
+    Limit<Q>: LoadQuery<Conn, U>,
+    I: RunQueryDsl<Conn> + LoadQuery<Conn, U> + Send,
+    Conn: 'static + Connection,
+    AsyncConn: AsyncConnection<Conn>,
+{
+    asc.transaction(move |conn| first_or_insert(find, insert, conn))
+        .await
+}
+
+fn first_or_insert<U, Q, I, Conn>(find: Q, insert: I, conn: &Conn) -> QueryResult<U>
+where
+    Q: Clone + RunQueryDsl<Conn> + LimitDsl,
+    Limit<Q>: LoadQuery<Conn, U>,
+    I: RunQueryDsl<Conn> + LoadQuery<Conn, U>,
+    Conn: Connection,
+{
+    match find.clone().first(conn) {
+        Err(Error::NotFound) => {}
+        result => return result,
+    }
+
+    // The savepoint keeps the transaction usable when the insert loses a race
+    match conn.transaction(|| insert.get_result(conn)) {
+        Err(Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => find.first(conn),
+        result => result,
+    }
+}
+
+/// Run `insert` with a `RETURNING` clause for the primary key of the table and return the
+/// key of the inserted row, e.g. the id generated by the database.
+///
+/// If several rows are inserted, the key of the first one is returned; use
+/// `execute_returning_async` for all of them. `RETURNING` is supported by PostgreSQL and
+/// SQLite 3.35 or later.
+#[cfg(not(feature = "tokio-rt-threaded"))]
+pub async fn insert_and_get_id_async<T, U, Op, Id, Conn, AsyncConn>(
+    insert: InsertStatement<T, U, Op>,
+    asc: &AsyncConn,
+) -> AsyncResult<Id>
+where
+    T: 'static + Table + HasTable<Table = T> + Send,
+    T::PrimaryKey: 'static + QueryFragment<Conn::Backend> + Send,
+    U: 'static + Send,
+    Op: 'static + Send,
+    InsertStatement<T, U, Op>: QueryFragment<Conn::Backend>,
+    Id: 'static + Queryable<<T::PrimaryKey as Expression>::SqlType, Conn::Backend> + Send,
+    Conn: 'static + Connection,
+    Conn::Backend: HasSqlType<<T::PrimaryKey as Expression>::SqlType>,
+    AsyncConn: AsyncConnection<Conn>,
+{
+    let query = ReturningPrimaryKey {
+        key: T::table().primary_key(),
+        insert,
+    };
+    asc.run(move |conn| query.get_result(conn)).await
+}
+
+/// Run `insert` with a `RETURNING` clause for the primary key of the table and return the
+/// key of the inserted row, e.g. the id generated by the database.
+///
+/// If several rows are inserted, the key of the first one is returned; use
+/// `execute_returning_async` for all of them. `RETURNING` is supported by PostgreSQL and
+/// SQLite 3.35 or later.
+#[cfg(feature = "tokio-rt-threaded")]
+pub async fn insert_and_get_id_async<T, U, Op, Id, Conn, AsyncConn>(
+    insert: InsertStatement<T, U, Op>,
+    asc: &AsyncConn,
+) -> AsyncResult<Id>
+where
+    T: Table + HasTable<Table = T> + Send,
+    T::PrimaryKey: QueryFragment<Conn::Backend> + Send,
+    U: Send,
+    Op: Send,
+    InsertStatement<T, U, Op>: QueryFragment<Conn::Backend>,
+    Id: 'static + Queryable<<T::PrimaryKey as Expression>::SqlType, Conn::Backend> + Send,
+    Conn: 'static + Connection,
+    Conn::Backend: HasSqlType<<T::PrimaryKey as Expression>::SqlType>,
+    AsyncConn: AsyncConnection<Conn>,
+{
+    let query = ReturningPrimaryKey {
+        key: T::table().primary_key(),
+        insert,
+    };
+    asc.run(move |conn| query.get_result(conn)).await
+}
+
+// `INSERT ... RETURNING <primary key>`. Diesel's own returning clause cannot be named outside
+// of diesel, so the clause is appended here instead.
+struct ReturningPrimaryKey<I, K> {
+    insert: I,
+    key: K,
+}
+
+impl<I, K> QueryId for ReturningPrimaryKey<I, K> {
+    type QueryId = ();
+
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<I, K> Query for ReturningPrimaryKey<I, K>
+where
+    K: Expression,
+{
+    type SqlType = K::SqlType;
+}
+
+impl<I, K, Conn> RunQueryDsl<Conn> for ReturningPrimaryKey<I, K> {}
+
+impl<I, K, DB> QueryFragment<DB> for ReturningPrimaryKey<I, K>
+where
+    DB: Backend,
+    I: QueryFragment<DB>,
+    K: QueryFragment<DB>,
+{
+    fn walk_ast(&self, mut out: AstPass<DB>) -> QueryResult<()> {
+        self.insert.walk_ast(out.reborrow())?;
+        out.push_sql(" RETURNING ");
+        self.key.walk_ast(out.reborrow())?;
+        Ok(())
+    }
+}
@@ -3,35 +3,217 @@
 //! ## Feature Flags
 //!
 //! - __tokio-rt-threaded__: Available when using the `rt-threaded` feature of tokio.
-//! This feature will remove the `'static` lifetime restriction on the closures sent to the
-//! `AsyncConnection` trait by using `tokio::task::block_in_place` instead of
-//! `tokio::task::spawn_blocking`. It will also remove the `'static` restriction on the
-//! `AsyncRunQueryDsl` and `AsyncSaveChangesDsl` implementations.
+//!   This feature will remove the `'static` lifetime restriction on the closures sent to the
+//!   `AsyncConnection` trait by using `tokio::task::block_in_place` instead of
+//!   `tokio::task::spawn_blocking`. It will also remove the `'static` restriction on the
+//!   `AsyncRunQueryDsl` and `AsyncSaveChangesDsl` implementations.
+//!   Operations then have to run on the threaded scheduler of tokio: `block_in_place` panics on
+//!   the basic scheduler, e.g. under a plain `#[tokio::test]`, which needs to be
+//!   `#[tokio::test(threaded_scheduler)]` instead.
+//!   `run_timeout` and `transaction_timeout` are the exception: they keep the `'static` bound
+//!   and run the work on another thread, as the timeout can only be awaited meanwhile.
+//!
+//! - __bb8__: Implements the async traits for `bb8::Pool<bb8_diesel::DieselConnectionManager<_>>`.
+//!   Connections are checked out asynchronously and only the query itself runs on the blocking
+//!   thread pool.
+//!
+//! - __deadpool__: Implements the async traits for `deadpool_diesel::Pool`. Connections are checked
+//!   out asynchronously and queries run through `interact`, i.e. on the blocking thread pool of the
+//!   runtime the manager was created with. The `rt_tokio_1` runtime of `deadpool-diesel` needs a
+//!   tokio 1 runtime, so applications on tokio 0.2 should enable `rt_async-std_1` instead.
+//!
+//! - __mobc__: Implements the async traits for `mobc::Pool<MobcManager<_>>`. Connections are checked
+//!   out asynchronously and only the query itself runs on the blocking thread pool.
+//!
+//! - __tracing__: Opens a `tokio_diesel.run` span around every blocking database operation,
+//!   recording the operation, how long the checkout took and whether it succeeded, and how long
+//!   the query itself took. Failures are reported with `tracing::error!`. The span is a child of
+//!   the span current when the operation was started, even though it runs on another thread.
+//!   Pools built with `AsyncPoolBuilder::slow_query_threshold` also warn about slow queries,
+//!   and those built with `AsyncPoolBuilder::leak_detection_threshold` about connections held
+//!   for too long.
+//!
+//! - __async-std__: Runs the blocking work on the thread pool of `async-std` instead of tokio, for
+//!   applications driven by the `async-std` runtime. The public API is unchanged. This feature is
+//!   mutually exclusive with the tokio-specific `tokio-rt-threaded`, `bb8` and `mobc` features.
+//!
+//! - __metrics__: Records how long blocking database work waited for a thread of the blocking pool
+//!   before it started, as the `tokio_diesel_blocking_queue_seconds` histogram of the `metrics`
+//!   crate. A growing wait means the blocking pool is saturated rather than the database slow.
+//!
+//! - __mysql__: Adds `upsert_async` for MySQL's `INSERT ... ON DUPLICATE KEY UPDATE`.
+//!
+//! - __postgres__: Adds `run_cancellable`, which cancels the running query with
+//!   `pg_cancel_backend` when its future is dropped, and `AsyncCursorDsl` to stream large
+//!   result sets through a server-side cursor, `upsert_on_conflict_async`,
+//!   `upsert_on_conflict_returning_async` and `insert_ignore_async` for `INSERT ... ON CONFLICT`,
+//!   `count_estimate_async` for an approximate row count, `query_dynamic_async` to load the rows
+//!   of arbitrary SQL, `AsyncExplainDsl` to capture the plan of a query, `transaction_deferred`
+//!   to check deferrable constraints only at commit, as well as `run_with_statement_timeout` to
+//!   cap the execution time of the statements of a transaction.
+//!
+//! - __sqlite__: Adds `AsyncPoolBuilder::busy_timeout` and `transaction_retry_busy` for pools
+//!   with several connections to the same SQLite database, as well as `insert_and_get_rowid_async`
+//!   to get the rowid of an inserted row.
+//!
+//! - __test-util__: Adds `TestTransaction`, a connection inside a transaction that is never
+//!   committed, so integration tests leave no trace in the database.
+//!
+//! - __http__: Adds `AsyncError::to_http_status`, mapping an error to the HTTP status code a web
+//!   application would usually answer with, e.g. 404 when no row was found.
 
 use async_trait::async_trait;
 use diesel::{
-    connection::SimpleConnection,
-    dsl::Limit,
+    connection::{SimpleConnection, TransactionManager},
+    dsl::{count_star, exists, CountStar, Limit, Select},
+    expression::{exists::Exists, Expression},
+    query_builder::SelectStatement,
     query_dsl::{
-        methods::{ExecuteDsl, LimitDsl, LoadQuery},
+        methods::{ExecuteDsl, LimitDsl, LoadQuery, SelectDsl},
         RunQueryDsl, SaveChangesDsl, UpdateAndFetchResults,
     },
-    r2d2::{ConnectionManager, Pool},
-    result::QueryResult,
+    r2d2::{ConnectionManager, Pool, PooledConnection},
+    result::{DatabaseErrorInformation, DatabaseErrorKind, QueryResult},
+    sql_types::Foldable,
     Connection,
 };
-use std::{error::Error as StdError, fmt};
+use futures::{
+    future::{self, Either, Future, FutureExt},
+    sink::{Sink, SinkExt},
+    stream::StreamExt,
+};
+use std::{
+    cell::Cell,
+    collections::{HashMap, HashSet},
+    convert::TryFrom,
+    error::Error as StdError,
+    fmt,
+    hash::Hash,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+#[cfg(feature = "tokio-rt-threaded")]
 use tokio::task;
 
+#[cfg(all(
+    feature = "async-std",
+    any(feature = "tokio-rt-threaded", feature = "bb8", feature = "mobc")
+))]
+compile_error!(
+    "the `async-std` feature cannot be combined with `tokio-rt-threaded`, `bb8` or `mobc`"
+);
+
+mod aggregate;
+#[cfg(feature = "bb8")]
+mod bb8;
+#[cfg(feature = "deadpool")]
+mod deadpool;
+mod deferred;
+mod delegate;
+mod executor;
+mod guard;
+mod handle;
+#[cfg(feature = "http")]
+mod http;
+mod insert;
+mod many;
+#[cfg(feature = "mobc")]
+mod mobc;
+#[cfg(feature = "mysql")]
+mod mysql;
+mod paginate;
+mod pool;
+#[cfg(feature = "postgres")]
+mod postgres;
+mod raw;
+mod rt;
+mod scope;
+mod sharded;
+mod split;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod stream;
+#[cfg(feature = "test-util")]
+mod test_util;
+mod trace;
+mod wrapper;
+
+pub use aggregate::{Avg, Sum};
+pub use deferred::{defer, Deferred};
+pub use executor::{BlockingExecutor, DefaultExecutor};
+pub use guard::AsyncConnGuard;
+pub use handle::JoinHandle;
+pub use insert::{first_or_insert_async, insert_and_get_id_async, insert_chunked_async};
+pub use many::{boxed_execute, execute_many_async, BoxedExecute};
+#[cfg(feature = "mobc")]
+pub use mobc::MobcManager;
+#[cfg(feature = "mysql")]
+pub use mysql::upsert_async;
+pub use paginate::{Paginate, Paginated};
+pub use pool::{
+    AsyncHandle, AsyncPool, AsyncPoolBuilder, BuildError, CheckoutInfo, DeadlinePool,
+    InstrumentationEvent,
+};
+#[cfg(feature = "postgres")]
+pub use postgres::{
+    count_estimate_async, insert_ignore_async, query_dynamic_async, run_cancellable,
+    run_with_statement_timeout, transaction_deferred, upsert_on_conflict_async,
+    upsert_on_conflict_returning_async, AsyncCursorDsl, AsyncExplainDsl, DynamicRow, DynamicValue,
+};
+pub use raw::{sql_execute_async, sql_query_async};
+pub use sharded::ShardedPool;
+pub use split::SplitPool;
+#[cfg(feature = "sqlite")]
+pub use sqlite::{insert_and_get_rowid_async, transaction_retry_busy};
+pub use stream::{Decoded, LoadStream};
+#[cfg(feature = "test-util")]
+pub use test_util::TestTransaction;
+use trace::OpSpan;
+pub use wrapper::AsyncConnectionWrapper;
+
 pub type AsyncResult<R> = Result<R, AsyncError>;
 
 #[derive(Debug)]
 pub enum AsyncError {
     // Failed to checkout a connection
-    Checkout(r2d2::Error),
+    Checkout(Box<dyn StdError + Send + Sync>),
 
     // The query failed in some way
     Error(diesel::result::Error),
+
+    // `first_async` or `get_result_async` found no row
+    NotFound,
+
+    // The operation did not complete within the given duration
+    Timeout(Duration),
+
+    // The pool is shutting down and no longer accepts new operations
+    ShuttingDown,
+
+    // `AsyncTryRun::try_run` found no idle connection
+    NoConnectionAvailable,
+
+    // The operation was cancelled through `run_until` before it completed
+    Cancelled,
+
+    // `ShardedPool` has no pool for the shard a key is routed to
+    NoShard,
+
+    // A row of `load_into_async` could not be converted into the requested type
+    Conversion(Box<dyn StdError + Send + Sync>),
+
+    // The sink of `load_to_sink_async` failed to accept a row
+    Sink(Box<dyn StdError + Send + Sync>),
+
+    // The error of an operation labeled through `run_labeled` or one of the `*_labeled_async`
+    // methods
+    Labeled(&'static str, Box<AsyncError>),
+
+    // A statement of `batch_execute_labeled_async` failed, with its index and text
+    Statement(usize, String, Box<AsyncError>),
 }
 
 pub trait OptionalExtension<T> {
@@ -42,17 +224,208 @@ impl<T> OptionalExtension<T> for AsyncResult<T> {
     fn optional(self) -> Result<Option<T>, AsyncError> {
         match self {
             Ok(value) => Ok(Some(value)),
-            Err(AsyncError::Error(diesel::result::Error::NotFound)) => Ok(None),
+            Err(ref err) if err.is_not_found() => Ok(None),
             Err(e) => Err(e),
         }
     }
 }
 
+/// `OptionalExtension` for futures, e.g. `query.get_result_async(&pool).optional().await`.
+pub trait FutureOptionalExtension<T>: Future<Output = AsyncResult<T>> + Sized {
+    fn optional(self) -> OptionalFuture<Self, T>;
+}
+
+/// The future returned by `FutureOptionalExtension::optional`.
+pub type OptionalFuture<F, T> = future::Map<F, fn(AsyncResult<T>) -> AsyncResult<Option<T>>>;
+
+impl<T, F> FutureOptionalExtension<T> for F
+where
+    F: Future<Output = AsyncResult<T>>,
+{
+    fn optional(self) -> OptionalFuture<Self, T> {
+        self.map(OptionalExtension::optional)
+    }
+}
+
+/// A snapshot of the connections held by a pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolState {
+    /// The number of connections currently being managed by the pool.
+    pub connections: u32,
+
+    /// The number of idle connections.
+    pub idle_connections: u32,
+}
+
+/// How long the phases of an operation run with `AsyncConnection::run_timed` took.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timing {
+    /// From starting the operation until its work began on a connection: waiting for a
+    /// blocking thread and checking out the connection.
+    pub checkout: Duration,
+
+    /// The work on the connection itself.
+    pub query: Duration,
+}
+
+/// The isolation level of a transaction started by `transaction_with_isolation`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IsolationLevel {
+    ReadCommitted,
+    RepeatableRead,
+    Serializable,
+}
+
+impl IsolationLevel {
+    fn set_transaction_sql(self) -> &'static str {
+        match self {
+            IsolationLevel::ReadCommitted => "SET TRANSACTION ISOLATION LEVEL READ COMMITTED",
+            IsolationLevel::RepeatableRead => "SET TRANSACTION ISOLATION LEVEL REPEATABLE READ",
+            IsolationLevel::Serializable => "SET TRANSACTION ISOLATION LEVEL SERIALIZABLE",
+        }
+    }
+}
+
+impl AsyncError {
+    /// Whether the query found no rows where at least one was expected.
+    pub fn is_not_found(&self) -> bool {
+        matches!(
+            *self.innermost(),
+            AsyncError::NotFound | AsyncError::Error(diesel::result::Error::NotFound)
+        )
+    }
+
+    /// Whether the query violated a unique constraint.
+    pub fn is_unique_violation(&self) -> bool {
+        matches!(
+            *self.innermost(),
+            AsyncError::Error(diesel::result::Error::DatabaseError(
+                DatabaseErrorKind::UniqueViolation,
+                _,
+            ))
+        )
+    }
+
+    /// The kind and details of the error reported by the database, if the query was rejected
+    /// by it.
+    pub fn as_database_error(&self) -> Option<(&DatabaseErrorKind, &dyn DatabaseErrorInformation)> {
+        match *self.innermost() {
+            AsyncError::Error(diesel::result::Error::DatabaseError(ref kind, ref info)) => {
+                Some((kind, &**info))
+            }
+            _ => None,
+        }
+    }
+
+    /// The name of the constraint the query violated, if the database reports one, e.g. to
+    /// tell which unique key a duplicate row collided with.
+    pub fn constraint_name(&self) -> Option<&str> {
+        self.as_database_error()
+            .and_then(|(_, info)| info.constraint_name())
+    }
+
+    /// The name of the table involved in the database error, if the database reports one.
+    pub fn table_name(&self) -> Option<&str> {
+        self.as_database_error()
+            .and_then(|(_, info)| info.table_name())
+    }
+
+    /// The name of the column involved in the database error, if the database reports one.
+    pub fn column_name(&self) -> Option<&str> {
+        self.as_database_error()
+            .and_then(|(_, info)| info.column_name())
+    }
+
+    /// Attach `label` to this error, naming the operation that produced it. The label is
+    /// printed in front of the message.
+    pub fn labeled(self, label: &'static str) -> Self {
+        AsyncError::Labeled(label, Box::new(self))
+    }
+
+    /// The label of the operation that produced this error, if any.
+    pub fn label(&self) -> Option<&'static str> {
+        match *self {
+            AsyncError::Labeled(label, _) => Some(label),
+            _ => None,
+        }
+    }
+
+    // The error without any labels or statement context attached
+    fn innermost(&self) -> &AsyncError {
+        match *self {
+            AsyncError::Labeled(_, ref err) | AsyncError::Statement(_, _, ref err) => {
+                err.innermost()
+            }
+            ref err => err,
+        }
+    }
+
+    /// Copy this error into a representation that can be cloned, e.g. to hand one error to
+    /// several waiting consumers.
+    pub fn to_cloneable(&self) -> CloneableError {
+        match *self {
+            AsyncError::Checkout(ref err) => CloneableError::Checkout(err.to_string()),
+            AsyncError::NotFound | AsyncError::Error(diesel::result::Error::NotFound) => {
+                CloneableError::NotFound
+            }
+            AsyncError::Error(diesel::result::Error::DatabaseError(kind, ref info)) => {
+                CloneableError::Database(kind, info.message().to_string())
+            }
+            AsyncError::Error(ref err) => CloneableError::Error(err.to_string()),
+            AsyncError::Timeout(duration) => CloneableError::Timeout(duration),
+            AsyncError::ShuttingDown => CloneableError::ShuttingDown,
+            AsyncError::NoConnectionAvailable => CloneableError::NoConnectionAvailable,
+            AsyncError::Cancelled => CloneableError::Cancelled,
+            AsyncError::NoShard => CloneableError::NoShard,
+            AsyncError::Conversion(_) => CloneableError::Conversion(self.to_string()),
+            AsyncError::Sink(_) => CloneableError::Sink(self.to_string()),
+            AsyncError::Labeled(label, ref err) => {
+                CloneableError::Labeled(label, Box::new(err.to_cloneable()))
+            }
+            AsyncError::Statement(index, ref statement, ref err) => {
+                CloneableError::Statement(index, statement.clone(), Box::new(err.to_cloneable()))
+            }
+        }
+    }
+}
+
+// Report a missing row as `AsyncError::NotFound`, for the methods loading exactly one row
+fn not_found(err: AsyncError) -> AsyncError {
+    match err {
+        AsyncError::Error(diesel::result::Error::NotFound) => AsyncError::NotFound,
+        err => err,
+    }
+}
+
+impl From<r2d2::Error> for AsyncError {
+    fn from(err: r2d2::Error) -> Self {
+        AsyncError::Checkout(Box::new(err))
+    }
+}
+
+impl From<diesel::result::Error> for AsyncError {
+    fn from(err: diesel::result::Error) -> Self {
+        AsyncError::Error(err)
+    }
+}
+
 impl fmt::Display for AsyncError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             AsyncError::Checkout(ref err) => err.fmt(f),
             AsyncError::Error(ref err) => err.fmt(f),
+            AsyncError::NotFound => diesel::result::Error::NotFound.fmt(f),
+            AsyncError::Timeout(duration) => write!(f, "operation timed out after {:?}", duration),
+            AsyncError::ShuttingDown => f.write_str("the pool is shutting down"),
+            AsyncError::NoConnectionAvailable => f.write_str("no connection is available"),
+            AsyncError::Cancelled => f.write_str("the operation was cancelled"),
+            AsyncError::NoShard => f.write_str("no shard for the key"),
+            AsyncError::Conversion(ref err) => write!(f, "failed to convert a row: {}", err),
+            AsyncError::Sink(ref err) => write!(f, "failed to forward a row: {}", err),
+            AsyncError::Labeled(label, ref err) => write!(f, "{}: {}", label, err),
+            AsyncError::Statement(index, ref statement, ref err) => {
+                write!(f, "statement {} (`{}`): {}", index, statement, err)
+            }
         }
     }
 }
@@ -60,8 +433,155 @@ impl fmt::Display for AsyncError {
 impl StdError for AsyncError {
     fn source(&self) -> Option<&(dyn StdError + 'static)> {
         match *self {
-            AsyncError::Checkout(ref err) => Some(err),
+            AsyncError::Checkout(ref err)
+            | AsyncError::Conversion(ref err)
+            | AsyncError::Sink(ref err) => Some(&**err),
             AsyncError::Error(ref err) => Some(err),
+            AsyncError::Labeled(_, ref err) | AsyncError::Statement(_, _, ref err) => Some(&**err),
+            AsyncError::NotFound
+            | AsyncError::Timeout(_)
+            | AsyncError::ShuttingDown
+            | AsyncError::NoConnectionAvailable
+            | AsyncError::Cancelled
+            | AsyncError::NoShard => None,
+        }
+    }
+}
+
+/// A `Clone`-able copy of an `AsyncError`, created by `AsyncError::to_cloneable`.
+///
+/// The underlying errors are reduced to their messages, which are displayed exactly like the
+/// original error.
+#[derive(Debug, Clone)]
+pub enum CloneableError {
+    // Failed to checkout a connection
+    Checkout(String),
+
+    // The query found no rows where at least one was expected
+    NotFound,
+
+    // The database rejected the query
+    Database(DatabaseErrorKind, String),
+
+    // The query failed in some other way
+    Error(String),
+
+    // The operation did not complete within the given duration
+    Timeout(Duration),
+
+    // The pool is shutting down and no longer accepts new operations
+    ShuttingDown,
+
+    // No idle connection was available
+    NoConnectionAvailable,
+
+    // The operation was cancelled
+    Cancelled,
+
+    // No shard for a key
+    NoShard,
+
+    // A row could not be converted
+    Conversion(String),
+
+    // A row could not be forwarded to a sink
+    Sink(String),
+
+    // The error of a labeled operation
+    Labeled(&'static str, Box<CloneableError>),
+
+    // A statement of a batch failed, with its index and text
+    Statement(usize, String, Box<CloneableError>),
+}
+
+impl From<&AsyncError> for CloneableError {
+    fn from(err: &AsyncError) -> Self {
+        err.to_cloneable()
+    }
+}
+
+impl fmt::Display for CloneableError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CloneableError::Checkout(ref message)
+            | CloneableError::Database(_, ref message)
+            | CloneableError::Error(ref message)
+            | CloneableError::Conversion(ref message)
+            | CloneableError::Sink(ref message) => f.write_str(message),
+            CloneableError::NotFound => diesel::result::Error::NotFound.fmt(f),
+            CloneableError::Timeout(duration) => AsyncError::Timeout(duration).fmt(f),
+            CloneableError::ShuttingDown => AsyncError::ShuttingDown.fmt(f),
+            CloneableError::NoConnectionAvailable => AsyncError::NoConnectionAvailable.fmt(f),
+            CloneableError::Cancelled => AsyncError::Cancelled.fmt(f),
+            CloneableError::NoShard => AsyncError::NoShard.fmt(f),
+            CloneableError::Labeled(label, ref err) => write!(f, "{}: {}", label, err),
+            CloneableError::Statement(index, ref statement, ref err) => {
+                write!(f, "statement {} (`{}`): {}", index, statement, err)
+            }
+        }
+    }
+}
+
+impl StdError for CloneableError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match *self {
+            CloneableError::Labeled(_, ref err) | CloneableError::Statement(_, _, ref err) => {
+                Some(&**err)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Handed to the closure of `AsyncConnection::transaction_scoped` to decide how the
+/// transaction ends.
+#[derive(Debug, Default)]
+pub struct TxScope {
+    rollback: Cell<bool>,
+}
+
+impl TxScope {
+    /// Roll the transaction back once the closure returns, even if it succeeds.
+    pub fn rollback(&self) {
+        self.rollback.set(true);
+    }
+
+    /// Whether the transaction will be rolled back.
+    pub fn is_rolled_back(&self) -> bool {
+        self.rollback.get()
+    }
+}
+
+/// The error returned by `AsyncConnection::transaction_err`.
+#[derive(Debug)]
+pub enum TransactionError<E> {
+    // The transaction could not be run, e.g. because no connection could be checked out
+    Async(AsyncError),
+
+    // The transaction was rolled back with this error
+    Error(E),
+}
+
+impl<E> fmt::Display for TransactionError<E>
+where
+    E: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TransactionError::Async(ref err) => err.fmt(f),
+            TransactionError::Error(ref err) => err.fmt(f),
+        }
+    }
+}
+
+impl<E> StdError for TransactionError<E>
+where
+    E: StdError + 'static,
+{
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match *self {
+            TransactionError::Async(ref err) => Some(err),
+            TransactionError::Error(ref err) => Some(err),
         }
     }
 }
@@ -83,12 +603,10 @@ where
     async fn batch_execute_async(&self, query: &str) -> AsyncResult<()> {
         let self_ = self.clone();
         let query = query.to_string();
-        task::spawn_blocking(move || {
-            let conn = self_.get().map_err(AsyncError::Checkout)?;
-            conn.batch_execute(&query).map_err(AsyncError::Error)
+        rt::spawn_blocking(move || {
+            with_conn(&self_, "batch_execute", |conn| conn.batch_execute(&query))
         })
         .await
-        .expect("task has panicked")
     }
 }
 
@@ -108,205 +626,1983 @@ where
         R: 'static + Send,
         Func: FnOnce(&Conn) -> QueryResult<R> + Send;
 
+    /// Run `f` within a transaction on a checked out connection, committing if it returns
+    /// `Ok` and rolling back otherwise.
+    ///
+    /// Calling `Connection::transaction` on the connection from within `f` nests a transaction
+    /// through a `SAVEPOINT`, which is released or rolled back without affecting the outer
+    /// transaction. Each call to `transaction` checks out its own connection though, so
+    /// transactions only nest within a single closure.
     #[cfg(not(feature = "tokio-rt-threaded"))]
     async fn transaction<R, Func>(&self, f: Func) -> AsyncResult<R>
     where
         R: 'static + Send,
         Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send;
+    /// Run `f` within a transaction on a checked out connection, committing if it returns
+    /// `Ok` and rolling back otherwise.
+    ///
+    /// Calling `Connection::transaction` on the connection from within `f` nests a transaction
+    /// through a `SAVEPOINT`, which is released or rolled back without affecting the outer
+    /// transaction. Each call to `transaction` checks out its own connection though, so
+    /// transactions only nest within a single closure.
     #[cfg(feature = "tokio-rt-threaded")]
     async fn transaction<R, Func>(&self, f: Func) -> AsyncResult<R>
     where
         R: 'static + Send,
         Func: FnOnce(&Conn) -> QueryResult<R> + Send;
-}
 
-#[async_trait]
-impl<Conn> AsyncConnection<Conn> for Pool<ConnectionManager<Conn>>
-where
-    Conn: 'static + Connection,
-{
+    /// Like `run`, but hands `&mut Conn` to the closure, as the query methods of diesel 2.x
+    /// expect. Both forms are always available: the whole `AsyncRunQueryDsl` runs on `run`, as
+    /// diesel 1.x takes `&Conn`.
     #[cfg(not(feature = "tokio-rt-threaded"))]
-    #[inline]
-    async fn run<R, Func>(&self, f: Func) -> AsyncResult<R>
+    async fn run_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
     where
         R: 'static + Send,
-        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
-    {
-        let self_ = self.clone();
-        task::spawn_blocking(move || {
-            let conn = self_.get().map_err(AsyncError::Checkout)?;
-            f(&*conn).map_err(AsyncError::Error)
-        })
-        .await
-        .expect("task has panicked")
-    }
+        Func: 'static + FnOnce(&mut Conn) -> QueryResult<R> + Send;
+    /// Like `run`, but hands `&mut Conn` to the closure, as the query methods of diesel 2.x
+    /// expect. Both forms are always available: the whole `AsyncRunQueryDsl` runs on `run`, as
+    /// diesel 1.x takes `&Conn`.
     #[cfg(feature = "tokio-rt-threaded")]
-    #[inline]
-    async fn run<R, Func>(&self, f: Func) -> AsyncResult<R>
+    async fn run_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
     where
         R: 'static + Send,
-        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
-    {
-        let self_ = self.clone();
-        task::block_in_place(move || {
-            let conn = self_.get().map_err(AsyncError::Checkout)?;
-            f(&*conn).map_err(AsyncError::Error)
-        })
-    }
+        Func: FnOnce(&mut Conn) -> QueryResult<R> + Send;
 
+    /// Like `transaction`, but hands `&mut Conn` to the closure.
     #[cfg(not(feature = "tokio-rt-threaded"))]
-    #[inline]
-    async fn transaction<R, Func>(&self, f: Func) -> AsyncResult<R>
+    async fn transaction_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
     where
         R: 'static + Send,
-        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
-    {
-        let self_ = self.clone();
-        task::spawn_blocking(move || {
-            let conn = self_.get().map_err(AsyncError::Checkout)?;
-            conn.transaction(|| f(&*conn)).map_err(AsyncError::Error)
-        })
-        .await
-        .expect("task has panicked")
-    }
+        Func: 'static + FnOnce(&mut Conn) -> QueryResult<R> + Send;
+    /// Like `transaction`, but hands `&mut Conn` to the closure.
     #[cfg(feature = "tokio-rt-threaded")]
-    #[inline]
-    async fn transaction<R, Func>(&self, f: Func) -> AsyncResult<R>
+    async fn transaction_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
     where
         R: 'static + Send,
-        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
-    {
-        let self_ = self.clone();
-        task::block_in_place(move || {
-            let conn = self_.get().map_err(AsyncError::Checkout)?;
-            conn.transaction(|| f(&*conn)).map_err(AsyncError::Error)
-        })
-    }
-}
-
-#[async_trait]
-pub trait AsyncRunQueryDsl<Conn, AsyncConn>
-where
-    Conn: 'static + Connection,
-{
-    async fn execute_async(self, asc: &AsyncConn) -> AsyncResult<usize>
-    where
-        Self: ExecuteDsl<Conn>;
+        Func: FnOnce(&mut Conn) -> QueryResult<R> + Send;
 
-    async fn load_async<U>(self, asc: &AsyncConn) -> AsyncResult<Vec<U>>
+    /// Like `run`, but resolves with `AsyncError::Timeout` if `timeout` elapses first.
+    ///
+    /// The blocking work cannot be cancelled and is left to finish on the thread pool.
+    /// The closure is `'static` even with the `tokio-rt-threaded` feature: it always runs on
+    /// another thread, so that the timeout can be awaited meanwhile.
+    async fn run_timeout<R, Func>(&self, timeout: Duration, f: Func) -> AsyncResult<R>
     where
-        U: 'static + Send,
-        Self: LoadQuery<Conn, U>;
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send;
 
-    async fn get_result_async<U>(self, asc: &AsyncConn) -> AsyncResult<U>
+    /// Like `transaction`, but resolves with `AsyncError::Timeout` if `timeout` elapses first.
+    ///
+    /// The blocking work cannot be cancelled and is left to finish on the thread pool.
+    /// The closure is `'static` even with the `tokio-rt-threaded` feature: it always runs on
+    /// another thread, so that the timeout can be awaited meanwhile.
+    async fn transaction_timeout<R, Func>(&self, timeout: Duration, f: Func) -> AsyncResult<R>
     where
-        U: 'static + Send,
-        Self: LoadQuery<Conn, U>;
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send;
 
-    async fn get_results_async<U>(self, asc: &AsyncConn) -> AsyncResult<Vec<U>>
-    where
-        U: 'static + Send,
-        Self: LoadQuery<Conn, U>;
+    /// Report how many connections the pool holds and how many of them are idle.
+    async fn pool_state_async(&self) -> AsyncResult<PoolState>;
 
-    async fn first_async<U>(self, asc: &AsyncConn) -> AsyncResult<U>
-    where
-        U: 'static + Send,
-        Self: LimitDsl,
-        Limit<Self>: LoadQuery<Conn, U>;
-}
+    /// Check out a connection and run `SELECT 1` on it, e.g. for a readiness probe.
+    async fn ping_async(&self) -> AsyncResult<()> {
+        self.run(|conn| conn.batch_execute("SELECT 1")).await
+    }
 
-#[cfg(not(feature = "tokio-rt-threaded"))]
-#[async_trait]
-impl<T, Conn> AsyncRunQueryDsl<Conn, Pool<ConnectionManager<Conn>>> for T
-where
-    T: 'static + Send + RunQueryDsl<Conn>,
-    Conn: 'static + Connection,
-{
-    async fn execute_async(self, asc: &Pool<ConnectionManager<Conn>>) -> AsyncResult<usize>
-    where
-        Self: ExecuteDsl<Conn>,
-    {
-        asc.run(|conn| self.execute(&*conn)).await
+    /// Like `ping_async`, but resolves with `AsyncError::Timeout` if `timeout` elapses first.
+    async fn ping_timeout_async(&self, timeout: Duration) -> AsyncResult<()> {
+        self.run_timeout(timeout, |conn| conn.batch_execute("SELECT 1"))
+            .await
     }
 
-    async fn load_async<U>(self, asc: &Pool<ConnectionManager<Conn>>) -> AsyncResult<Vec<U>>
+    /// Check out a connection to run several operations on through the returned guard, e.g. to
+    /// create a temporary table and query it. Unless the `tokio-rt-threaded` feature is enabled,
+    /// the `AsyncRunQueryDsl` methods accept the guard like any other `AsyncConnection`.
+    ///
+    /// The connection stays checked out, and a blocking thread busy, until the guard is
+    /// dropped.
+    async fn acquire_async(&self) -> AsyncResult<AsyncConnGuard<Conn>>
     where
-        U: 'static + Send,
-        Self: LoadQuery<Conn, U>,
+        Self: 'static + Clone + Send + Sync,
     {
-        asc.run(|conn| self.load(&*conn)).await
+        guard::acquire(self.clone()).await
     }
 
-    async fn get_result_async<U>(self, asc: &Pool<ConnectionManager<Conn>>) -> AsyncResult<U>
+    /// Spawn `f` within a transaction onto a task of its own and return its handle right away,
+    /// e.g. for writes nobody needs to wait for. Dropping the handle loses the result.
+    fn spawn_transaction<R, Func>(&self, f: Func) -> JoinHandle<AsyncResult<R>>
     where
-        U: 'static + Send,
-        Self: LoadQuery<Conn, U>,
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+        Self: 'static + Clone + Send + Sync,
     {
-        asc.run(|conn| self.get_result(&*conn)).await
+        let asc = self.clone();
+        JoinHandle::spawn(async move { asc.transaction(f).await })
     }
 
-    async fn get_results_async<U>(self, asc: &Pool<ConnectionManager<Conn>>) -> AsyncResult<Vec<U>>
-    where
-        U: 'static + Send,
-        Self: LoadQuery<Conn, U>,
-    {
-        asc.run(|conn| self.get_results(&*conn)).await
+    /// Execute `statements` one after the other on a single connection, optionally within a
+    /// transaction, e.g. for migration tooling.
+    ///
+    /// Execution stops at the first statement that fails, which is reported as
+    /// `AsyncError::Statement` along with its index, counting from 0, and its text. Within a
+    /// transaction, the statements executed before it are rolled back.
+    async fn batch_execute_labeled_async(
+        &self,
+        statements: &[&str],
+        in_transaction: bool,
+    ) -> AsyncResult<()> {
+        let statements: Vec<String> = statements.iter().map(|s| s.to_string()).collect();
+        let failed = Arc::new(AtomicUsize::new(usize::MAX));
+        let batch = {
+            let statements = statements.clone();
+            let failed = failed.clone();
+            move |conn: &Conn| {
+                for (index, statement) in statements.iter().enumerate() {
+                    if let Err(err) = conn.batch_execute(statement) {
+                        failed.store(index, Ordering::SeqCst);
+                        return Err(err);
+                    }
+                }
+                Ok(())
+            }
+        };
+
+        let result = if in_transaction {
+            self.transaction(batch).await
+        } else {
+            self.run(batch).await
+        };
+        result.map_err(|err| match failed.load(Ordering::SeqCst) {
+            usize::MAX => err,
+            index => AsyncError::Statement(index, statements[index].clone(), Box::new(err)),
+        })
     }
 
-    async fn first_async<U>(self, asc: &Pool<ConnectionManager<Conn>>) -> AsyncResult<U>
+    /// Like `run`, for an operation that only reads. The read-only methods of
+    /// `AsyncRunQueryDsl`, like `load_async` and `first_async`, go through here so that pools
+    /// with read replicas, such as `SplitPool`, can run them on a replica. Any other
+    /// connection runs it like `run`.
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    async fn run_read<R, Func>(&self, f: Func) -> AsyncResult<R>
     where
-        U: 'static + Send,
-        Self: LimitDsl,
-        Limit<Self>: LoadQuery<Conn, U>,
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
     {
-        asc.run(|conn| self.first(&*conn)).await
+        self.run(f).await
     }
-}
-#[cfg(feature = "tokio-rt-threaded")]
-#[async_trait]
-impl<T, Conn> AsyncRunQueryDsl<Conn, Pool<ConnectionManager<Conn>>> for T
-where
-    T: Send + RunQueryDsl<Conn>,
-    Conn: 'static + Connection,
-{
-    async fn execute_async(self, asc: &Pool<ConnectionManager<Conn>>) -> AsyncResult<usize>
+    /// Like `run`, for an operation that only reads. The read-only methods of
+    /// `AsyncRunQueryDsl`, like `load_async` and `first_async`, go through here so that pools
+    /// with read replicas, such as `SplitPool`, can run them on a replica. Any other
+    /// connection runs it like `run`.
+    #[cfg(feature = "tokio-rt-threaded")]
+    async fn run_read<R, Func>(&self, f: Func) -> AsyncResult<R>
     where
-        Self: ExecuteDsl<Conn>,
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
     {
-        asc.run(|conn| self.execute(&*conn)).await
+        self.run(f).await
     }
 
-    async fn load_async<U>(self, asc: &Pool<ConnectionManager<Conn>>) -> AsyncResult<Vec<U>>
+    /// Like `run`, but attaches `label` to any error through `AsyncError::labeled`, so the
+    /// failing operation can be told apart in logs.
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    async fn run_labeled<R, Func>(&self, label: &'static str, f: Func) -> AsyncResult<R>
     where
-        U: 'static + Send,
-        Self: LoadQuery<Conn, U>,
-    {
-        asc.run(|conn| self.load(&*conn)).await
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        self.run(f).await.map_err(|err| err.labeled(label))
+    }
+    /// Like `run`, but attaches `label` to any error through `AsyncError::labeled`, so the
+    /// failing operation can be told apart in logs.
+    #[cfg(feature = "tokio-rt-threaded")]
+    async fn run_labeled<R, Func>(&self, label: &'static str, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        self.run(f).await.map_err(|err| err.labeled(label))
+    }
+
+    /// Like `run`, but fails with `AsyncError::Cancelled` as soon as `cancelled` completes,
+    /// e.g. `token.cancelled()` of a `tokio_util::sync::CancellationToken` to stop waiting on
+    /// shutdown. If `cancelled` has completed already, `f` is not run at all.
+    ///
+    /// Cancelling only stops waiting for the result: work that has started still runs to
+    /// completion on its thread, and its connection is returned to the pool afterwards. With
+    /// the `tokio-rt-threaded` feature the work runs on the awaiting task, so cancellation can
+    /// only be observed before it starts.
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    async fn run_until<R, Func, C>(&self, cancelled: C, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+        C: Future<Output = ()> + Send,
+    {
+        // `cancelled` is polled first, so that the work is not started once it has completed
+        match future::select(Box::pin(cancelled), self.run(f)).await {
+            Either::Left(((), _)) => Err(AsyncError::Cancelled),
+            Either::Right((result, _)) => result,
+        }
+    }
+    /// Like `run`, but fails with `AsyncError::Cancelled` as soon as `cancelled` completes,
+    /// e.g. `token.cancelled()` of a `tokio_util::sync::CancellationToken` to stop waiting on
+    /// shutdown. If `cancelled` has completed already, `f` is not run at all.
+    ///
+    /// Cancelling only stops waiting for the result: work that has started still runs to
+    /// completion on its thread, and its connection is returned to the pool afterwards. With
+    /// the `tokio-rt-threaded` feature the work runs on the awaiting task, so cancellation can
+    /// only be observed before it starts.
+    #[cfg(feature = "tokio-rt-threaded")]
+    async fn run_until<R, Func, C>(&self, cancelled: C, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+        C: Future<Output = ()> + Send,
+    {
+        // `cancelled` is polled first, so that the work is not started once it has completed
+        match future::select(Box::pin(cancelled), self.run(f)).await {
+            Either::Left(((), _)) => Err(AsyncError::Cancelled),
+            Either::Right((result, _)) => result,
+        }
+    }
+
+    /// Like `run`, but also returns how long the checkout and the work on the connection took,
+    /// e.g. to record metrics without the `tracing` feature. Nothing is returned for an
+    /// operation that fails.
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    async fn run_timed<R, Func>(&self, f: Func) -> AsyncResult<(R, Timing)>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        let start = Instant::now();
+        self.run(move |conn| {
+            let started = Instant::now();
+            let value = f(conn)?;
+            let timing = Timing {
+                checkout: started.duration_since(start),
+                query: started.elapsed(),
+            };
+            Ok((value, timing))
+        })
+        .await
+    }
+    /// Like `run`, but also returns how long the checkout and the work on the connection took,
+    /// e.g. to record metrics without the `tracing` feature. Nothing is returned for an
+    /// operation that fails.
+    #[cfg(feature = "tokio-rt-threaded")]
+    async fn run_timed<R, Func>(&self, f: Func) -> AsyncResult<(R, Timing)>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        let start = Instant::now();
+        self.run(move |conn| {
+            let started = Instant::now();
+            let value = f(conn)?;
+            let timing = Timing {
+                checkout: started.duration_since(start),
+                query: started.elapsed(),
+            };
+            Ok((value, timing))
+        })
+        .await
+    }
+
+    /// Like `transaction`, but issues `SET TRANSACTION READ ONLY` first so the database rejects
+    /// any write made by the closure. Backends that do not support this inside a transaction
+    /// (e.g. SQLite or MySQL) fail with `AsyncError::Error`.
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    async fn read_only_transaction<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        self.transaction(move |conn| {
+            conn.batch_execute("SET TRANSACTION READ ONLY")?;
+            f(conn)
+        })
+        .await
+    }
+
+    /// Like `transaction`, but issues `SET TRANSACTION READ ONLY` first so the database rejects
+    /// any write made by the closure. Backends that do not support this inside a transaction
+    /// (e.g. SQLite or MySQL) fail with `AsyncError::Error`.
+    #[cfg(feature = "tokio-rt-threaded")]
+    async fn read_only_transaction<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        self.transaction(move |conn| {
+            conn.batch_execute("SET TRANSACTION READ ONLY")?;
+            f(conn)
+        })
+        .await
+    }
+
+    /// Like `transaction`, but runs the transaction at the given isolation level. As with
+    /// `read_only_transaction`, the level is set from within the transaction, which is
+    /// supported by PostgreSQL.
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    async fn transaction_with_isolation<R, Func>(
+        &self,
+        level: IsolationLevel,
+        f: Func,
+    ) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        self.transaction(move |conn| {
+            conn.batch_execute(level.set_transaction_sql())?;
+            f(conn)
+        })
+        .await
+    }
+
+    /// Like `transaction`, but runs the transaction at the given isolation level. As with
+    /// `read_only_transaction`, the level is set from within the transaction, which is
+    /// supported by PostgreSQL.
+    #[cfg(feature = "tokio-rt-threaded")]
+    async fn transaction_with_isolation<R, Func>(
+        &self,
+        level: IsolationLevel,
+        f: Func,
+    ) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        self.transaction(move |conn| {
+            conn.batch_execute(level.set_transaction_sql())?;
+            f(conn)
+        })
+        .await
+    }
+
+    /// Like `transaction`, but the closure may fail with its own error type. Returning an error
+    /// rolls the transaction back and hands the error to the caller as `TransactionError::Error`.
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    async fn transaction_err<R, E, Func>(&self, f: Func) -> Result<R, TransactionError<E>>
+    where
+        R: 'static + Send,
+        E: 'static + From<diesel::result::Error> + Send,
+        Func: 'static + FnOnce(&Conn) -> Result<R, E> + Send,
+    {
+        self.run(move |conn| Ok(conn.transaction(|| f(conn))))
+            .await
+            .map_err(TransactionError::Async)?
+            .map_err(TransactionError::Error)
+    }
+
+    /// Like `transaction`, but the closure may fail with its own error type. Returning an error
+    /// rolls the transaction back and hands the error to the caller as `TransactionError::Error`.
+    #[cfg(feature = "tokio-rt-threaded")]
+    async fn transaction_err<R, E, Func>(&self, f: Func) -> Result<R, TransactionError<E>>
+    where
+        R: 'static + Send,
+        E: 'static + From<diesel::result::Error> + Send,
+        Func: FnOnce(&Conn) -> Result<R, E> + Send,
+    {
+        self.run(move |conn| Ok(conn.transaction(|| f(conn))))
+            .await
+            .map_err(TransactionError::Async)?
+            .map_err(TransactionError::Error)
+    }
+
+    /// Like `transaction`, with hooks around the commit, e.g. to publish an outbox message only
+    /// once the transaction is known to have committed.
+    ///
+    /// `before_commit` runs on the blocking thread right after `f`, inside the transaction, and
+    /// vetoes the commit by returning an error, which rolls the transaction back. `after_commit`
+    /// runs on the awaiting task with the result of `f` once the commit has succeeded, and never
+    /// if the transaction was rolled back or the commit failed. Inside an outer transaction,
+    /// e.g. on a `TestTransaction`, the commit only releases a savepoint.
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    async fn transaction_with_hooks<R, Func, Before, After>(
+        &self,
+        f: Func,
+        before_commit: Before,
+        after_commit: After,
+    ) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+        Before: 'static + FnOnce(&Conn) -> QueryResult<()> + Send,
+        After: FnOnce(&R) + Send,
+    {
+        let value = self
+            .transaction(move |conn| {
+                let value = f(conn)?;
+                before_commit(conn)?;
+                Ok(value)
+            })
+            .await?;
+        after_commit(&value);
+        Ok(value)
+    }
+    /// Like `transaction`, with hooks around the commit, e.g. to publish an outbox message only
+    /// once the transaction is known to have committed.
+    ///
+    /// `before_commit` runs on the blocking thread right after `f`, inside the transaction, and
+    /// vetoes the commit by returning an error, which rolls the transaction back. `after_commit`
+    /// runs on the awaiting task with the result of `f` once the commit has succeeded, and never
+    /// if the transaction was rolled back or the commit failed. Inside an outer transaction,
+    /// e.g. on a `TestTransaction`, the commit only releases a savepoint.
+    #[cfg(feature = "tokio-rt-threaded")]
+    async fn transaction_with_hooks<R, Func, Before, After>(
+        &self,
+        f: Func,
+        before_commit: Before,
+        after_commit: After,
+    ) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+        Before: FnOnce(&Conn) -> QueryResult<()> + Send,
+        After: FnOnce(&R) + Send,
+    {
+        let value = self
+            .transaction(move |conn| {
+                let value = f(conn)?;
+                before_commit(conn)?;
+                Ok(value)
+            })
+            .await?;
+        after_commit(&value);
+        Ok(value)
+    }
+
+    /// Like `transaction`, but hands a `TxScope` to the closure, through which it can roll the
+    /// transaction back while still returning its value, e.g. for a dry run. The transaction
+    /// is committed if the closure succeeds without calling `TxScope::rollback`.
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    async fn transaction_scoped<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn, &TxScope) -> QueryResult<R> + Send,
+    {
+        self.run(move |conn| {
+            let scope = TxScope::default();
+            let mut rolled_back = None;
+            let result = conn.transaction(|| {
+                let value = f(conn, &scope)?;
+                if scope.is_rolled_back() {
+                    rolled_back = Some(value);
+                    return Err(diesel::result::Error::RollbackTransaction);
+                }
+                Ok(value)
+            });
+            match (result, rolled_back) {
+                (Err(diesel::result::Error::RollbackTransaction), Some(value)) => Ok(value),
+                (result, _) => result,
+            }
+        })
+        .await
+    }
+    /// Like `transaction`, but hands a `TxScope` to the closure, through which it can roll the
+    /// transaction back while still returning its value, e.g. for a dry run. The transaction
+    /// is committed if the closure succeeds without calling `TxScope::rollback`.
+    #[cfg(feature = "tokio-rt-threaded")]
+    async fn transaction_scoped<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn, &TxScope) -> QueryResult<R> + Send,
+    {
+        self.run(move |conn| {
+            let scope = TxScope::default();
+            let mut rolled_back = None;
+            let result = conn.transaction(|| {
+                let value = f(conn, &scope)?;
+                if scope.is_rolled_back() {
+                    rolled_back = Some(value);
+                    return Err(diesel::result::Error::RollbackTransaction);
+                }
+                Ok(value)
+            });
+            match (result, rolled_back) {
+                (Err(diesel::result::Error::RollbackTransaction), Some(value)) => Ok(value),
+                (result, _) => result,
+            }
+        })
+        .await
+    }
+
+    /// Like `transaction`, but retries up to `retries` times with exponential backoff when the
+    /// transaction fails with a serialization failure (SQLSTATE 40001). If every attempt fails,
+    /// the last error is returned.
+    ///
+    /// The closure may be invoked several times and must therefore be idempotent.
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    async fn transaction_retry<R, Func>(&self, retries: usize, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + Fn(&Conn) -> QueryResult<R> + Send + Sync,
+    {
+        let f = Arc::new(f);
+        let mut backoff = Duration::from_millis(10);
+        let mut attempt = 0;
+        loop {
+            let f = f.clone();
+            match self.transaction(move |conn| f(conn)).await {
+                Err(ref err)
+                    if attempt < retries
+                        && matches!(
+                            err.as_database_error(),
+                            Some((DatabaseErrorKind::SerializationFailure, _))
+                        ) =>
+                {
+                    attempt += 1;
+                    rt::sleep(backoff).await;
+                    backoff *= 2;
+                }
+                result => return result,
+            }
+        }
+    }
+    /// Like `transaction`, but retries up to `retries` times with exponential backoff when the
+    /// transaction fails with a serialization failure (SQLSTATE 40001). If every attempt fails,
+    /// the last error is returned.
+    ///
+    /// The closure may be invoked several times and must therefore be idempotent.
+    #[cfg(feature = "tokio-rt-threaded")]
+    async fn transaction_retry<R, Func>(&self, retries: usize, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: Fn(&Conn) -> QueryResult<R> + Send + Sync,
+    {
+        let f = Arc::new(f);
+        let mut backoff = Duration::from_millis(10);
+        let mut attempt = 0;
+        loop {
+            let f = f.clone();
+            match self.transaction(move |conn| f(conn)).await {
+                Err(ref err)
+                    if attempt < retries
+                        && matches!(
+                            err.as_database_error(),
+                            Some((DatabaseErrorKind::SerializationFailure, _))
+                        ) =>
+                {
+                    attempt += 1;
+                    rt::sleep(backoff).await;
+                    backoff *= 2;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<Conn> AsyncConnection<Conn> for Pool<ConnectionManager<Conn>>
+where
+    Conn: 'static + Connection,
+{
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn run<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        let self_ = self.clone();
+        rt::spawn_blocking(move || with_conn(&self_, "run", |conn| f(conn))).await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn run<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        let self_ = self.clone();
+        task::block_in_place(move || with_conn(&self_, "run", |conn| f(conn)))
+    }
+
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn transaction<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        let self_ = self.clone();
+        rt::spawn_blocking(move || {
+            with_conn(&self_, "transaction", |conn| conn.transaction(|| f(conn)))
+        })
+        .await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn transaction<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        let self_ = self.clone();
+        task::block_in_place(move || {
+            with_conn(&self_, "transaction", |conn| conn.transaction(|| f(conn)))
+        })
+    }
+
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn run_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        let self_ = self.clone();
+        rt::spawn_blocking(move || with_conn(&self_, "run_mut", f)).await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn run_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        let self_ = self.clone();
+        task::block_in_place(move || with_conn(&self_, "run_mut", f))
+    }
+
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn transaction_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        let self_ = self.clone();
+        rt::spawn_blocking(move || {
+            with_conn(&self_, "transaction_mut", |conn| transaction_mut(conn, f))
+        })
+        .await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn transaction_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        let self_ = self.clone();
+        task::block_in_place(move || {
+            with_conn(&self_, "transaction_mut", |conn| transaction_mut(conn, f))
+        })
+    }
+
+    #[inline]
+    async fn run_timeout<R, Func>(&self, timeout: Duration, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        let self_ = self.clone();
+        let handle = rt::spawn_blocking(move || with_conn(&self_, "run_timeout", |conn| f(conn)));
+        rt::timeout(timeout, handle).await?
+    }
+
+    #[inline]
+    async fn transaction_timeout<R, Func>(&self, timeout: Duration, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        let self_ = self.clone();
+        let handle = rt::spawn_blocking(move || {
+            with_conn(&self_, "transaction_timeout", |conn| {
+                conn.transaction(|| f(conn))
+            })
+        });
+        rt::timeout(timeout, handle).await?
+    }
+
+    #[inline]
+    async fn pool_state_async(&self) -> AsyncResult<PoolState> {
+        let self_ = self.clone();
+        rt::spawn_blocking(move || {
+            let state = self_.state();
+            Ok(PoolState {
+                connections: state.connections,
+                idle_connections: state.idle_connections,
+            })
+        })
+        .await
+    }
+}
+
+/// Running operations only if a connection is available right away, e.g. to shed load
+/// instead of queueing up behind a saturated pool.
+#[async_trait]
+pub trait AsyncTryRun<Conn>: AsyncConnection<Conn>
+where
+    Conn: 'static + Connection,
+{
+    /// Like `AsyncConnection::run`, but fails with `AsyncError::NoConnectionAvailable` instead
+    /// of waiting for a connection if none is idle; no new connection is established either.
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    async fn try_run<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send;
+    /// Like `AsyncConnection::run`, but fails with `AsyncError::NoConnectionAvailable` instead
+    /// of waiting for a connection if none is idle; no new connection is established either.
+    #[cfg(feature = "tokio-rt-threaded")]
+    async fn try_run<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send;
+}
+
+#[async_trait]
+impl<Conn> AsyncTryRun<Conn> for Pool<ConnectionManager<Conn>>
+where
+    Conn: 'static + Connection,
+{
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn try_run<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        let self_ = self.clone();
+        rt::spawn_blocking(move || try_with_conn(&self_, "try_run", |conn| f(conn))).await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn try_run<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        let self_ = self.clone();
+        task::block_in_place(move || try_with_conn(&self_, "try_run", |conn| f(conn)))
+    }
+}
+
+/// Running operations with a checkout timeout of their own, e.g. a short one for a
+/// latency-sensitive endpoint and a long one for a background job sharing the pool.
+#[async_trait]
+pub trait AsyncRunWithCheckoutTimeout<Conn>: AsyncConnection<Conn>
+where
+    Conn: 'static + Connection,
+{
+    /// Like `AsyncConnection::run`, but waits at most `timeout` for a connection instead of the
+    /// connection timeout of the pool, failing with `AsyncError::Checkout` once it elapses.
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    async fn run_with_checkout_timeout<R, Func>(
+        &self,
+        timeout: Duration,
+        f: Func,
+    ) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send;
+    /// Like `AsyncConnection::run`, but waits at most `timeout` for a connection instead of the
+    /// connection timeout of the pool, failing with `AsyncError::Checkout` once it elapses.
+    #[cfg(feature = "tokio-rt-threaded")]
+    async fn run_with_checkout_timeout<R, Func>(
+        &self,
+        timeout: Duration,
+        f: Func,
+    ) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send;
+}
+
+#[async_trait]
+impl<Conn> AsyncRunWithCheckoutTimeout<Conn> for Pool<ConnectionManager<Conn>>
+where
+    Conn: 'static + Connection,
+{
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn run_with_checkout_timeout<R, Func>(&self, timeout: Duration, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        let self_ = self.clone();
+        rt::spawn_blocking(move || {
+            with_conn_checkout_timeout(&self_, "run_with_checkout_timeout", timeout, |conn| f(conn))
+        })
+        .await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn run_with_checkout_timeout<R, Func>(&self, timeout: Duration, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        let self_ = self.clone();
+        task::block_in_place(move || {
+            with_conn_checkout_timeout(&self_, "run_with_checkout_timeout", timeout, |conn| f(conn))
+        })
+    }
+}
+
+/// Running a transaction on the thread of the awaiting task, so that its closure may borrow
+/// from the caller, e.g. `&mut` state updated between the queries of the transaction.
+///
+/// Only available with the `tokio-rt-threaded` feature: without `block_in_place`, the
+/// transaction would stall every other task on the thread of the basic scheduler.
+#[cfg(feature = "tokio-rt-threaded")]
+#[async_trait]
+pub trait AsyncTransactionBlocking<Conn>: AsyncConnection<Conn>
+where
+    Conn: 'static + Connection,
+{
+    /// Like `AsyncConnection::transaction`, but checks out the connection and runs `f` on the
+    /// current thread instead of the blocking thread pool, so `f` need not be `'static`.
+    ///
+    /// The thread is blocked until the transaction has finished, through `block_in_place`,
+    /// which hands the other tasks of the worker to another one meanwhile.
+    async fn transaction_blocking<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send;
+}
+
+#[cfg(feature = "tokio-rt-threaded")]
+#[async_trait]
+impl<Conn> AsyncTransactionBlocking<Conn> for Pool<ConnectionManager<Conn>>
+where
+    Conn: 'static + Connection,
+{
+    #[inline]
+    async fn transaction_blocking<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        rt::block_in_place(|| {
+            with_conn(self, "transaction_blocking", |conn| {
+                conn.transaction(|| f(conn))
+            })
+        })
+    }
+}
+
+// Check out a connection from `pool` and run `f` with it on the current thread
+fn with_conn<Conn, R, Func>(
+    pool: &Pool<ConnectionManager<Conn>>,
+    op: &'static str,
+    f: Func,
+) -> AsyncResult<R>
+where
+    Conn: 'static + Connection,
+    Func: FnOnce(&mut Conn) -> QueryResult<R>,
+{
+    with_checkout(op, || pool.get().map_err(AsyncError::from), f)
+}
+
+// Like `with_conn`, but fails right away if `pool` has no idle connection
+fn try_with_conn<Conn, R, Func>(
+    pool: &Pool<ConnectionManager<Conn>>,
+    op: &'static str,
+    f: Func,
+) -> AsyncResult<R>
+where
+    Conn: 'static + Connection,
+    Func: FnOnce(&mut Conn) -> QueryResult<R>,
+{
+    with_checkout(
+        op,
+        || pool.try_get().ok_or(AsyncError::NoConnectionAvailable),
+        f,
+    )
+}
+
+// Like `with_conn`, but waits at most `timeout` for a connection of `pool`
+fn with_conn_checkout_timeout<Conn, R, Func>(
+    pool: &Pool<ConnectionManager<Conn>>,
+    op: &'static str,
+    timeout: Duration,
+    f: Func,
+) -> AsyncResult<R>
+where
+    Conn: 'static + Connection,
+    Func: FnOnce(&mut Conn) -> QueryResult<R>,
+{
+    with_checkout(
+        op,
+        || pool.get_timeout(timeout).map_err(AsyncError::from),
+        f,
+    )
+}
+
+// Check out a connection through `checkout` and run `f` with it on the current thread
+fn with_checkout<Conn, R, Checkout, Func>(
+    op: &'static str,
+    checkout: Checkout,
+    f: Func,
+) -> AsyncResult<R>
+where
+    Conn: 'static + Connection,
+    Checkout: FnOnce() -> AsyncResult<PooledConnection<ConnectionManager<Conn>>>,
+    Func: FnOnce(&mut Conn) -> QueryResult<R>,
+{
+    let span = OpSpan::new(op);
+    span.in_scope(|| {
+        let start = Instant::now();
+        let mut conn = checkout().map_err(|err| span.checkout_failed(start, err))?;
+        span.checked_out(start);
+
+        let start = Instant::now();
+        span.finished(start, f(&mut *conn).map_err(AsyncError::Error))
+    })
+}
+
+// `Connection::transaction` only hands out `&Conn`, so drive the transaction manager
+// directly to be able to pass `&mut Conn` to the closure
+fn transaction_mut<Conn, R, Func>(conn: &mut Conn, f: Func) -> QueryResult<R>
+where
+    Conn: Connection,
+    Func: FnOnce(&mut Conn) -> QueryResult<R>,
+{
+    conn.transaction_manager().begin_transaction(conn)?;
+    match f(conn) {
+        Ok(value) => {
+            conn.transaction_manager().commit_transaction(conn)?;
+            Ok(value)
+        }
+        Err(e) => {
+            conn.transaction_manager().rollback_transaction(conn)?;
+            Err(e)
+        }
+    }
+}
+
+// Roll back the transactions open on `conn` down to `depth`, e.g. those a panic left open
+fn rollback_to<Conn>(conn: &Conn, depth: u32) -> QueryResult<()>
+where
+    Conn: Connection,
+{
+    let transaction_manager = conn.transaction_manager();
+    while transaction_manager.get_transaction_depth() > depth {
+        transaction_manager.rollback_transaction(conn)?;
+    }
+    Ok(())
+}
+
+/// Run diesel queries on any `AsyncConnection`, handed to every method by reference.
+///
+/// Pools can be passed however they are stored: `load_async(&pool)` for a pool held by value
+/// and `load_async(&arc_pool)` for an `Arc<Pool<_>>` both work, as do references to either,
+/// since the async traits are forwarded through `Arc` and `&`.
+#[async_trait]
+pub trait AsyncRunQueryDsl<Conn, AsyncConn>
+where
+    Conn: 'static + Connection,
+    AsyncConn: Send + Sync + AsyncConnection<Conn>,
+{
+    async fn execute_async(self, asc: &AsyncConn) -> AsyncResult<usize>
+    where
+        Self: ExecuteDsl<Conn>;
+
+    /// Like `execute_async`, but discards the number of affected rows, e.g. for setup code
+    /// that only cares whether the statement succeeded.
+    async fn run_async(self, asc: &AsyncConn) -> AsyncResult<()>
+    where
+        Self: ExecuteDsl<Conn>;
+
+    /// Load the rows of this query. `U` is the type of a row, e.g. `(i32, i64)` for
+    /// `group_by(user_id).select((user_id, sql::<BigInt>("COUNT(*)")))`, and is moved back from
+    /// the blocking thread, hence `'static + Send`: rows borrowing from the query, like `&str`,
+    /// cannot be loaded. When the row type cannot be inferred from the use of the result, name
+    /// it with `load_async::<(i32, i64)>`.
+    ///
+    /// This is a read, run by `AsyncConnection::run_read`, so a `SplitPool` sends it to a
+    /// replica, as it does with every other method loading rows. A statement with a
+    /// `RETURNING` clause must be run with `execute_returning_async`, `update_returning_async`
+    /// or `delete_returning_async` instead, which run on the primary.
+    async fn load_async<U>(self, asc: &AsyncConn) -> AsyncResult<Vec<U>>
+    where
+        U: 'static + Send,
+        Self: LoadQuery<Conn, U>;
+
+    /// Like `load_async`, but loads into a `Vec` created with room for `capacity` rows, e.g.
+    /// for a page of a known size. The rows are pushed onto it one at a time as they are
+    /// deserialized, so it is only reallocated if there are more rows than `capacity`.
+    async fn load_with_capacity_async<U>(
+        self,
+        capacity: usize,
+        asc: &AsyncConn,
+    ) -> AsyncResult<Vec<U>>
+    where
+        U: 'static + Send,
+        Self: LoadQuery<Conn, Decoded<U>>;
+
+    /// Load the single row returned by this query, failing with `AsyncError::NotFound` if
+    /// there is none.
+    async fn get_result_async<U>(self, asc: &AsyncConn) -> AsyncResult<U>
+    where
+        U: 'static + Send,
+        Self: LoadQuery<Conn, U>;
+
+    async fn get_results_async<U>(self, asc: &AsyncConn) -> AsyncResult<Vec<U>>
+    where
+        U: 'static + Send,
+        Self: LoadQuery<Conn, U>;
+
+    /// Run an insert, update or delete statement with a `RETURNING` clause and load the
+    /// returned rows. This is `get_results_async` run as a write, so a `SplitPool` sends it to
+    /// the primary rather than to a replica.
+    async fn execute_returning_async<U>(self, asc: &AsyncConn) -> AsyncResult<Vec<U>>
+    where
+        U: 'static + Send,
+        Self: LoadQuery<Conn, U>;
+
+    /// Run an `UPDATE ... RETURNING` statement and load the updated rows, e.g. for an audit
+    /// log. The number of updated rows is the length of the result.
+    ///
+    /// Without an explicit `returning` clause every column of the table is returned. Backends
+    /// without `RETURNING` support, such as MySQL, reject this at compile time.
+    async fn update_returning_async<U>(self, asc: &AsyncConn) -> AsyncResult<Vec<U>>
+    where
+        U: 'static + Send,
+        Self: LoadQuery<Conn, U>;
+
+    /// Run a `DELETE ... RETURNING` statement and load the deleted rows, e.g. to archive them
+    /// in the same round trip. The number of deleted rows is the length of the result.
+    ///
+    /// Without an explicit `returning` clause every column of the table is returned. Diesel 1.x
+    /// supports `RETURNING` on PostgreSQL only, other backends reject this at compile time.
+    async fn delete_returning_async<U>(self, asc: &AsyncConn) -> AsyncResult<Vec<U>>
+    where
+        U: 'static + Send,
+        Self: LoadQuery<Conn, U>;
+
+    /// Like `execute_async`, but attaches `label` to any error.
+    async fn execute_labeled_async(
+        self,
+        label: &'static str,
+        asc: &AsyncConn,
+    ) -> AsyncResult<usize>
+    where
+        Self: ExecuteDsl<Conn>;
+
+    /// Like `load_async`, but attaches `label` to any error.
+    async fn load_labeled_async<U>(
+        self,
+        label: &'static str,
+        asc: &AsyncConn,
+    ) -> AsyncResult<Vec<U>>
+    where
+        U: 'static + Send,
+        Self: LoadQuery<Conn, U>;
+
+    /// Like `get_result_async`, but attaches `label` to any error, including the
+    /// `AsyncError::NotFound` of a missing row.
+    async fn get_result_labeled_async<U>(
+        self,
+        label: &'static str,
+        asc: &AsyncConn,
+    ) -> AsyncResult<U>
+    where
+        U: 'static + Send,
+        Self: LoadQuery<Conn, U>;
+
+    /// Load the first row of this query, failing with `AsyncError::NotFound` if there is none.
+    async fn first_async<U>(self, asc: &AsyncConn) -> AsyncResult<U>
+    where
+        U: 'static + Send,
+        Self: LimitDsl,
+        Limit<Self>: LoadQuery<Conn, U>;
+
+    /// Like `get_result_async`, but returns `Ok(None)` when no row was found.
+    async fn get_result_opt_async<U>(self, asc: &AsyncConn) -> AsyncResult<Option<U>>
+    where
+        U: 'static + Send,
+        Self: LoadQuery<Conn, U>;
+
+    /// Like `first_async`, but returns `Ok(None)` when no row was found.
+    async fn first_opt_async<U>(self, asc: &AsyncConn) -> AsyncResult<Option<U>>
+    where
+        U: 'static + Send,
+        Self: LimitDsl,
+        Limit<Self>: LoadQuery<Conn, U>;
+
+    /// Like `first_async`, but returns `default` when no row was found.
+    async fn first_or_async<U>(self, default: U, asc: &AsyncConn) -> AsyncResult<U>
+    where
+        U: 'static + Send,
+        Self: LimitDsl,
+        Limit<Self>: LoadQuery<Conn, U>;
+
+    /// Like `first_async`, but returns the value computed by `default` when no row was found.
+    async fn first_or_else_async<U, F>(self, default: F, asc: &AsyncConn) -> AsyncResult<U>
+    where
+        U: 'static + Send,
+        F: FnOnce() -> U + Send,
+        Self: LimitDsl,
+        Limit<Self>: LoadQuery<Conn, U>;
+
+    async fn count_async(self, asc: &AsyncConn) -> AsyncResult<i64>
+    where
+        Self: SelectDsl<CountStar>,
+        Select<Self, CountStar>: LoadQuery<Conn, i64>;
+
+    /// Select the `SUM` of `expr` over the rows of this query, e.g. `sum_async(orders::total)`.
+    /// The sum of no rows is `None`.
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    async fn sum_async<E, U>(self, expr: E, asc: &AsyncConn) -> AsyncResult<Option<U>>
+    where
+        E: 'static + Expression + Send,
+        E::SqlType: Foldable,
+        U: 'static + Send,
+        Self: SelectDsl<Sum<E>>,
+        Select<Self, Sum<E>>: LoadQuery<Conn, Option<U>>;
+    /// Select the `SUM` of `expr` over the rows of this query, e.g. `sum_async(orders::total)`.
+    /// The sum of no rows is `None`.
+    #[cfg(feature = "tokio-rt-threaded")]
+    async fn sum_async<E, U>(self, expr: E, asc: &AsyncConn) -> AsyncResult<Option<U>>
+    where
+        E: Expression + Send,
+        E::SqlType: Foldable,
+        U: 'static + Send,
+        Self: SelectDsl<Sum<E>>,
+        Select<Self, Sum<E>>: LoadQuery<Conn, Option<U>>;
+
+    /// Select the `AVG` of `expr` over the rows of this query, e.g. `avg_async(orders::total)`.
+    /// The average of no rows is `None`.
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    async fn avg_async<E, U>(self, expr: E, asc: &AsyncConn) -> AsyncResult<Option<U>>
+    where
+        E: 'static + Expression + Send,
+        E::SqlType: Foldable,
+        U: 'static + Send,
+        Self: SelectDsl<Avg<E>>,
+        Select<Self, Avg<E>>: LoadQuery<Conn, Option<U>>;
+    /// Select the `AVG` of `expr` over the rows of this query, e.g. `avg_async(orders::total)`.
+    /// The average of no rows is `None`.
+    #[cfg(feature = "tokio-rt-threaded")]
+    async fn avg_async<E, U>(self, expr: E, asc: &AsyncConn) -> AsyncResult<Option<U>>
+    where
+        E: Expression + Send,
+        E::SqlType: Foldable,
+        U: 'static + Send,
+        Self: SelectDsl<Avg<E>>,
+        Select<Self, Avg<E>>: LoadQuery<Conn, Option<U>>;
+
+    async fn exists_async(self, asc: &AsyncConn) -> AsyncResult<bool>
+    where
+        Self: Sized,
+        Exists<Self>: Expression,
+        SelectStatement<()>: SelectDsl<Exists<Self>>,
+        Select<SelectStatement<()>, Exists<Self>>: LoadQuery<Conn, bool>;
+
+    /// Load the rows of this query into a map keyed by `key_fn`. The map is built on the
+    /// blocking thread along with the load. When several rows have the same key, the last one
+    /// wins.
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    async fn load_indexed_async<K, U, F>(
+        self,
+        key_fn: F,
+        asc: &AsyncConn,
+    ) -> AsyncResult<HashMap<K, U>>
+    where
+        K: 'static + Eq + Hash + Send,
+        U: 'static + Send,
+        F: 'static + Fn(&U) -> K + Send,
+        Self: LoadQuery<Conn, U>;
+    /// Load the rows of this query into a map keyed by `key_fn`. The map is built on the
+    /// blocking thread along with the load. When several rows have the same key, the last one
+    /// wins.
+    #[cfg(feature = "tokio-rt-threaded")]
+    async fn load_indexed_async<K, U, F>(
+        self,
+        key_fn: F,
+        asc: &AsyncConn,
+    ) -> AsyncResult<HashMap<K, U>>
+    where
+        K: 'static + Eq + Hash + Send,
+        U: 'static + Send,
+        F: Fn(&U) -> K + Send,
+        Self: LoadQuery<Conn, U>;
+
+    /// Load the rows of this query, keeping only the first row of every key returned by
+    /// `key_fn` and dropping later rows with the same key, e.g. to deduplicate the rows of a
+    /// join. The rows are deduplicated on the blocking thread along with the load, in the order
+    /// of the query, so an `order_by` decides which row of a key is kept.
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    async fn load_unique_by_async<K, U, F>(self, key_fn: F, asc: &AsyncConn) -> AsyncResult<Vec<U>>
+    where
+        K: Eq + Hash + Send,
+        U: 'static + Send,
+        F: 'static + Fn(&U) -> K + Send,
+        Self: LoadQuery<Conn, U>;
+    /// Load the rows of this query, keeping only the first row of every key returned by
+    /// `key_fn` and dropping later rows with the same key, e.g. to deduplicate the rows of a
+    /// join. The rows are deduplicated on the blocking thread along with the load, in the order
+    /// of the query, so an `order_by` decides which row of a key is kept.
+    #[cfg(feature = "tokio-rt-threaded")]
+    async fn load_unique_by_async<K, U, F>(self, key_fn: F, asc: &AsyncConn) -> AsyncResult<Vec<U>>
+    where
+        K: Eq + Hash + Send,
+        U: 'static + Send,
+        F: Fn(&U) -> K + Send,
+        Self: LoadQuery<Conn, U>;
+
+    /// Load the `(key, value)` rows of this query into a map, e.g. the results of a grouped
+    /// aggregate such as `group_by(user_id).select((user_id, sql::<BigInt>("COUNT(*)")))` into
+    /// a `HashMap<i32, i64>`. Both types can usually be inferred from the map they are assigned
+    /// to. When several rows have the same key, the last one wins.
+    ///
+    /// Diesel 1.x rejects selecting its own aggregates such as `count_star()` next to plain
+    /// columns, which is why the aggregate is written with `dsl::sql` here.
+    async fn load_grouped_async<K, V>(self, asc: &AsyncConn) -> AsyncResult<HashMap<K, V>>
+    where
+        K: 'static + Eq + Hash + Send,
+        V: 'static + Send,
+        Self: LoadQuery<Conn, (K, V)>;
+
+    /// Fold the rows of this query into an accumulator, starting from `init`, on the blocking
+    /// thread along with the load, e.g. to sum a column. Only the accumulator is handed back.
+    /// Diesel still loads the whole result set before folding it.
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    async fn fold_async<U, B, F>(self, init: B, f: F, asc: &AsyncConn) -> AsyncResult<B>
+    where
+        U: 'static,
+        B: 'static + Send,
+        F: 'static + FnMut(B, U) -> B + Send,
+        Self: LoadQuery<Conn, U>;
+    /// Fold the rows of this query into an accumulator, starting from `init`, on the blocking
+    /// thread along with the load, e.g. to sum a column. Only the accumulator is handed back.
+    /// Diesel still loads the whole result set before folding it.
+    #[cfg(feature = "tokio-rt-threaded")]
+    async fn fold_async<U, B, F>(self, init: B, f: F, asc: &AsyncConn) -> AsyncResult<B>
+    where
+        U: 'static,
+        B: 'static + Send,
+        F: FnMut(B, U) -> B + Send,
+        Self: LoadQuery<Conn, U>;
+
+    /// Load the rows of this query as `Db` and convert each into `Domain` through `TryFrom`,
+    /// e.g. to validate database rows into a domain type. The conversion runs on the blocking
+    /// thread along with the load; the first row that fails it fails the whole load with
+    /// `AsyncError::Conversion`.
+    async fn load_into_async<Db, Domain>(self, asc: &AsyncConn) -> AsyncResult<Vec<Domain>>
+    where
+        Db: 'static,
+        Domain: 'static + TryFrom<Db> + Send,
+        <Domain as TryFrom<Db>>::Error: Into<Box<dyn StdError + Send + Sync>>,
+        Self: LoadQuery<Conn, Db>;
+
+    /// Load the rows of this query and forward them to `sink`, e.g. a CSV writer, returning
+    /// the number of rows forwarded. The sink applies backpressure as rows are fed to it, and
+    /// is flushed once every row was forwarded.
+    ///
+    /// Diesel still loads the result set in one go on the blocking thread. If the sink fails
+    /// to accept a row, the remaining ones are dropped and the load fails with
+    /// `AsyncError::Sink`.
+    async fn load_to_sink_async<U, S>(self, sink: S, asc: &AsyncConn) -> AsyncResult<usize>
+    where
+        U: 'static + Send,
+        S: Sink<U> + Send,
+        S::Error: Into<Box<dyn StdError + Send + Sync>>,
+        Self: LoadQuery<Conn, U>;
+
+    /// Load the rows of this query as a `Stream` instead of a `Vec`.
+    ///
+    /// Every row is sent to the stream as soon as it is deserialized on the blocking thread,
+    /// instead of being collected into a `Vec` first. At most `capacity` rows are buffered
+    /// between the two: the load waits while the stream is full, and stops once it is dropped.
+    /// If a row fails to deserialize, the rows before it are still yielded, followed by the
+    /// error, and the stream ends.
+    ///
+    /// The database driver may still hold the whole result set in memory, as libpq does for
+    /// Postgres; `AsyncCursorDsl::cursor_stream_async` fetches it in batches instead.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0.
+    fn load_stream_async<U>(self, capacity: usize, asc: &AsyncConn) -> LoadStream<U>
+    where
+        U: 'static + Send,
+        Self: 'static + LoadQuery<Conn, Decoded<U>>,
+        AsyncConn: 'static + Clone;
+
+    /// Spawn this statement onto a task of its own and return its handle right away, e.g. for
+    /// analytics writes nobody needs to wait for. Awaiting the handle yields the number of
+    /// affected rows; dropping it loses the result, including any error.
+    fn spawn_execute_async(self, asc: &AsyncConn) -> JoinHandle<AsyncResult<usize>>
+    where
+        Self: 'static + ExecuteDsl<Conn>,
+        AsyncConn: 'static + Clone;
+}
+
+#[cfg(not(feature = "tokio-rt-threaded"))]
+#[async_trait]
+impl<T, Conn, AsyncConn> AsyncRunQueryDsl<Conn, AsyncConn> for T
+where
+    T: 'static + Send + RunQueryDsl<Conn>,
+    Conn: 'static + Connection,
+    AsyncConn: Send + Sync + AsyncConnection<Conn>,
+{
+    async fn execute_async(self, asc: &AsyncConn) -> AsyncResult<usize>
+    where
+        Self: ExecuteDsl<Conn>,
+    {
+        asc.run(|conn| self.execute(conn)).await
+    }
+
+    async fn run_async(self, asc: &AsyncConn) -> AsyncResult<()>
+    where
+        Self: ExecuteDsl<Conn>,
+    {
+        asc.run(|conn| self.execute(conn).map(|_| ())).await
+    }
+
+    async fn load_async<U>(self, asc: &AsyncConn) -> AsyncResult<Vec<U>>
+    where
+        U: 'static + Send,
+        Self: LoadQuery<Conn, U>,
+    {
+        asc.run_read(|conn| self.load(conn)).await
+    }
+
+    async fn load_with_capacity_async<U>(
+        self,
+        capacity: usize,
+        asc: &AsyncConn,
+    ) -> AsyncResult<Vec<U>>
+    where
+        U: 'static + Send,
+        Self: LoadQuery<Conn, Decoded<U>>,
+    {
+        asc.run_read(move |conn| Decoded::collect(capacity, || self.load(conn)))
+            .await
+    }
+
+    async fn get_result_async<U>(self, asc: &AsyncConn) -> AsyncResult<U>
+    where
+        U: 'static + Send,
+        Self: LoadQuery<Conn, U>,
+    {
+        asc.run_read(|conn| self.get_result(conn))
+            .await
+            .map_err(not_found)
+    }
+
+    async fn get_results_async<U>(self, asc: &AsyncConn) -> AsyncResult<Vec<U>>
+    where
+        U: 'static + Send,
+        Self: LoadQuery<Conn, U>,
+    {
+        asc.run_read(|conn| self.get_results(conn)).await
+    }
+
+    async fn execute_returning_async<U>(self, asc: &AsyncConn) -> AsyncResult<Vec<U>>
+    where
+        U: 'static + Send,
+        Self: LoadQuery<Conn, U>,
+    {
+        asc.run(|conn| self.get_results(conn)).await
+    }
+
+    async fn update_returning_async<U>(self, asc: &AsyncConn) -> AsyncResult<Vec<U>>
+    where
+        U: 'static + Send,
+        Self: LoadQuery<Conn, U>,
+    {
+        asc.run(|conn| self.get_results(conn)).await
+    }
+
+    async fn delete_returning_async<U>(self, asc: &AsyncConn) -> AsyncResult<Vec<U>>
+    where
+        U: 'static + Send,
+        Self: LoadQuery<Conn, U>,
+    {
+        asc.run(|conn| self.get_results(conn)).await
+    }
+
+    async fn execute_labeled_async(self, label: &'static str, asc: &AsyncConn) -> AsyncResult<usize>
+    where
+        Self: ExecuteDsl<Conn>,
+    {
+        asc.run_labeled(label, |conn| self.execute(conn)).await
+    }
+
+    async fn load_labeled_async<U>(
+        self,
+        label: &'static str,
+        asc: &AsyncConn,
+    ) -> AsyncResult<Vec<U>>
+    where
+        U: 'static + Send,
+        Self: LoadQuery<Conn, U>,
+    {
+        asc.run_read(|conn| self.load(conn))
+            .await
+            .map_err(|err| err.labeled(label))
+    }
+
+    async fn get_result_labeled_async<U>(
+        self,
+        label: &'static str,
+        asc: &AsyncConn,
+    ) -> AsyncResult<U>
+    where
+        U: 'static + Send,
+        Self: LoadQuery<Conn, U>,
+    {
+        asc.run_read(|conn| self.get_result(conn))
+            .await
+            .map_err(|err| not_found(err).labeled(label))
+    }
+
+    async fn first_async<U>(self, asc: &AsyncConn) -> AsyncResult<U>
+    where
+        U: 'static + Send,
+        Self: LimitDsl,
+        Limit<Self>: LoadQuery<Conn, U>,
+    {
+        asc.run_read(|conn| self.first(conn))
+            .await
+            .map_err(not_found)
+    }
+
+    async fn get_result_opt_async<U>(self, asc: &AsyncConn) -> AsyncResult<Option<U>>
+    where
+        U: 'static + Send,
+        Self: LoadQuery<Conn, U>,
+    {
+        asc.run_read(|conn| diesel::OptionalExtension::optional(self.get_result(conn)))
+            .await
+    }
+
+    async fn first_opt_async<U>(self, asc: &AsyncConn) -> AsyncResult<Option<U>>
+    where
+        U: 'static + Send,
+        Self: LimitDsl,
+        Limit<Self>: LoadQuery<Conn, U>,
+    {
+        asc.run_read(|conn| diesel::OptionalExtension::optional(self.first(conn)))
+            .await
+    }
+
+    async fn first_or_async<U>(self, default: U, asc: &AsyncConn) -> AsyncResult<U>
+    where
+        U: 'static + Send,
+        Self: LimitDsl,
+        Limit<Self>: LoadQuery<Conn, U>,
+    {
+        let row = self.first_opt_async(asc).await?;
+        Ok(row.unwrap_or(default))
+    }
+
+    async fn first_or_else_async<U, F>(self, default: F, asc: &AsyncConn) -> AsyncResult<U>
+    where
+        U: 'static + Send,
+        F: FnOnce() -> U + Send,
+        Self: LimitDsl,
+        Limit<Self>: LoadQuery<Conn, U>,
+    {
+        let row = self.first_opt_async(asc).await?;
+        Ok(row.unwrap_or_else(default))
+    }
+
+    async fn count_async(self, asc: &AsyncConn) -> AsyncResult<i64>
+    where
+        Self: SelectDsl<CountStar>,
+        Select<Self, CountStar>: LoadQuery<Conn, i64>,
+    {
+        asc.run_read(|conn| self.select(count_star()).get_result(conn))
+            .await
+    }
+
+    async fn sum_async<E, U>(self, expr: E, asc: &AsyncConn) -> AsyncResult<Option<U>>
+    where
+        E: 'static + Expression + Send,
+        E::SqlType: Foldable,
+        U: 'static + Send,
+        Self: SelectDsl<Sum<E>>,
+        Select<Self, Sum<E>>: LoadQuery<Conn, Option<U>>,
+    {
+        asc.run_read(move |conn| self.select(Sum(expr)).get_result(conn))
+            .await
+    }
+
+    async fn avg_async<E, U>(self, expr: E, asc: &AsyncConn) -> AsyncResult<Option<U>>
+    where
+        E: 'static + Expression + Send,
+        E::SqlType: Foldable,
+        U: 'static + Send,
+        Self: SelectDsl<Avg<E>>,
+        Select<Self, Avg<E>>: LoadQuery<Conn, Option<U>>,
+    {
+        asc.run_read(move |conn| self.select(Avg(expr)).get_result(conn))
+            .await
     }
 
-    async fn get_result_async<U>(self, asc: &Pool<ConnectionManager<Conn>>) -> AsyncResult<U>
+    async fn exists_async(self, asc: &AsyncConn) -> AsyncResult<bool>
+    where
+        Self: Sized,
+        Exists<Self>: Expression,
+        SelectStatement<()>: SelectDsl<Exists<Self>>,
+        Select<SelectStatement<()>, Exists<Self>>: LoadQuery<Conn, bool>,
+    {
+        asc.run_read(|conn| diesel::select(exists(self)).get_result(conn))
+            .await
+    }
+
+    async fn load_indexed_async<K, U, F>(
+        self,
+        key_fn: F,
+        asc: &AsyncConn,
+    ) -> AsyncResult<HashMap<K, U>>
+    where
+        K: 'static + Eq + Hash + Send,
+        U: 'static + Send,
+        F: 'static + Fn(&U) -> K + Send,
+        Self: LoadQuery<Conn, U>,
+    {
+        asc.run_read(move |conn| {
+            let rows = self.load::<U>(conn)?;
+            Ok(rows.into_iter().map(|row| (key_fn(&row), row)).collect())
+        })
+        .await
+    }
+
+    async fn load_unique_by_async<K, U, F>(self, key_fn: F, asc: &AsyncConn) -> AsyncResult<Vec<U>>
+    where
+        K: Eq + Hash + Send,
+        U: 'static + Send,
+        F: 'static + Fn(&U) -> K + Send,
+        Self: LoadQuery<Conn, U>,
+    {
+        asc.run_read(move |conn| {
+            let rows = self.load::<U>(conn)?;
+            let mut seen = HashSet::new();
+            Ok(rows
+                .into_iter()
+                .filter(|row| seen.insert(key_fn(row)))
+                .collect())
+        })
+        .await
+    }
+
+    async fn load_grouped_async<K, V>(self, asc: &AsyncConn) -> AsyncResult<HashMap<K, V>>
+    where
+        K: 'static + Eq + Hash + Send,
+        V: 'static + Send,
+        Self: LoadQuery<Conn, (K, V)>,
+    {
+        asc.run_read(|conn| Ok(self.load::<(K, V)>(conn)?.into_iter().collect()))
+            .await
+    }
+
+    async fn fold_async<U, B, F>(self, init: B, f: F, asc: &AsyncConn) -> AsyncResult<B>
+    where
+        U: 'static,
+        B: 'static + Send,
+        F: 'static + FnMut(B, U) -> B + Send,
+        Self: LoadQuery<Conn, U>,
+    {
+        asc.run_read(move |conn| Ok(self.load::<U>(conn)?.into_iter().fold(init, f)))
+            .await
+    }
+
+    async fn load_into_async<Db, Domain>(self, asc: &AsyncConn) -> AsyncResult<Vec<Domain>>
+    where
+        Db: 'static,
+        Domain: 'static + TryFrom<Db> + Send,
+        <Domain as TryFrom<Db>>::Error: Into<Box<dyn StdError + Send + Sync>>,
+        Self: LoadQuery<Conn, Db>,
+    {
+        let rows: Result<_, Box<dyn StdError + Send + Sync>> = asc
+            .run_read(|conn| {
+                let rows = self.load::<Db>(conn)?.into_iter();
+                Ok(rows
+                    .map(|row| Domain::try_from(row).map_err(Into::into))
+                    .collect())
+            })
+            .await?;
+        rows.map_err(AsyncError::Conversion)
+    }
+
+    async fn load_to_sink_async<U, S>(self, sink: S, asc: &AsyncConn) -> AsyncResult<usize>
+    where
+        U: 'static + Send,
+        S: Sink<U> + Send,
+        S::Error: Into<Box<dyn StdError + Send + Sync>>,
+        Self: LoadQuery<Conn, U>,
+    {
+        let rows = asc.run_read(|conn| self.load::<U>(conn)).await?;
+        let count = rows.len();
+        futures::pin_mut!(sink);
+        for row in rows {
+            sink.feed(row)
+                .await
+                .map_err(|err| AsyncError::Sink(err.into()))?;
+        }
+        sink.flush()
+            .await
+            .map_err(|err| AsyncError::Sink(err.into()))?;
+        Ok(count)
+    }
+
+    fn load_stream_async<U>(self, capacity: usize, asc: &AsyncConn) -> LoadStream<U>
+    where
+        U: 'static + Send,
+        Self: 'static + LoadQuery<Conn, Decoded<U>>,
+        AsyncConn: 'static + Clone,
+    {
+        let asc = asc.clone();
+        LoadStream::spawn(capacity, |tx| async move {
+            asc.run_read(move |conn| Decoded::forward(tx, || self.load(conn)))
+                .await
+        })
+    }
+
+    fn spawn_execute_async(self, asc: &AsyncConn) -> JoinHandle<AsyncResult<usize>>
+    where
+        Self: 'static + ExecuteDsl<Conn>,
+        AsyncConn: 'static + Clone,
+    {
+        let asc = asc.clone();
+        JoinHandle::spawn(async move { asc.run(|conn| self.execute(conn)).await })
+    }
+}
+#[cfg(feature = "tokio-rt-threaded")]
+#[async_trait]
+impl<T, Conn, AsyncConn> AsyncRunQueryDsl<Conn, AsyncConn> for T
+where
+    T: Send + RunQueryDsl<Conn>,
+    Conn: 'static + Connection,
+    AsyncConn: Send + Sync + AsyncConnection<Conn>,
+{
+    async fn execute_async(self, asc: &AsyncConn) -> AsyncResult<usize>
+    where
+        Self: ExecuteDsl<Conn>,
+    {
+        asc.run(|conn| self.execute(conn)).await
+    }
+
+    async fn run_async(self, asc: &AsyncConn) -> AsyncResult<()>
+    where
+        Self: ExecuteDsl<Conn>,
+    {
+        asc.run(|conn| self.execute(conn).map(|_| ())).await
+    }
+
+    async fn load_async<U>(self, asc: &AsyncConn) -> AsyncResult<Vec<U>>
+    where
+        U: 'static + Send,
+        Self: LoadQuery<Conn, U>,
+    {
+        asc.run_read(|conn| self.load(conn)).await
+    }
+
+    async fn load_with_capacity_async<U>(
+        self,
+        capacity: usize,
+        asc: &AsyncConn,
+    ) -> AsyncResult<Vec<U>>
+    where
+        U: 'static + Send,
+        Self: LoadQuery<Conn, Decoded<U>>,
+    {
+        asc.run_read(move |conn| Decoded::collect(capacity, || self.load(conn)))
+            .await
+    }
+
+    async fn get_result_async<U>(self, asc: &AsyncConn) -> AsyncResult<U>
+    where
+        U: 'static + Send,
+        Self: LoadQuery<Conn, U>,
+    {
+        asc.run_read(|conn| self.get_result(conn))
+            .await
+            .map_err(not_found)
+    }
+
+    async fn get_results_async<U>(self, asc: &AsyncConn) -> AsyncResult<Vec<U>>
+    where
+        U: 'static + Send,
+        Self: LoadQuery<Conn, U>,
+    {
+        asc.run_read(|conn| self.get_results(conn)).await
+    }
+
+    async fn execute_returning_async<U>(self, asc: &AsyncConn) -> AsyncResult<Vec<U>>
+    where
+        U: 'static + Send,
+        Self: LoadQuery<Conn, U>,
+    {
+        asc.run(|conn| self.get_results(conn)).await
+    }
+
+    async fn update_returning_async<U>(self, asc: &AsyncConn) -> AsyncResult<Vec<U>>
+    where
+        U: 'static + Send,
+        Self: LoadQuery<Conn, U>,
+    {
+        asc.run(|conn| self.get_results(conn)).await
+    }
+
+    async fn delete_returning_async<U>(self, asc: &AsyncConn) -> AsyncResult<Vec<U>>
+    where
+        U: 'static + Send,
+        Self: LoadQuery<Conn, U>,
+    {
+        asc.run(|conn| self.get_results(conn)).await
+    }
+
+    async fn execute_labeled_async(self, label: &'static str, asc: &AsyncConn) -> AsyncResult<usize>
+    where
+        Self: ExecuteDsl<Conn>,
+    {
+        asc.run_labeled(label, |conn| self.execute(conn)).await
+    }
+
+    async fn load_labeled_async<U>(
+        self,
+        label: &'static str,
+        asc: &AsyncConn,
+    ) -> AsyncResult<Vec<U>>
+    where
+        U: 'static + Send,
+        Self: LoadQuery<Conn, U>,
+    {
+        asc.run_read(|conn| self.load(conn))
+            .await
+            .map_err(|err| err.labeled(label))
+    }
+
+    async fn get_result_labeled_async<U>(
+        self,
+        label: &'static str,
+        asc: &AsyncConn,
+    ) -> AsyncResult<U>
     where
         U: 'static + Send,
         Self: LoadQuery<Conn, U>,
     {
-        asc.run(|conn| self.get_result(&*conn)).await
+        asc.run_read(|conn| self.get_result(conn))
+            .await
+            .map_err(|err| not_found(err).labeled(label))
+    }
+
+    async fn first_async<U>(self, asc: &AsyncConn) -> AsyncResult<U>
+    where
+        U: 'static + Send,
+        Self: LimitDsl,
+        Limit<Self>: LoadQuery<Conn, U>,
+    {
+        asc.run_read(|conn| self.first(conn))
+            .await
+            .map_err(not_found)
     }
 
-    async fn get_results_async<U>(self, asc: &Pool<ConnectionManager<Conn>>) -> AsyncResult<Vec<U>>
+    async fn get_result_opt_async<U>(self, asc: &AsyncConn) -> AsyncResult<Option<U>>
     where
         U: 'static + Send,
         Self: LoadQuery<Conn, U>,
     {
-        asc.run(|conn| self.get_results(&*conn)).await
+        asc.run_read(|conn| diesel::OptionalExtension::optional(self.get_result(conn)))
+            .await
+    }
+
+    async fn first_opt_async<U>(self, asc: &AsyncConn) -> AsyncResult<Option<U>>
+    where
+        U: 'static + Send,
+        Self: LimitDsl,
+        Limit<Self>: LoadQuery<Conn, U>,
+    {
+        asc.run_read(|conn| diesel::OptionalExtension::optional(self.first(conn)))
+            .await
+    }
+
+    async fn first_or_async<U>(self, default: U, asc: &AsyncConn) -> AsyncResult<U>
+    where
+        U: 'static + Send,
+        Self: LimitDsl,
+        Limit<Self>: LoadQuery<Conn, U>,
+    {
+        let row = self.first_opt_async(asc).await?;
+        Ok(row.unwrap_or(default))
     }
 
-    async fn first_async<U>(self, asc: &Pool<ConnectionManager<Conn>>) -> AsyncResult<U>
+    async fn first_or_else_async<U, F>(self, default: F, asc: &AsyncConn) -> AsyncResult<U>
     where
         U: 'static + Send,
+        F: FnOnce() -> U + Send,
         Self: LimitDsl,
         Limit<Self>: LoadQuery<Conn, U>,
     {
-        asc.run(|conn| self.first(&*conn)).await
+        let row = self.first_opt_async(asc).await?;
+        Ok(row.unwrap_or_else(default))
+    }
+
+    async fn count_async(self, asc: &AsyncConn) -> AsyncResult<i64>
+    where
+        Self: SelectDsl<CountStar>,
+        Select<Self, CountStar>: LoadQuery<Conn, i64>,
+    {
+        asc.run_read(|conn| self.select(count_star()).get_result(conn))
+            .await
+    }
+
+    async fn sum_async<E, U>(self, expr: E, asc: &AsyncConn) -> AsyncResult<Option<U>>
+    where
+        E: Expression + Send,
+        E::SqlType: Foldable,
+        U: 'static + Send,
+        Self: SelectDsl<Sum<E>>,
+        Select<Self, Sum<E>>: LoadQuery<Conn, Option<U>>,
+    {
+        asc.run_read(move |conn| self.select(Sum(expr)).get_result(conn))
+            .await
+    }
+
+    async fn avg_async<E, U>(self, expr: E, asc: &AsyncConn) -> AsyncResult<Option<U>>
+    where
+        E: Expression + Send,
+        E::SqlType: Foldable,
+        U: 'static + Send,
+        Self: SelectDsl<Avg<E>>,
+        Select<Self, Avg<E>>: LoadQuery<Conn, Option<U>>,
+    {
+        asc.run_read(move |conn| self.select(Avg(expr)).get_result(conn))
+            .await
+    }
+
+    async fn exists_async(self, asc: &AsyncConn) -> AsyncResult<bool>
+    where
+        Self: Sized,
+        Exists<Self>: Expression,
+        SelectStatement<()>: SelectDsl<Exists<Self>>,
+        Select<SelectStatement<()>, Exists<Self>>: LoadQuery<Conn, bool>,
+    {
+        asc.run_read(|conn| diesel::select(exists(self)).get_result(conn))
+            .await
+    }
+
+    async fn load_indexed_async<K, U, F>(
+        self,
+        key_fn: F,
+        asc: &AsyncConn,
+    ) -> AsyncResult<HashMap<K, U>>
+    where
+        K: 'static + Eq + Hash + Send,
+        U: 'static + Send,
+        F: Fn(&U) -> K + Send,
+        Self: LoadQuery<Conn, U>,
+    {
+        asc.run_read(move |conn| {
+            let rows = self.load::<U>(conn)?;
+            Ok(rows.into_iter().map(|row| (key_fn(&row), row)).collect())
+        })
+        .await
+    }
+
+    async fn load_unique_by_async<K, U, F>(self, key_fn: F, asc: &AsyncConn) -> AsyncResult<Vec<U>>
+    where
+        K: Eq + Hash + Send,
+        U: 'static + Send,
+        F: Fn(&U) -> K + Send,
+        Self: LoadQuery<Conn, U>,
+    {
+        asc.run_read(move |conn| {
+            let rows = self.load::<U>(conn)?;
+            let mut seen = HashSet::new();
+            Ok(rows
+                .into_iter()
+                .filter(|row| seen.insert(key_fn(row)))
+                .collect())
+        })
+        .await
+    }
+
+    async fn load_grouped_async<K, V>(self, asc: &AsyncConn) -> AsyncResult<HashMap<K, V>>
+    where
+        K: 'static + Eq + Hash + Send,
+        V: 'static + Send,
+        Self: LoadQuery<Conn, (K, V)>,
+    {
+        asc.run_read(|conn| Ok(self.load::<(K, V)>(conn)?.into_iter().collect()))
+            .await
+    }
+
+    async fn fold_async<U, B, F>(self, init: B, f: F, asc: &AsyncConn) -> AsyncResult<B>
+    where
+        U: 'static,
+        B: 'static + Send,
+        F: FnMut(B, U) -> B + Send,
+        Self: LoadQuery<Conn, U>,
+    {
+        asc.run_read(move |conn| Ok(self.load::<U>(conn)?.into_iter().fold(init, f)))
+            .await
+    }
+
+    async fn load_into_async<Db, Domain>(self, asc: &AsyncConn) -> AsyncResult<Vec<Domain>>
+    where
+        Db: 'static,
+        Domain: 'static + TryFrom<Db> + Send,
+        <Domain as TryFrom<Db>>::Error: Into<Box<dyn StdError + Send + Sync>>,
+        Self: LoadQuery<Conn, Db>,
+    {
+        let rows: Result<_, Box<dyn StdError + Send + Sync>> = asc
+            .run_read(|conn| {
+                let rows = self.load::<Db>(conn)?.into_iter();
+                Ok(rows
+                    .map(|row| Domain::try_from(row).map_err(Into::into))
+                    .collect())
+            })
+            .await?;
+        rows.map_err(AsyncError::Conversion)
+    }
+
+    async fn load_to_sink_async<U, S>(self, sink: S, asc: &AsyncConn) -> AsyncResult<usize>
+    where
+        U: 'static + Send,
+        S: Sink<U> + Send,
+        S::Error: Into<Box<dyn StdError + Send + Sync>>,
+        Self: LoadQuery<Conn, U>,
+    {
+        let rows = asc.run_read(|conn| self.load::<U>(conn)).await?;
+        let count = rows.len();
+        futures::pin_mut!(sink);
+        for row in rows {
+            sink.feed(row)
+                .await
+                .map_err(|err| AsyncError::Sink(err.into()))?;
+        }
+        sink.flush()
+            .await
+            .map_err(|err| AsyncError::Sink(err.into()))?;
+        Ok(count)
+    }
+
+    fn load_stream_async<U>(self, capacity: usize, asc: &AsyncConn) -> LoadStream<U>
+    where
+        U: 'static + Send,
+        Self: 'static + LoadQuery<Conn, Decoded<U>>,
+        AsyncConn: 'static + Clone,
+    {
+        let asc = asc.clone();
+        LoadStream::spawn(capacity, |tx| async move {
+            asc.run_read(move |conn| Decoded::forward(tx, || self.load(conn)))
+                .await
+        })
+    }
+
+    fn spawn_execute_async(self, asc: &AsyncConn) -> JoinHandle<AsyncResult<usize>>
+    where
+        Self: 'static + ExecuteDsl<Conn>,
+        AsyncConn: 'static + Clone,
+    {
+        let asc = asc.clone();
+        JoinHandle::spawn(async move { asc.run(|conn| self.execute(conn)).await })
     }
 }
 
@@ -321,32 +2617,143 @@ pub trait AsyncSaveChangesDsl<Conn, AsyncConn> {
 
 #[cfg(not(feature = "tokio-rt-threaded"))]
 #[async_trait]
-impl<T, Conn> AsyncSaveChangesDsl<Conn, Pool<ConnectionManager<Conn>>> for T
+impl<T, Conn, AsyncConn> AsyncSaveChangesDsl<Conn, AsyncConn> for T
 where
     T: 'static + Send + SaveChangesDsl<Conn>,
     Conn: 'static + Connection,
+    AsyncConn: Send + Sync + AsyncConnection<Conn>,
 {
-    async fn save_changes_async<U>(self, asc: &Pool<ConnectionManager<Conn>>) -> AsyncResult<U>
+    async fn save_changes_async<U>(self, asc: &AsyncConn) -> AsyncResult<U>
     where
         U: 'static + Send,
         Conn: UpdateAndFetchResults<T, U>,
     {
-        asc.run(|conn| self.save_changes(&*conn)).await
+        asc.run(|conn| self.save_changes(conn)).await
     }
 }
 
 #[cfg(feature = "tokio-rt-threaded")]
 #[async_trait]
-impl<T, Conn> AsyncSaveChangesDsl<Conn, Pool<ConnectionManager<Conn>>> for T
+impl<T, Conn, AsyncConn> AsyncSaveChangesDsl<Conn, AsyncConn> for T
 where
     T: Send + SaveChangesDsl<Conn>,
     Conn: 'static + Connection,
+    AsyncConn: Send + Sync + AsyncConnection<Conn>,
 {
-    async fn save_changes_async<U>(self, asc: &Pool<ConnectionManager<Conn>>) -> AsyncResult<U>
+    async fn save_changes_async<U>(self, asc: &AsyncConn) -> AsyncResult<U>
     where
         U: 'static + Send,
         Conn: UpdateAndFetchResults<T, U>,
     {
-        asc.run(|conn| self.save_changes(&*conn)).await
+        asc.run(|conn| self.save_changes(conn)).await
+    }
+}
+
+#[async_trait]
+pub trait AsyncSaveAllChangesDsl<T, Conn, AsyncConn> {
+    /// Save the changes of every record within a single transaction, returning the updated rows
+    /// in the order of the input. If any record fails to save, no changes are kept.
+    async fn save_all_changes_async<U>(self, asc: &AsyncConn) -> AsyncResult<Vec<U>>
+    where
+        U: 'static + Send,
+        Conn: for<'a> UpdateAndFetchResults<&'a T, U>;
+
+    /// Save the changes of every record concurrently, each on its own connection and task,
+    /// returning the updated rows in the order of the input.
+    ///
+    /// At most as many records are saved at once as the pool has connections.
+    ///
+    /// Unlike `save_all_changes_async` this is not atomic: every record is saved in its own
+    /// statement, and when a record fails to save, the others are still saved. Once all of them
+    /// have finished, the error of the first failing record in the input is returned.
+    async fn save_changes_parallel_async<U>(self, asc: &AsyncConn) -> AsyncResult<Vec<U>>
+    where
+        T: 'static,
+        U: 'static + Send,
+        Conn: for<'a> UpdateAndFetchResults<&'a T, U>,
+        AsyncConn: 'static + Clone;
+}
+
+#[cfg(not(feature = "tokio-rt-threaded"))]
+#[async_trait]
+impl<T, Conn, AsyncConn> AsyncSaveAllChangesDsl<T, Conn, AsyncConn> for Vec<T>
+where
+    T: 'static + Send,
+    for<'a> &'a T: SaveChangesDsl<Conn>,
+    Conn: 'static + Connection,
+    AsyncConn: Send + Sync + AsyncConnection<Conn>,
+{
+    async fn save_all_changes_async<U>(self, asc: &AsyncConn) -> AsyncResult<Vec<U>>
+    where
+        U: 'static + Send,
+        Conn: for<'a> UpdateAndFetchResults<&'a T, U>,
+    {
+        asc.transaction(move |conn| {
+            self.iter()
+                .map(|record| record.save_changes(conn))
+                .collect()
+        })
+        .await
+    }
+
+    async fn save_changes_parallel_async<U>(self, asc: &AsyncConn) -> AsyncResult<Vec<U>>
+    where
+        T: 'static,
+        U: 'static + Send,
+        Conn: for<'a> UpdateAndFetchResults<&'a T, U>,
+        AsyncConn: 'static + Clone,
+    {
+        let concurrency = many::pool_concurrency(asc).await?;
+        let results: Vec<_> = futures::stream::iter(self)
+            .map(|record| {
+                let asc = asc.clone();
+                rt::spawn(async move { asc.run(move |conn| (&record).save_changes(conn)).await })
+            })
+            .buffered(concurrency)
+            .collect()
+            .await;
+        results.into_iter().collect()
+    }
+}
+
+#[cfg(feature = "tokio-rt-threaded")]
+#[async_trait]
+impl<T, Conn, AsyncConn> AsyncSaveAllChangesDsl<T, Conn, AsyncConn> for Vec<T>
+where
+    T: Send,
+    for<'a> &'a T: SaveChangesDsl<Conn>,
+    Conn: 'static + Connection,
+    AsyncConn: Send + Sync + AsyncConnection<Conn>,
+{
+    async fn save_all_changes_async<U>(self, asc: &AsyncConn) -> AsyncResult<Vec<U>>
+    where
+        U: 'static + Send,
+        Conn: for<'a> UpdateAndFetchResults<&'a T, U>,
+    {
+        asc.transaction(move |conn| {
+            self.iter()
+                .map(|record| record.save_changes(conn))
+                .collect()
+        })
+        .await
+    }
+
+    async fn save_changes_parallel_async<U>(self, asc: &AsyncConn) -> AsyncResult<Vec<U>>
+    where
+        T: 'static,
+        U: 'static + Send,
+        Conn: for<'a> UpdateAndFetchResults<&'a T, U>,
+        AsyncConn: 'static + Clone,
+    {
+        let concurrency = many::pool_concurrency(asc).await?;
+        let results: Vec<_> = futures::stream::iter(self)
+            .map(|record| {
+                let asc = asc.clone();
+                rt::spawn(async move { asc.run(move |conn| (&record).save_changes(conn)).await })
+            })
+            .buffered(concurrency)
+            .collect()
+            .await;
+        results.into_iter().collect()
     }
 }
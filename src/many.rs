@@ -0,0 +1,53 @@
+use crate::{rt, AsyncConnection, AsyncResult};
+use diesel::{query_dsl::methods::ExecuteDsl, result::QueryResult, Connection};
+use futures::stream::{self, StreamExt};
+
+/// A statement of any type for `execute_many_async`, created by `boxed_execute`.
+pub type BoxedExecute<Conn> = Box<dyn FnOnce(&Conn) -> QueryResult<usize> + Send>;
+
+/// Box `statement`, e.g. an insert or an update, so that statements of different types can
+/// be handed to `execute_many_async` together.
+pub fn boxed_execute<Q, Conn>(statement: Q) -> BoxedExecute<Conn>
+where
+    Q: 'static + ExecuteDsl<Conn> + Send,
+    Conn: Connection,
+{
+    Box::new(move |conn| ExecuteDsl::execute(statement, conn))
+}
+
+/// Execute independent `statements` concurrently, each on its own connection, returning the
+/// number of affected rows of each in the order of `statements`.
+///
+/// At most as many statements run at once as the pool has connections. There is no
+/// transaction around the statements, a failing one does not affect the others.
+pub async fn execute_many_async<Conn, AsyncConn>(
+    statements: Vec<BoxedExecute<Conn>>,
+    asc: &AsyncConn,
+) -> Vec<AsyncResult<usize>>
+where
+    Conn: 'static + Connection,
+    AsyncConn: 'static + Clone + Send + Sync + AsyncConnection<Conn>,
+{
+    // A pool that cannot report its state fails the statements themselves one at a time
+    let concurrency = pool_concurrency(asc).await.unwrap_or(1);
+
+    stream::iter(statements)
+        .map(|statement| {
+            let asc = asc.clone();
+            rt::spawn(async move { asc.run(move |conn| statement(conn)).await })
+        })
+        .buffered(concurrency)
+        .collect()
+        .await
+}
+
+// How many operations of a batch to run at once: one per connection of the pool, as more
+// would only wait for a connection while holding a blocking thread
+pub(crate) async fn pool_concurrency<Conn, AsyncConn>(asc: &AsyncConn) -> AsyncResult<usize>
+where
+    Conn: 'static + Connection,
+    AsyncConn: AsyncConnection<Conn>,
+{
+    let state = asc.pool_state_async().await?;
+    Ok((state.connections as usize).max(1))
+}
@@ -0,0 +1,313 @@
+use crate::{
+    rt, trace::OpSpan, transaction_mut, AsyncConnection, AsyncError, AsyncResult,
+    AsyncSimpleConnection, PoolState,
+};
+use async_trait::async_trait;
+use diesel::{
+    r2d2::{self, ConnectionManager, ManageConnection},
+    result::QueryResult,
+    Connection,
+};
+use futures::future::Future;
+use mobc::Pool;
+use std::{
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+#[cfg(feature = "tokio-rt-threaded")]
+use tokio::task;
+
+/// A `mobc` manager for diesel connections, to build a `mobc::Pool<MobcManager<Conn>>`.
+///
+/// Connections are established and checked on the blocking thread pool.
+pub struct MobcManager<Conn> {
+    manager: Arc<ConnectionManager<Conn>>,
+}
+
+impl<Conn> MobcManager<Conn> {
+    /// A manager connecting to `database_url`.
+    pub fn new<S: Into<String>>(database_url: S) -> Self {
+        MobcManager {
+            manager: Arc::new(ConnectionManager::new(database_url)),
+        }
+    }
+}
+
+impl<Conn> fmt::Debug for MobcManager<Conn> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MobcManager").finish()
+    }
+}
+
+#[async_trait]
+impl<Conn> mobc::Manager for MobcManager<Conn>
+where
+    Conn: 'static + Connection,
+{
+    type Connection = Conn;
+    type Error = r2d2::Error;
+
+    fn spawn_task<T>(&self, task: T)
+    where
+        T: Future + Send + 'static,
+        T::Output: Send + 'static,
+    {
+        // Connections are recycled by tasks nobody waits for, the task is detached
+        drop(rt::spawn(task));
+    }
+
+    async fn connect(&self) -> Result<Conn, r2d2::Error> {
+        let manager = self.manager.clone();
+        rt::spawn_blocking(move || manager.connect()).await
+    }
+
+    async fn check(&self, mut conn: Conn) -> Result<Conn, r2d2::Error> {
+        let manager = self.manager.clone();
+        rt::spawn_blocking(move || manager.is_valid(&mut conn).map(|()| conn)).await
+    }
+}
+
+// Check out a connection asynchronously, recording the checkout on `span`
+async fn checkout<Conn>(
+    pool: &Pool<MobcManager<Conn>>,
+    span: &OpSpan,
+) -> AsyncResult<mobc::Connection<MobcManager<Conn>>>
+where
+    Conn: 'static + Connection,
+{
+    let start = Instant::now();
+    let conn = pool
+        .get()
+        .await
+        .map_err(|err| span.checkout_failed(start, AsyncError::Checkout(Box::new(err))))?;
+    span.checked_out(start);
+    Ok(conn)
+}
+
+// Check out a connection asynchronously and run `f` with it on the blocking thread pool. The
+// connection is handed back so that it is returned to the pool from the async side.
+async fn spawn_with_conn<Conn, R, Func>(
+    pool: &Pool<MobcManager<Conn>>,
+    op: &'static str,
+    f: Func,
+) -> AsyncResult<R>
+where
+    Conn: 'static + Connection,
+    R: 'static + Send,
+    Func: 'static + FnOnce(&mut Conn) -> AsyncResult<R> + Send,
+{
+    let span = OpSpan::new(op);
+    let mut conn = checkout(pool, &span).await?;
+    let (result, _conn) = rt::spawn_blocking(move || {
+        let result = span.in_scope(|| {
+            let start = Instant::now();
+            span.finished(start, f(&mut *conn))
+        });
+        (result, conn)
+    })
+    .await;
+    result
+}
+
+#[cfg(not(feature = "tokio-rt-threaded"))]
+async fn with_conn<Conn, R, Func>(
+    pool: &Pool<MobcManager<Conn>>,
+    op: &'static str,
+    f: Func,
+) -> AsyncResult<R>
+where
+    Conn: 'static + Connection,
+    R: 'static + Send,
+    Func: 'static + FnOnce(&mut Conn) -> AsyncResult<R> + Send,
+{
+    spawn_with_conn(pool, op, f).await
+}
+
+#[cfg(feature = "tokio-rt-threaded")]
+async fn with_conn<Conn, R, Func>(
+    pool: &Pool<MobcManager<Conn>>,
+    op: &'static str,
+    f: Func,
+) -> AsyncResult<R>
+where
+    Conn: 'static + Connection,
+    R: 'static + Send,
+    Func: FnOnce(&mut Conn) -> AsyncResult<R> + Send,
+{
+    let span = OpSpan::new(op);
+    let mut conn = checkout(pool, &span).await?;
+    task::block_in_place(|| {
+        span.in_scope(|| {
+            let start = Instant::now();
+            span.finished(start, f(&mut *conn))
+        })
+    })
+}
+
+// Like `spawn_with_conn`, but gives up waiting after `timeout`. The work is spawned as its own
+// task so the connection is still returned once the work completes.
+async fn with_conn_timeout<Conn, R, Func>(
+    pool: &Pool<MobcManager<Conn>>,
+    op: &'static str,
+    timeout: Duration,
+    f: Func,
+) -> AsyncResult<R>
+where
+    Conn: 'static + Connection,
+    R: 'static + Send,
+    Func: 'static + FnOnce(&mut Conn) -> AsyncResult<R> + Send,
+{
+    let pool = pool.clone();
+    let handle = rt::spawn(async move { spawn_with_conn(&pool, op, f).await });
+
+    rt::timeout(timeout, handle).await?
+}
+
+#[async_trait]
+impl<Conn> AsyncSimpleConnection<Conn> for Pool<MobcManager<Conn>>
+where
+    Conn: 'static + Connection,
+{
+    #[inline]
+    async fn batch_execute_async(&self, query: &str) -> AsyncResult<()> {
+        let query = query.to_string();
+        spawn_with_conn(self, "batch_execute", move |conn| {
+            conn.batch_execute(&query).map_err(AsyncError::Error)
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl<Conn> AsyncConnection<Conn> for Pool<MobcManager<Conn>>
+where
+    Conn: 'static + Connection,
+{
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn run<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        with_conn(self, "run", move |conn| f(conn).map_err(AsyncError::Error)).await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn run<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        with_conn(self, "run", move |conn| f(conn).map_err(AsyncError::Error)).await
+    }
+
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn transaction<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        with_conn(self, "transaction", move |conn| {
+            conn.transaction(|| f(conn)).map_err(AsyncError::Error)
+        })
+        .await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn transaction<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        with_conn(self, "transaction", move |conn| {
+            conn.transaction(|| f(conn)).map_err(AsyncError::Error)
+        })
+        .await
+    }
+
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn run_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        with_conn(self, "run_mut", move |conn| {
+            f(conn).map_err(AsyncError::Error)
+        })
+        .await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn run_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        with_conn(self, "run_mut", move |conn| {
+            f(conn).map_err(AsyncError::Error)
+        })
+        .await
+    }
+
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn transaction_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        with_conn(self, "transaction_mut", move |conn| {
+            transaction_mut(conn, f).map_err(AsyncError::Error)
+        })
+        .await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn transaction_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        with_conn(self, "transaction_mut", move |conn| {
+            transaction_mut(conn, f).map_err(AsyncError::Error)
+        })
+        .await
+    }
+
+    #[inline]
+    async fn run_timeout<R, Func>(&self, timeout: Duration, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        with_conn_timeout(self, "run_timeout", timeout, move |conn| {
+            f(conn).map_err(AsyncError::Error)
+        })
+        .await
+    }
+
+    #[inline]
+    async fn transaction_timeout<R, Func>(&self, timeout: Duration, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        with_conn_timeout(self, "transaction_timeout", timeout, move |conn| {
+            conn.transaction(|| f(conn)).map_err(AsyncError::Error)
+        })
+        .await
+    }
+
+    #[inline]
+    async fn pool_state_async(&self) -> AsyncResult<PoolState> {
+        let state = self.state().await;
+        Ok(PoolState {
+            connections: state.connections as u32,
+            idle_connections: state.idle as u32,
+        })
+    }
+}
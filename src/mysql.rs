@@ -0,0 +1,84 @@
+use crate::{AsyncConnection, AsyncResult, AsyncRunQueryDsl};
+use diesel::{
+    mysql::{Mysql, MysqlConnection},
+    query_builder::{AsChangeset, AstPass, QueryFragment, QueryId},
+    query_dsl::RunQueryDsl,
+    result::QueryResult,
+};
+
+// `INSERT ... ON DUPLICATE KEY UPDATE ...`, which diesel 1.x cannot express
+struct OnDuplicateKeyUpdate<I, C> {
+    insert: I,
+    changes: C,
+}
+
+impl<I, C> QueryId for OnDuplicateKeyUpdate<I, C> {
+    type QueryId = ();
+
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<I, C> QueryFragment<Mysql> for OnDuplicateKeyUpdate<I, C>
+where
+    I: QueryFragment<Mysql>,
+    C: QueryFragment<Mysql>,
+{
+    fn walk_ast(&self, mut out: AstPass<Mysql>) -> QueryResult<()> {
+        self.insert.walk_ast(out.reborrow())?;
+        out.push_sql(" ON DUPLICATE KEY UPDATE ");
+        self.changes.walk_ast(out.reborrow())?;
+        Ok(())
+    }
+}
+
+impl<I, C> RunQueryDsl<MysqlConnection> for OnDuplicateKeyUpdate<I, C> {}
+
+/// Run `insert` as `INSERT ... ON DUPLICATE KEY UPDATE`, applying `changes` to the existing
+/// row when the insert conflicts with a primary or unique key.
+///
+/// Following MySQL, the number of affected rows is 1 for each inserted row and 2 for each
+/// updated one.
+#[cfg(not(feature = "tokio-rt-threaded"))]
+pub async fn upsert_async<I, U, AsyncConn>(
+    insert: I,
+    changes: U,
+    asc: &AsyncConn,
+) -> AsyncResult<usize>
+where
+    I: 'static + QueryFragment<Mysql> + Send,
+    U: AsChangeset,
+    U::Changeset: 'static + QueryFragment<Mysql> + Send,
+    AsyncConn: Send + Sync + AsyncConnection<MysqlConnection>,
+{
+    OnDuplicateKeyUpdate {
+        insert,
+        changes: changes.as_changeset(),
+    }
+    .execute_async(asc)
+    .await
+}
+
+/// Run `insert` as `INSERT ... ON DUPLICATE KEY UPDATE`, applying `changes` to the existing
+/// row when the insert conflicts with a primary or unique key.
+///
+/// Following MySQL, the number of affected rows is 1 for each inserted row and 2 for each
+/// updated one.
+#[cfg(feature = "tokio-rt-threaded")]
+pub async fn upsert_async<I, U, AsyncConn>(
+    insert: I,
+    changes: U,
+    asc: &AsyncConn,
+) -> AsyncResult<usize>
+where
+    I: QueryFragment<Mysql> + Send,
+    U: AsChangeset,
+    U::Changeset: QueryFragment<Mysql> + Send,
+    AsyncConn: Send + Sync + AsyncConnection<MysqlConnection>,
+{
+    OnDuplicateKeyUpdate {
+        insert,
+        changes: changes.as_changeset(),
+    }
+    .execute_async(asc)
+    .await
+}
@@ -0,0 +1,118 @@
+use crate::{AsyncConnection, AsyncResult};
+use diesel::{
+    backend::Backend,
+    query_builder::{AstPass, Query, QueryFragment, QueryId},
+    query_dsl::{methods::LoadQuery, RunQueryDsl},
+    result::QueryResult,
+    serialize::ToSql,
+    sql_types::BigInt,
+    Connection,
+};
+
+pub trait Paginate: Sized {
+    /// Select page `page` of `per_page` rows of this query, counting from 1, along with the
+    /// total number of rows. Both arguments are clamped to at least 1.
+    fn paginate(self, page: i64, per_page: i64) -> Paginated<Self>;
+}
+
+impl<T> Paginate for T {
+    fn paginate(self, page: i64, per_page: i64) -> Paginated<Self> {
+        let per_page = per_page.max(1);
+        Paginated {
+            query: self,
+            per_page,
+            offset: (page.max(1) - 1).saturating_mul(per_page),
+        }
+    }
+}
+
+/// A page of a query, created by `Paginate::paginate`.
+///
+/// Every row carries the total row count of the query through `COUNT(*) OVER ()`, so the page
+/// and the total are fetched in a single round trip.
+#[derive(Debug, Clone, Copy)]
+pub struct Paginated<T> {
+    query: T,
+    per_page: i64,
+    offset: i64,
+}
+
+impl<T> Paginated<T> {
+    /// Load the page along with the total number of rows of the query.
+    ///
+    /// A page past the last one has no rows to carry the count, in which case the total is 0.
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    pub async fn load_and_count_async<U, Conn, AsyncConn>(
+        self,
+        asc: &AsyncConn,
+    ) -> AsyncResult<(Vec<U>, i64)>
+    where
+        U: 'static + Send,
+        Self: 'static + LoadQuery<Conn, (U, i64)> + Send,
+        Conn: 'static + Connection,
+        AsyncConn: AsyncConnection<Conn>,
+    {
+        asc.run(move |conn| load_and_count(self, conn)).await
+    }
+
+    /// Load the page along with the total number of rows of the query.
+    ///
+    /// A page past the last one has no rows to carry the count, in which case the total is 0.
+    #[cfg(feature = "tokio-rt-threaded")]
+    pub async fn load_and_count_async<U, Conn, AsyncConn>(
+        self,
+        asc: &AsyncConn,
+    ) -> AsyncResult<(Vec<U>, i64)>
+    where
+        U: 'static + Send,
+        Self: LoadQuery<Conn, (U, i64)> + Send,
+        Conn: 'static + Connection,
+        AsyncConn: AsyncConnection<Conn>,
+    {
+        asc.run(move |conn| load_and_count(self, conn)).await
+    }
+}
+
+fn load_and_count<Q, U, Conn>(query: Q, conn: &Conn) -> QueryResult<(Vec<U>, i64)>
+where
+    Q: LoadQuery<Conn, (U, i64)>,
+{
+    let rows = query.load::<(U, i64)>(conn)?;
+    let total = rows.first().map_or(0, |row| row.1);
+    Ok((rows.into_iter().map(|row| row.0).collect(), total))
+}
+
+impl<T> QueryId for Paginated<T>
+where
+    T: QueryId,
+{
+    type QueryId = Paginated<T::QueryId>;
+
+    const HAS_STATIC_QUERY_ID: bool = T::HAS_STATIC_QUERY_ID;
+}
+
+impl<T> Query for Paginated<T>
+where
+    T: Query,
+{
+    type SqlType = (T::SqlType, BigInt);
+}
+
+impl<T, Conn> RunQueryDsl<Conn> for Paginated<T> {}
+
+impl<T, DB> QueryFragment<DB> for Paginated<T>
+where
+    DB: Backend,
+    T: QueryFragment<DB>,
+    i64: ToSql<BigInt, DB>,
+{
+    fn walk_ast(&self, mut out: AstPass<DB>) -> QueryResult<()> {
+        out.push_sql("SELECT *, COUNT(*) OVER () FROM (");
+        self.query.walk_ast(out.reborrow())?;
+        out.push_sql(") t LIMIT ");
+        out.push_bind_param::<BigInt, _>(&self.per_page)?;
+        out.push_sql(" OFFSET ");
+        out.push_bind_param::<BigInt, _>(&self.offset)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,1590 @@
+#[cfg(feature = "tokio-rt-threaded")]
+use crate::AsyncTransactionBlocking;
+use crate::{
+    executor::{BlockingExecutor, DefaultExecutor},
+    rt, scope,
+    trace::{self, LeakWatch},
+    transaction_mut, with_checkout, AsyncConnection, AsyncError, AsyncResult,
+    AsyncRunWithCheckoutTimeout, AsyncSimpleConnection, AsyncTryRun, PoolState,
+};
+#[cfg(not(feature = "tokio-rt-threaded"))]
+use crate::{try_with_conn, with_conn_checkout_timeout};
+use async_trait::async_trait;
+use diesel::{
+    r2d2::{
+        self,
+        event::{AcquireEvent, CheckoutEvent, ReleaseEvent},
+        ConnectionManager, CustomizeConnection, HandleEvent, Pool, PooledConnection,
+    },
+    result::QueryResult,
+    Connection,
+};
+use futures::future::{self, Future};
+use std::{
+    collections::HashMap,
+    error::Error as StdError,
+    fmt,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard, PoisonError,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use tokio::sync::{Notify, OwnedSemaphorePermit, Semaphore};
+
+/// An r2d2 pool that bounds how many blocking database tasks may run at once.
+///
+/// Every operation waits for a permit before it is handed to the blocking thread pool and
+/// holds on to it until the blocking work has finished, so that a burst of slow queries
+/// cannot exhaust the threads shared with other `spawn_blocking` users.
+///
+/// The blocking work is run by the executor `E`, the blocking thread pool of the runtime unless
+/// another one is set with `AsyncPoolBuilder::executor`. With the `tokio-rt-threaded` feature,
+/// the closures of `run`, `transaction`, `run_mut`, `transaction_mut` and `try_run` need not be
+/// `'static` and keep running on the awaiting task instead.
+pub struct AsyncPool<Conn, E = DefaultExecutor>
+where
+    Conn: 'static + Connection,
+{
+    pool: Pool<ConnectionManager<Conn>>,
+    permits: Arc<Semaphore>,
+    timeout: Option<Duration>,
+    hooks: Arc<Hooks>,
+    in_flight: Arc<InFlight>,
+    executor: Arc<E>,
+}
+
+impl<Conn> AsyncPool<Conn>
+where
+    Conn: 'static + Connection,
+{
+    /// Wrap `pool`, allowing at most `concurrency` blocking tasks to run at the same time.
+    ///
+    /// Fails with `BuildError::InvalidConfig` if `concurrency` is 0, as no operation could ever
+    /// run, like `AsyncPoolBuilder::build` does.
+    pub fn with_concurrency(
+        pool: Pool<ConnectionManager<Conn>>,
+        concurrency: usize,
+    ) -> Result<Self, BuildError> {
+        if concurrency == 0 {
+            return Err(BuildError::InvalidConfig(
+                "concurrency must be greater than 0",
+            ));
+        }
+        Ok(AsyncPool {
+            pool,
+            permits: Arc::new(Semaphore::new(concurrency)),
+            timeout: None,
+            hooks: Arc::new(Hooks::default()),
+            in_flight: Arc::new(InFlight::default()),
+            executor: Arc::new(DefaultExecutor),
+        })
+    }
+
+    /// Create a builder that sets up the r2d2 pool along with the async behavior.
+    pub fn builder() -> AsyncPoolBuilder<Conn> {
+        AsyncPoolBuilder::new()
+    }
+}
+
+impl<Conn, E> AsyncPool<Conn, E>
+where
+    Conn: 'static + Connection,
+    E: BlockingExecutor,
+{
+    /// The underlying r2d2 pool.
+    pub fn pool(&self) -> &Pool<ConnectionManager<Conn>> {
+        &self.pool
+    }
+
+    /// Stop accepting new operations and wait for those in flight to finish.
+    ///
+    /// Operations started afterwards on this pool or any of its clones fail with
+    /// `AsyncError::ShuttingDown`. Resolves with `AsyncError::Timeout` if operations are still
+    /// running once `timeout` has elapsed.
+    pub async fn shutdown(&self, timeout: Duration) -> AsyncResult<()> {
+        self.in_flight.closed.store(true, Ordering::SeqCst);
+        rt::timeout(timeout, async {
+            while self.in_flight.count.load(Ordering::SeqCst) > 0 {
+                self.in_flight.idle.notified().await;
+            }
+        })
+        .await
+    }
+
+    /// Open up to `n` connections ahead of the first requests, so that those do not pay for
+    /// establishing a connection. The connections are checked out concurrently on the blocking
+    /// thread pool and all returned to the pool once every checkout has finished. `n` is capped
+    /// at the maximum size of the pool.
+    ///
+    /// If any connection could not be opened, fails with an `AsyncError::Checkout` counting the
+    /// failures, whose source is the first of them.
+    pub async fn warm_up(&self, n: u32) -> AsyncResult<()> {
+        let checkouts = (0..n.min(self.pool.max_size())).map(|_| {
+            let pool = self.pool.clone();
+            self.executor
+                .spawn_blocking(rt::instrument_blocking(move || pool.get()))
+        });
+        let results = future::join_all(checkouts).await;
+
+        let total = results.len();
+        let errors: Vec<_> = results.into_iter().filter_map(Result::err).collect();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(AsyncError::Checkout(Box::new(WarmUpError {
+                total,
+                errors,
+            })))
+        }
+    }
+
+    /// A handle to this pool whose operations must finish by `deadline`, e.g. the deadline of
+    /// the request being served. An operation started once the deadline has passed fails
+    /// right away with `AsyncError::Timeout`, any other one is given the time remaining.
+    pub fn with_deadline(&self, deadline: Instant) -> DeadlinePool<Conn, E> {
+        DeadlinePool {
+            pool: self.clone(),
+            deadline,
+        }
+    }
+
+    /// A handle to this pool on which a timeout and a label can be set without affecting the
+    /// pool or its other handles, e.g. a handle per part of an application.
+    pub fn handle(&self) -> AsyncHandle<Conn, E> {
+        AsyncHandle {
+            pool: self.clone(),
+            label: None,
+        }
+    }
+
+    /// Run `fut` with every operation of this pool and its clones running on `pool` instead,
+    /// e.g. to run service code against a throwaway database in a test without handing the
+    /// pool to every function.
+    ///
+    /// The override is task-local: it applies to the operations awaited within `fut`, on the
+    /// task awaiting it, but not to tasks spawned from `fut`. Scopes nest, the innermost one
+    /// wins. Methods of the pool itself, like `warm_up` or `shutdown`, are not redirected.
+    pub async fn scoped<Fut: Future>(pool: Self, fut: Fut) -> Fut::Output {
+        scope::scope(pool, fut).await
+    }
+
+    // The pool of the innermost `scoped` around the current task, unless it is this one
+    fn scoped_pool(&self) -> Option<Self> {
+        scope::current::<Self>().filter(|pool| !Arc::ptr_eq(&pool.in_flight, &self.in_flight))
+    }
+
+    // Register an operation and wait for a permit, both held until the blocking work is done
+    async fn enter(&self) -> AsyncResult<Ticket> {
+        self.in_flight.count.fetch_add(1, Ordering::SeqCst);
+        let in_flight = InFlightGuard(self.in_flight.clone());
+        if self.in_flight.closed.load(Ordering::SeqCst) {
+            return Err(AsyncError::ShuttingDown);
+        }
+
+        let permit = self.permits.clone().acquire_owned().await;
+        Ok(Ticket {
+            _permit: permit,
+            _in_flight: in_flight,
+        })
+    }
+
+    // Like `enter`, but fails right away if no permit is available
+    fn try_enter(&self) -> AsyncResult<Ticket> {
+        self.in_flight.count.fetch_add(1, Ordering::SeqCst);
+        let in_flight = InFlightGuard(self.in_flight.clone());
+        if self.in_flight.closed.load(Ordering::SeqCst) {
+            return Err(AsyncError::ShuttingDown);
+        }
+
+        let permit = self
+            .permits
+            .clone()
+            .try_acquire_owned()
+            .map_err(|_| AsyncError::NoConnectionAvailable)?;
+        Ok(Ticket {
+            _permit: permit,
+            _in_flight: in_flight,
+        })
+    }
+
+    // Apply the default timeout, if any, to an operation on the inner pool
+    async fn with_timeout<R>(&self, op: impl Future<Output = AsyncResult<R>>) -> AsyncResult<R> {
+        match self.timeout {
+            Some(timeout) => rt::timeout(timeout, op).await?,
+            None => op.await,
+        }
+    }
+
+    // Wait for a permit, then run the operation `op` returns with it. The default timeout, if
+    // any, covers the wait for the permit as well, which is the longest one on a busy pool.
+    async fn with_ticket<R, Fut>(&self, op: impl FnOnce(Ticket) -> Fut) -> AsyncResult<R>
+    where
+        Fut: Future<Output = AsyncResult<R>>,
+    {
+        self.with_timeout(async { op(self.enter().await?).await })
+            .await
+    }
+
+    // Create the hooks of an operation `op` about to run
+    fn observer(&self, op: &'static str) -> Observer {
+        Observer {
+            hooks: self.hooks.clone(),
+            op,
+            leak_watch: LeakWatch::new(self.hooks.leak_detection_threshold, op),
+        }
+    }
+
+    // Run `work` with the inner pool through the executor
+    async fn blocking<R, W>(&self, work: W) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        W: 'static + FnOnce(&Checkout<Conn>) -> AsyncResult<R> + Send,
+    {
+        let checkout = self.checkout();
+        self.executor
+            .spawn_blocking(rt::instrument_blocking(move || work(&checkout)))
+            .await
+    }
+
+    // Like `blocking`, but runs `work` on the awaiting task through `block_in_place`
+    #[cfg(feature = "tokio-rt-threaded")]
+    async fn block_in_place<R, W>(&self, work: W) -> AsyncResult<R>
+    where
+        W: FnOnce(&Checkout<Conn>) -> AsyncResult<R>,
+    {
+        let checkout = self.checkout();
+        rt::block_in_place(|| work(&checkout))
+    }
+
+    // The inner pool as used by the blocking work
+    fn checkout(&self) -> Checkout<Conn> {
+        Checkout {
+            pool: self.pool.clone(),
+            hooks: self.hooks.clone(),
+        }
+    }
+}
+
+impl<Conn, E> Clone for AsyncPool<Conn, E>
+where
+    Conn: 'static + Connection,
+{
+    fn clone(&self) -> Self {
+        AsyncPool {
+            pool: self.pool.clone(),
+            permits: self.permits.clone(),
+            timeout: self.timeout,
+            hooks: self.hooks.clone(),
+            in_flight: self.in_flight.clone(),
+            executor: self.executor.clone(),
+        }
+    }
+}
+
+/// An `AsyncPool` whose operations must finish by a deadline, created by
+/// `AsyncPool::with_deadline`.
+///
+/// With the `tokio-rt-threaded` feature the blocking work runs on the awaiting task and cannot
+/// be interrupted, so the deadline only covers the wait for a slot.
+pub struct DeadlinePool<Conn, E = DefaultExecutor>
+where
+    Conn: 'static + Connection,
+{
+    pool: AsyncPool<Conn, E>,
+    deadline: Instant,
+}
+
+impl<Conn, E> DeadlinePool<Conn, E>
+where
+    Conn: 'static + Connection,
+{
+    /// The deadline of the operations of this handle.
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    // Run `op` within the time remaining until the deadline, failing without starting it if
+    // there is none left
+    async fn within<R>(&self, op: impl Future<Output = AsyncResult<R>>) -> AsyncResult<R> {
+        match self.deadline.checked_duration_since(Instant::now()) {
+            Some(remaining) if remaining > Duration::from_secs(0) => {
+                rt::timeout(remaining, op).await?
+            }
+            _ => Err(AsyncError::Timeout(Duration::from_secs(0))),
+        }
+    }
+}
+
+impl<Conn, E> Clone for DeadlinePool<Conn, E>
+where
+    Conn: 'static + Connection,
+{
+    fn clone(&self) -> Self {
+        DeadlinePool {
+            pool: self.pool.clone(),
+            deadline: self.deadline,
+        }
+    }
+}
+
+/// A handle to an `AsyncPool` carrying its own settings, created by `AsyncPool::handle`.
+///
+/// Handles are as cheap to clone as the pool itself. They share its connections, its
+/// concurrency limit and its hooks.
+pub struct AsyncHandle<Conn, E = DefaultExecutor>
+where
+    Conn: 'static + Connection,
+{
+    pool: AsyncPool<Conn, E>,
+    label: Option<&'static str>,
+}
+
+impl<Conn, E> AsyncHandle<Conn, E>
+where
+    Conn: 'static + Connection,
+{
+    /// Fail the operations of this handle with `AsyncError::Timeout` if they do not finish
+    /// within `timeout`, instead of the timeout of the pool, if any.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.pool.timeout = Some(timeout);
+        self
+    }
+
+    /// Label the errors of the operations of this handle with `label`, as
+    /// `AsyncConnection::run_labeled` does.
+    pub fn label(mut self, label: &'static str) -> Self {
+        self.label = Some(label);
+        self
+    }
+
+    // Label the error of `op`, if this handle has a label
+    async fn labeled<R>(&self, op: impl Future<Output = AsyncResult<R>>) -> AsyncResult<R> {
+        match self.label {
+            Some(label) => op.await.map_err(|err| err.labeled(label)),
+            None => op.await,
+        }
+    }
+}
+
+impl<Conn, E> Clone for AsyncHandle<Conn, E>
+where
+    Conn: 'static + Connection,
+{
+    fn clone(&self) -> Self {
+        AsyncHandle {
+            pool: self.pool.clone(),
+            label: self.label,
+        }
+    }
+}
+
+// The inner pool of an `AsyncPool` as seen by the blocking work, retrying failed checkouts as
+// configured with `AsyncPoolBuilder::retry_on_checkout`
+struct Checkout<Conn>
+where
+    Conn: 'static + Connection,
+{
+    pool: Pool<ConnectionManager<Conn>>,
+    hooks: Arc<Hooks>,
+}
+
+impl<Conn> Checkout<Conn>
+where
+    Conn: 'static + Connection,
+{
+    fn get(&self) -> AsyncResult<PooledConnection<ConnectionManager<Conn>>> {
+        let mut backoff = self.hooks.checkout_backoff;
+        let mut attempt = 0;
+        loop {
+            match self.pool.get() {
+                Err(_) if attempt < self.hooks.checkout_retries => {
+                    attempt += 1;
+                    thread::sleep(backoff);
+                    backoff *= 2;
+                }
+                result => return result.map_err(AsyncError::from),
+            }
+        }
+    }
+}
+
+// Check out a connection through `checkout` and run `f` with it on the current thread
+fn with_conn<Conn, R, Func>(checkout: &Checkout<Conn>, op: &'static str, f: Func) -> AsyncResult<R>
+where
+    Conn: 'static + Connection,
+    Func: FnOnce(&mut Conn) -> QueryResult<R>,
+{
+    with_checkout(op, || checkout.get(), f)
+}
+
+// The checkouts of `AsyncPool::warm_up` that failed
+#[derive(Debug)]
+struct WarmUpError {
+    total: usize,
+    errors: Vec<r2d2::PoolError>,
+}
+
+impl fmt::Display for WarmUpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "failed to open {} of {} connections: {}",
+            self.errors.len(),
+            self.total,
+            self.errors[0]
+        )
+    }
+}
+
+impl StdError for WarmUpError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(&self.errors[0])
+    }
+}
+
+/// The error of `AsyncPoolBuilder::build`, telling a misconfigured pool apart from a database
+/// that cannot be reached.
+#[derive(Debug)]
+pub enum BuildError {
+    // A setting of the builder is out of range
+    InvalidConfig(&'static str),
+
+    // The initial connections could not be established
+    Connection(r2d2::PoolError),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BuildError::InvalidConfig(message) => {
+                write!(f, "invalid pool configuration: {}", message)
+            }
+            BuildError::Connection(ref err) => {
+                write!(f, "failed to establish the initial connections: {}", err)
+            }
+        }
+    }
+}
+
+impl StdError for BuildError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match *self {
+            BuildError::InvalidConfig(_) => None,
+            BuildError::Connection(ref err) => Some(err),
+        }
+    }
+}
+
+// The operations currently running on a pool and its clones
+#[derive(Default)]
+struct InFlight {
+    closed: AtomicBool,
+    count: AtomicUsize,
+    idle: Notify,
+}
+
+struct InFlightGuard(Arc<InFlight>);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        if self.0.count.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.idle.notify();
+        }
+    }
+}
+
+struct Ticket {
+    _permit: OwnedSemaphorePermit,
+    _in_flight: InFlightGuard,
+}
+
+/// A builder for an `AsyncPool`, configuring the r2d2 pool and the async behavior together.
+///
+/// ```no_run
+/// # use diesel::pg::PgConnection;
+/// # use std::time::Duration;
+/// # use tokio_diesel::AsyncPool;
+/// let pool = AsyncPool::<PgConnection>::builder()
+///     .max_size(16)
+///     .concurrency(8)
+///     .timeout(Duration::from_secs(5))
+///     .build("postgres://localhost/app")?;
+/// # Ok::<(), tokio_diesel::BuildError>(())
+/// ```
+pub struct AsyncPoolBuilder<Conn, E = DefaultExecutor>
+where
+    Conn: 'static + Connection,
+{
+    builder: r2d2::Builder<ConnectionManager<Conn>>,
+    max_size: u32,
+    concurrency: Option<usize>,
+    connection_timeout: Option<Duration>,
+    timeout: Option<Duration>,
+    checkout_retries: usize,
+    checkout_backoff: Duration,
+    slow_query_threshold: Option<Duration>,
+    leak_detection_threshold: Option<Duration>,
+    instrumentation: Option<Instrumentation>,
+    events: EventHandler,
+    on_acquire: Vec<String>,
+    executor: E,
+}
+
+impl<Conn> AsyncPoolBuilder<Conn>
+where
+    Conn: 'static + Connection,
+{
+    pub fn new() -> Self {
+        AsyncPoolBuilder {
+            builder: Pool::builder(),
+            max_size: 10,
+            concurrency: None,
+            connection_timeout: None,
+            timeout: None,
+            checkout_retries: 0,
+            checkout_backoff: Duration::from_secs(0),
+            slow_query_threshold: None,
+            leak_detection_threshold: None,
+            instrumentation: None,
+            events: EventHandler::default(),
+            on_acquire: Vec::new(),
+            executor: DefaultExecutor,
+        }
+    }
+}
+
+impl<Conn, E> AsyncPoolBuilder<Conn, E>
+where
+    Conn: 'static + Connection,
+    E: BlockingExecutor,
+{
+    /// The maximum number of connections of the pool. Defaults to 10.
+    pub fn max_size(mut self, max_size: u32) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// The maximum number of blocking tasks allowed to run at once. Defaults to the maximum
+    /// number of connections.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = Some(concurrency);
+        self
+    }
+
+    /// How long to wait for a connection to become available before failing the checkout.
+    pub fn connection_timeout(mut self, connection_timeout: Duration) -> Self {
+        self.connection_timeout = Some(connection_timeout);
+        self
+    }
+
+    /// The default timeout of every operation that is not given one explicitly, after which it
+    /// resolves with `AsyncError::Timeout`. It includes the wait for one of the `concurrency`
+    /// slots, so that operations on a saturated pool fail rather than queue indefinitely.
+    ///
+    /// With the `tokio-rt-threaded` feature the blocking work runs on the awaiting task and
+    /// cannot be interrupted, so the timeout only covers the wait for a slot.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Retry a failed checkout up to `retries` times before failing the operation with
+    /// `AsyncError::Checkout`, e.g. to ride out a database restarting during maintenance. The
+    /// retries wait `backoff`, doubled after each of them; the query itself is never retried.
+    ///
+    /// Each checkout waits for up to the connection timeout itself, and the backoff is slept on
+    /// the blocking thread of the operation.
+    pub fn retry_on_checkout(mut self, retries: usize, backoff: Duration) -> Self {
+        self.checkout_retries = retries;
+        self.checkout_backoff = backoff;
+        self
+    }
+
+    /// Log every operation whose query takes longer than `threshold` with `tracing::warn!`,
+    /// along with the time it took. Only the work on the connection is measured, not waiting
+    /// for the checkout. Without the `tracing` feature nothing is logged.
+    pub fn slow_query_threshold(mut self, threshold: Duration) -> Self {
+        self.slow_query_threshold = Some(threshold);
+        self
+    }
+
+    /// Warn with `tracing::warn!` about every operation that holds on to its connection for
+    /// longer than `threshold`, e.g. a stuck query monopolizing the pool, or a transaction
+    /// closure accidentally blocked on `std::thread::sleep` or a network call. The warning is
+    /// logged in the span of the caller while the work is still running, which is left to
+    /// finish. Without the `tracing` feature nothing is logged.
+    pub fn leak_detection_threshold(mut self, threshold: Duration) -> Self {
+        self.leak_detection_threshold = Some(threshold);
+        self
+    }
+
+    /// Call `f` whenever an operation starts and finishes its work on a connection, e.g. to
+    /// log queries without the `tracing` feature.
+    ///
+    /// Diesel 1.x has no hook into the statements run on a connection, so the events describe
+    /// the operations of the pool, such as a `run` or a `load_async`, rather than every single
+    /// statement. `f` is called on the blocking thread running the operation, possibly from
+    /// several threads at once.
+    pub fn instrumentation<F>(mut self, f: F) -> Self
+    where
+        F: 'static + Fn(&InstrumentationEvent) + Send + Sync,
+    {
+        self.instrumentation = Some(Box::new(f));
+        self
+    }
+
+    /// Call `f` whenever a connection is checked out of the pool, with the time the checkout
+    /// took and whether the connection was freshly established, e.g. to observe pool
+    /// contention or to track down connections that are reopened too often.
+    ///
+    /// `f` is called on the blocking thread that checked out the connection, possibly from
+    /// several threads at once, and delays the operation it belongs to until it returns.
+    pub fn on_checkout<F>(mut self, f: F) -> Self
+    where
+        F: 'static + Fn(CheckoutInfo) + Send + Sync,
+    {
+        self.events.on_checkout = Some(Box::new(f));
+        self
+    }
+
+    /// Call `f` whenever the pool has opened a new connection, e.g. to graph the rate at which
+    /// connections are created while tuning the `max_lifetime` and `idle_timeout` of the pool.
+    /// This includes the initial connections and those replacing connections that were closed.
+    ///
+    /// `f` is called on the thread that opened the connection, once it has been set up.
+    pub fn on_new_connection<F>(mut self, f: F) -> Self
+    where
+        F: 'static + Fn() + Send + Sync,
+    {
+        self.events.on_new_connection = Some(Box::new(f));
+        self
+    }
+
+    /// Run `statements` on every new connection before it is handed out, e.g. to set the
+    /// `search_path` or a `statement_timeout`. A connection on which a statement fails is
+    /// discarded, which fails `build` if no initial connection can be set up.
+    pub fn on_acquire(mut self, statements: &[&str]) -> Self {
+        self.on_acquire
+            .extend(statements.iter().map(|statement| statement.to_string()));
+        self
+    }
+
+    /// Run the blocking work of the pool with `executor`, e.g. on a rayon pool or a dedicated set
+    /// of threads, instead of the blocking thread pool of the runtime.
+    pub fn executor<E2>(self, executor: E2) -> AsyncPoolBuilder<Conn, E2>
+    where
+        E2: BlockingExecutor,
+    {
+        AsyncPoolBuilder {
+            builder: self.builder,
+            max_size: self.max_size,
+            concurrency: self.concurrency,
+            connection_timeout: self.connection_timeout,
+            timeout: self.timeout,
+            checkout_retries: self.checkout_retries,
+            checkout_backoff: self.checkout_backoff,
+            slow_query_threshold: self.slow_query_threshold,
+            leak_detection_threshold: self.leak_detection_threshold,
+            instrumentation: self.instrumentation,
+            events: self.events,
+            on_acquire: self.on_acquire,
+            executor,
+        }
+    }
+
+    /// Build the pool for `database_url`, establishing its initial connections.
+    ///
+    /// Fails with `BuildError::InvalidConfig` if a setting is out of range, e.g. a `max_size`
+    /// of 0, and with `BuildError::Connection` if the initial connections could not be
+    /// established within the connection timeout.
+    pub fn build(self, database_url: impl Into<String>) -> Result<AsyncPool<Conn, E>, BuildError> {
+        if self.max_size == 0 {
+            return Err(BuildError::InvalidConfig("max_size must be greater than 0"));
+        }
+        if self.concurrency == Some(0) {
+            return Err(BuildError::InvalidConfig(
+                "concurrency must be greater than 0",
+            ));
+        }
+        let mut builder = self
+            .builder
+            .max_size(self.max_size)
+            .event_handler(Box::new(self.events));
+        if let Some(connection_timeout) = self.connection_timeout {
+            if connection_timeout == Duration::from_secs(0) {
+                return Err(BuildError::InvalidConfig(
+                    "connection_timeout must be greater than 0",
+                ));
+            }
+            builder = builder.connection_timeout(connection_timeout);
+        }
+        if !self.on_acquire.is_empty() {
+            builder = builder.connection_customizer(Box::new(OnAcquire(self.on_acquire)));
+        }
+        let pool = builder
+            .build(ConnectionManager::new(database_url))
+            .map_err(BuildError::Connection)?;
+        let concurrency = self.concurrency.unwrap_or(self.max_size as usize);
+
+        Ok(AsyncPool {
+            pool,
+            permits: Arc::new(Semaphore::new(concurrency)),
+            timeout: self.timeout,
+            hooks: Arc::new(Hooks {
+                checkout_retries: self.checkout_retries,
+                checkout_backoff: self.checkout_backoff,
+                slow_query_threshold: self.slow_query_threshold,
+                leak_detection_threshold: self.leak_detection_threshold,
+                instrumentation: self.instrumentation,
+            }),
+            in_flight: Arc::new(InFlight::default()),
+            executor: Arc::new(self.executor),
+        })
+    }
+}
+
+/// An event of the operations of an `AsyncPool`, handed to the callback registered with
+/// `AsyncPoolBuilder::instrumentation`.
+#[derive(Debug)]
+pub enum InstrumentationEvent<'a> {
+    // An operation checked out its connection and is about to run its work on it
+    Started {
+        op: &'static str,
+    },
+
+    // The work of an operation finished, successfully unless `error` is set
+    Finished {
+        op: &'static str,
+        elapsed: Duration,
+        error: Option<&'a diesel::result::Error>,
+    },
+}
+
+type Instrumentation = Box<dyn Fn(&InstrumentationEvent) + Send + Sync>;
+
+// The callbacks observing the work of every operation of a pool
+#[derive(Default)]
+struct Hooks {
+    checkout_retries: usize,
+    checkout_backoff: Duration,
+    slow_query_threshold: Option<Duration>,
+    leak_detection_threshold: Option<Duration>,
+    instrumentation: Option<Instrumentation>,
+}
+
+impl Hooks {
+    // Run the work of `op` on a connection, reporting it to the hooks
+    fn observe<R>(&self, op: &'static str, f: impl FnOnce() -> QueryResult<R>) -> QueryResult<R> {
+        let instrumentation = match self.instrumentation {
+            Some(ref instrumentation) => instrumentation,
+            None => return trace::slow_query(self.slow_query_threshold, op, f),
+        };
+
+        instrumentation(&InstrumentationEvent::Started { op });
+        let start = Instant::now();
+        let result = trace::slow_query(self.slow_query_threshold, op, f);
+        instrumentation(&InstrumentationEvent::Finished {
+            op,
+            elapsed: start.elapsed(),
+            error: result.as_ref().err(),
+        });
+        result
+    }
+}
+
+// The hooks of a single operation, created on the async side before its work is handed off
+struct Observer {
+    hooks: Arc<Hooks>,
+    op: &'static str,
+    leak_watch: Option<LeakWatch>,
+}
+
+impl Observer {
+    // Run the work of the operation on the connection it checked out
+    fn observe<R>(self, f: impl FnOnce() -> QueryResult<R>) -> QueryResult<R> {
+        let _held = self.leak_watch.map(LeakWatch::checked_out);
+        self.hooks.observe(self.op, f)
+    }
+}
+
+/// A checkout of a connection, handed to the callback registered with
+/// `AsyncPoolBuilder::on_checkout`.
+#[derive(Debug, Clone, Copy)]
+pub struct CheckoutInfo {
+    /// How long the checkout waited for the connection.
+    pub duration: Duration,
+
+    /// Whether this is the first checkout of the connection since the pool opened it.
+    pub fresh: bool,
+
+    /// How long ago the pool opened the connection.
+    pub age: Duration,
+}
+
+// Forwards the events of the r2d2 pool to the callbacks registered on the builder
+#[derive(Default)]
+struct EventHandler {
+    on_checkout: Option<Box<dyn Fn(CheckoutInfo) + Send + Sync>>,
+    on_new_connection: Option<Box<dyn Fn() + Send + Sync>>,
+
+    // When each open connection was established, and whether it has been checked out since,
+    // by r2d2's id of the connection
+    connections: Mutex<HashMap<u64, (Instant, bool)>>,
+}
+
+impl EventHandler {
+    // The tracked connections, whose map stays consistent even if a callback panicked
+    fn connections(&self) -> MutexGuard<'_, HashMap<u64, (Instant, bool)>> {
+        self.connections
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl fmt::Debug for EventHandler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EventHandler").finish()
+    }
+}
+
+impl HandleEvent for EventHandler {
+    fn handle_acquire(&self, event: AcquireEvent) {
+        if self.on_checkout.is_some() {
+            self.connections()
+                .insert(event.connection_id(), (Instant::now(), false));
+        }
+        if let Some(ref on_new_connection) = self.on_new_connection {
+            on_new_connection();
+        }
+    }
+
+    fn handle_release(&self, event: ReleaseEvent) {
+        if self.on_checkout.is_some() {
+            self.connections().remove(&event.connection_id());
+        }
+    }
+
+    fn handle_checkout(&self, event: CheckoutEvent) {
+        if let Some(ref on_checkout) = self.on_checkout {
+            let (fresh, age) = match self.connections().get_mut(&event.connection_id()) {
+                Some(&mut (established, ref mut checked_out)) => {
+                    (!std::mem::replace(checked_out, true), established.elapsed())
+                }
+                None => (false, Duration::from_secs(0)),
+            };
+            on_checkout(CheckoutInfo {
+                duration: event.duration(),
+                fresh,
+                age,
+            });
+        }
+    }
+}
+
+// Runs the statements registered with `AsyncPoolBuilder::on_acquire` on new connections
+#[derive(Debug)]
+struct OnAcquire(Vec<String>);
+
+impl<Conn> CustomizeConnection<Conn, r2d2::Error> for OnAcquire
+where
+    Conn: 'static + Connection,
+{
+    fn on_acquire(&self, conn: &mut Conn) -> Result<(), r2d2::Error> {
+        for statement in &self.0 {
+            conn.batch_execute(statement)
+                .map_err(r2d2::Error::QueryError)?;
+        }
+        Ok(())
+    }
+}
+
+impl<Conn> Default for AsyncPoolBuilder<Conn>
+where
+    Conn: 'static + Connection,
+{
+    fn default() -> Self {
+        AsyncPoolBuilder::new()
+    }
+}
+
+#[async_trait]
+impl<Conn, E> AsyncSimpleConnection<Conn> for AsyncPool<Conn, E>
+where
+    Conn: 'static + Connection,
+    E: BlockingExecutor,
+{
+    #[inline]
+    async fn batch_execute_async(&self, query: &str) -> AsyncResult<()> {
+        if let Some(pool) = self.scoped_pool() {
+            return pool.batch_execute_async(query).await;
+        }
+        let query = query.to_string();
+        let observer = self.observer("batch_execute");
+        self.with_ticket(move |ticket| {
+            self.blocking(move |pool| {
+                let _ticket = ticket;
+                with_conn(pool, "batch_execute", |conn| {
+                    observer.observe(|| conn.batch_execute(&query))
+                })
+            })
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl<Conn, E> AsyncConnection<Conn> for AsyncPool<Conn, E>
+where
+    Conn: 'static + Connection,
+    E: BlockingExecutor,
+{
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn run<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        if let Some(pool) = self.scoped_pool() {
+            return pool.run(f).await;
+        }
+        let observer = self.observer("run");
+        self.with_ticket(move |ticket| {
+            self.blocking(move |pool| {
+                let _ticket = ticket;
+                with_conn(pool, "run", |conn| observer.observe(|| f(conn)))
+            })
+        })
+        .await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn run<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        if let Some(pool) = self.scoped_pool() {
+            return pool.run(f).await;
+        }
+        let observer = self.observer("run");
+        self.with_ticket(move |ticket| {
+            self.block_in_place(move |pool| {
+                let _ticket = ticket;
+                with_conn(pool, "run", |conn| observer.observe(|| f(conn)))
+            })
+        })
+        .await
+    }
+
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn transaction<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        if let Some(pool) = self.scoped_pool() {
+            return pool.transaction(f).await;
+        }
+        let observer = self.observer("transaction");
+        self.with_ticket(move |ticket| {
+            self.blocking(move |pool| {
+                let _ticket = ticket;
+                with_conn(pool, "transaction", |conn| {
+                    conn.transaction(|| observer.observe(|| f(conn)))
+                })
+            })
+        })
+        .await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn transaction<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        if let Some(pool) = self.scoped_pool() {
+            return pool.transaction(f).await;
+        }
+        let observer = self.observer("transaction");
+        self.with_ticket(move |ticket| {
+            self.block_in_place(move |pool| {
+                let _ticket = ticket;
+                with_conn(pool, "transaction", |conn| {
+                    conn.transaction(|| observer.observe(|| f(conn)))
+                })
+            })
+        })
+        .await
+    }
+
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn run_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        if let Some(pool) = self.scoped_pool() {
+            return pool.run_mut(f).await;
+        }
+        let observer = self.observer("run_mut");
+        self.with_ticket(move |ticket| {
+            self.blocking(move |pool| {
+                let _ticket = ticket;
+                with_conn(pool, "run_mut", |conn| observer.observe(|| f(conn)))
+            })
+        })
+        .await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn run_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        if let Some(pool) = self.scoped_pool() {
+            return pool.run_mut(f).await;
+        }
+        let observer = self.observer("run_mut");
+        self.with_ticket(move |ticket| {
+            self.block_in_place(move |pool| {
+                let _ticket = ticket;
+                with_conn(pool, "run_mut", |conn| observer.observe(|| f(conn)))
+            })
+        })
+        .await
+    }
+
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn transaction_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        if let Some(pool) = self.scoped_pool() {
+            return pool.transaction_mut(f).await;
+        }
+        let observer = self.observer("transaction_mut");
+        self.with_ticket(move |ticket| {
+            self.blocking(move |pool| {
+                let _ticket = ticket;
+                with_conn(pool, "transaction_mut", |conn| {
+                    transaction_mut(conn, |conn| observer.observe(|| f(conn)))
+                })
+            })
+        })
+        .await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn transaction_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        if let Some(pool) = self.scoped_pool() {
+            return pool.transaction_mut(f).await;
+        }
+        let observer = self.observer("transaction_mut");
+        self.with_ticket(move |ticket| {
+            self.block_in_place(move |pool| {
+                let _ticket = ticket;
+                with_conn(pool, "transaction_mut", |conn| {
+                    transaction_mut(conn, |conn| observer.observe(|| f(conn)))
+                })
+            })
+        })
+        .await
+    }
+
+    #[inline]
+    async fn run_timeout<R, Func>(&self, timeout: Duration, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        if let Some(pool) = self.scoped_pool() {
+            return pool.run_timeout(timeout, f).await;
+        }
+        let observer = self.observer("run_timeout");
+        rt::timeout(timeout, async move {
+            let ticket = self.enter().await?;
+            self.blocking(move |pool| {
+                let _ticket = ticket;
+                with_conn(pool, "run_timeout", |conn| observer.observe(|| f(conn)))
+            })
+            .await
+        })
+        .await?
+    }
+
+    #[inline]
+    async fn transaction_timeout<R, Func>(&self, timeout: Duration, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        if let Some(pool) = self.scoped_pool() {
+            return pool.transaction_timeout(timeout, f).await;
+        }
+        let observer = self.observer("transaction_timeout");
+        rt::timeout(timeout, async move {
+            let ticket = self.enter().await?;
+            self.blocking(move |pool| {
+                let _ticket = ticket;
+                with_conn(pool, "transaction_timeout", |conn| {
+                    conn.transaction(|| observer.observe(|| f(conn)))
+                })
+            })
+            .await
+        })
+        .await?
+    }
+
+    #[inline]
+    async fn pool_state_async(&self) -> AsyncResult<PoolState> {
+        if let Some(pool) = self.scoped_pool() {
+            return pool.pool_state_async().await;
+        }
+        self.pool.pool_state_async().await
+    }
+}
+
+#[async_trait]
+impl<Conn, E> AsyncTryRun<Conn> for AsyncPool<Conn, E>
+where
+    Conn: 'static + Connection,
+    E: BlockingExecutor,
+{
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn try_run<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        if let Some(pool) = self.scoped_pool() {
+            return pool.try_run(f).await;
+        }
+        let ticket = self.try_enter()?;
+        let observer = self.observer("try_run");
+        self.with_timeout(self.blocking(move |pool| {
+            let _ticket = ticket;
+            try_with_conn(&pool.pool, "try_run", |conn| observer.observe(|| f(conn)))
+        }))
+        .await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn try_run<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        if let Some(pool) = self.scoped_pool() {
+            return pool.try_run(f).await;
+        }
+        let ticket = self.try_enter()?;
+        let observer = self.observer("try_run");
+        self.with_timeout(self.pool.try_run(move |conn| {
+            let _ticket = ticket;
+            observer.observe(|| f(conn))
+        }))
+        .await
+    }
+}
+
+#[async_trait]
+impl<Conn, E> AsyncRunWithCheckoutTimeout<Conn> for AsyncPool<Conn, E>
+where
+    Conn: 'static + Connection,
+    E: BlockingExecutor,
+{
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn run_with_checkout_timeout<R, Func>(&self, timeout: Duration, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        if let Some(pool) = self.scoped_pool() {
+            return pool.run_with_checkout_timeout(timeout, f).await;
+        }
+        let observer = self.observer("run_with_checkout_timeout");
+        self.with_ticket(move |ticket| {
+            self.blocking(move |pool| {
+                let _ticket = ticket;
+                with_conn_checkout_timeout(
+                    &pool.pool,
+                    "run_with_checkout_timeout",
+                    timeout,
+                    |conn| observer.observe(|| f(conn)),
+                )
+            })
+        })
+        .await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn run_with_checkout_timeout<R, Func>(&self, timeout: Duration, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        if let Some(pool) = self.scoped_pool() {
+            return pool.run_with_checkout_timeout(timeout, f).await;
+        }
+        let observer = self.observer("run_with_checkout_timeout");
+        self.with_ticket(move |ticket| {
+            self.pool.run_with_checkout_timeout(timeout, move |conn| {
+                let _ticket = ticket;
+                observer.observe(|| f(conn))
+            })
+        })
+        .await
+    }
+}
+
+#[cfg(feature = "tokio-rt-threaded")]
+#[async_trait]
+impl<Conn, E> AsyncTransactionBlocking<Conn> for AsyncPool<Conn, E>
+where
+    Conn: 'static + Connection,
+    E: BlockingExecutor,
+{
+    #[inline]
+    async fn transaction_blocking<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        if let Some(pool) = self.scoped_pool() {
+            return pool.transaction_blocking(f).await;
+        }
+        let observer = self.observer("transaction_blocking");
+        self.with_ticket(move |ticket| {
+            self.block_in_place(move |pool| {
+                let _ticket = ticket;
+                with_conn(pool, "transaction_blocking", |conn| {
+                    conn.transaction(|| observer.observe(|| f(conn)))
+                })
+            })
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl<Conn, E> AsyncSimpleConnection<Conn> for DeadlinePool<Conn, E>
+where
+    Conn: 'static + Connection,
+    E: BlockingExecutor,
+{
+    #[inline]
+    async fn batch_execute_async(&self, query: &str) -> AsyncResult<()> {
+        self.within(self.pool.batch_execute_async(query)).await
+    }
+}
+
+#[async_trait]
+impl<Conn, E> AsyncConnection<Conn> for DeadlinePool<Conn, E>
+where
+    Conn: 'static + Connection,
+    E: BlockingExecutor,
+{
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn run<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        self.within(self.pool.run(f)).await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn run<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        self.within(self.pool.run(f)).await
+    }
+
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn transaction<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        self.within(self.pool.transaction(f)).await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn transaction<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        self.within(self.pool.transaction(f)).await
+    }
+
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn run_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        self.within(self.pool.run_mut(f)).await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn run_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        self.within(self.pool.run_mut(f)).await
+    }
+
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn transaction_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        self.within(self.pool.transaction_mut(f)).await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn transaction_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        self.within(self.pool.transaction_mut(f)).await
+    }
+
+    #[inline]
+    async fn run_timeout<R, Func>(&self, timeout: Duration, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        self.within(self.pool.run_timeout(timeout, f)).await
+    }
+
+    #[inline]
+    async fn transaction_timeout<R, Func>(&self, timeout: Duration, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        self.within(self.pool.transaction_timeout(timeout, f)).await
+    }
+
+    #[inline]
+    async fn pool_state_async(&self) -> AsyncResult<PoolState> {
+        self.pool.pool_state_async().await
+    }
+}
+
+#[async_trait]
+impl<Conn, E> AsyncSimpleConnection<Conn> for AsyncHandle<Conn, E>
+where
+    Conn: 'static + Connection,
+    E: BlockingExecutor,
+{
+    #[inline]
+    async fn batch_execute_async(&self, query: &str) -> AsyncResult<()> {
+        self.labeled(self.pool.batch_execute_async(query)).await
+    }
+}
+
+#[async_trait]
+impl<Conn, E> AsyncConnection<Conn> for AsyncHandle<Conn, E>
+where
+    Conn: 'static + Connection,
+    E: BlockingExecutor,
+{
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn run<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        self.labeled(self.pool.run(f)).await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn run<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        self.labeled(self.pool.run(f)).await
+    }
+
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn transaction<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        self.labeled(self.pool.transaction(f)).await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn transaction<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        self.labeled(self.pool.transaction(f)).await
+    }
+
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn run_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        self.labeled(self.pool.run_mut(f)).await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn run_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        self.labeled(self.pool.run_mut(f)).await
+    }
+
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn transaction_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        self.labeled(self.pool.transaction_mut(f)).await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn transaction_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        self.labeled(self.pool.transaction_mut(f)).await
+    }
+
+    #[inline]
+    async fn run_timeout<R, Func>(&self, timeout: Duration, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        self.labeled(self.pool.run_timeout(timeout, f)).await
+    }
+
+    #[inline]
+    async fn transaction_timeout<R, Func>(&self, timeout: Duration, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        self.labeled(self.pool.transaction_timeout(timeout, f))
+            .await
+    }
+
+    #[inline]
+    async fn pool_state_async(&self) -> AsyncResult<PoolState> {
+        self.pool.pool_state_async().await
+    }
+}
+
+#[async_trait]
+impl<Conn, E> AsyncTryRun<Conn> for AsyncHandle<Conn, E>
+where
+    Conn: 'static + Connection,
+    E: BlockingExecutor,
+{
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn try_run<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        self.labeled(self.pool.try_run(f)).await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn try_run<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        self.labeled(self.pool.try_run(f)).await
+    }
+}
+
+#[async_trait]
+impl<Conn, E> AsyncRunWithCheckoutTimeout<Conn> for AsyncHandle<Conn, E>
+where
+    Conn: 'static + Connection,
+    E: BlockingExecutor,
+{
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn run_with_checkout_timeout<R, Func>(&self, timeout: Duration, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        self.labeled(self.pool.run_with_checkout_timeout(timeout, f))
+            .await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn run_with_checkout_timeout<R, Func>(&self, timeout: Duration, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        self.labeled(self.pool.run_with_checkout_timeout(timeout, f))
+            .await
+    }
+}
+
+#[cfg(feature = "tokio-rt-threaded")]
+#[async_trait]
+impl<Conn, E> AsyncTransactionBlocking<Conn> for AsyncHandle<Conn, E>
+where
+    Conn: 'static + Connection,
+    E: BlockingExecutor,
+{
+    #[inline]
+    async fn transaction_blocking<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        self.labeled(self.pool.transaction_blocking(f)).await
+    }
+}
@@ -0,0 +1,798 @@
+use crate::{rt, AsyncConnection, AsyncResult, AsyncRunQueryDsl, LoadStream};
+use async_trait::async_trait;
+use diesel::{
+    connection::{Connection, SimpleConnection},
+    deserialize::Queryable,
+    dsl::sql,
+    expression::{Expression, NonAggregate, SelectableExpression},
+    insertable::CanInsertInSingleQuery,
+    pg::upsert::IncompleteOnConflict,
+    pg::{Pg, PgConnection},
+    query_builder::{
+        AsChangeset, AsQuery, AstPass, InsertStatement, Query, QueryFragment, QueryId,
+        UndecoratedInsertRecord,
+    },
+    query_dsl::RunQueryDsl,
+    result::QueryResult,
+    sql_types::{BigInt, Bool, Double, HasSqlType, Integer, Nullable, Text},
+    Table,
+};
+use std::{
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard, PoisonError,
+    },
+    time::Duration,
+};
+
+// The backend running a cancellable operation, shared between the blocking work and the
+// future awaiting it
+#[derive(Debug, Default)]
+struct Backend {
+    // Process id of the backend while the operation runs, 0 otherwise; Postgres never hands
+    // out 0 as a process id. The cancel request holds the lock while it is sent, and the
+    // operation takes it to clear the id before its connection is released, so the request can
+    // only reach a statement of this operation.
+    pid: Mutex<i32>,
+    cancelled: AtomicBool,
+}
+
+impl Backend {
+    fn pid(&self) -> MutexGuard<'_, i32> {
+        self.pid.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // Run `f` on `conn`, unless the operation has already been cancelled. Returns `None` in
+    // that case, which is only possible once nobody is waiting for the result anymore.
+    fn run<R, Func>(&self, conn: &PgConnection, f: Func) -> QueryResult<Option<R>>
+    where
+        Func: FnOnce(&PgConnection) -> QueryResult<R>,
+    {
+        let pid = diesel::select(sql::<Integer>("pg_backend_pid()")).get_result(conn)?;
+        {
+            let mut current = self.pid();
+            if self.cancelled.load(Ordering::SeqCst) {
+                return Ok(None);
+            }
+            *current = pid;
+        }
+
+        // Cleared even if `f` panics, as the connection is returned to the pool regardless
+        let _running = Running(self);
+        f(conn).map(Some)
+    }
+}
+
+// Clears the process id of a backend when dropped
+struct Running<'a>(&'a Backend);
+
+impl Drop for Running<'_> {
+    fn drop(&mut self) {
+        *self.0.pid() = 0;
+    }
+}
+
+// Cancels the operation on `backend` through another connection of `asc` when dropped while
+// still armed
+struct CancelOnDrop<AsyncConn>
+where
+    AsyncConn: 'static + Clone + Send + Sync + AsyncConnection<PgConnection>,
+{
+    asc: AsyncConn,
+    backend: Arc<Backend>,
+    armed: bool,
+}
+
+impl<AsyncConn> Drop for CancelOnDrop<AsyncConn>
+where
+    AsyncConn: 'static + Clone + Send + Sync + AsyncConnection<PgConnection>,
+{
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
+        }
+
+        // The operation either has not started yet and is skipped, or has already finished
+        self.backend.cancelled.store(true, Ordering::SeqCst);
+        if *self.backend.pid() == 0 {
+            return;
+        }
+
+        let asc = self.asc.clone();
+        let backend = self.backend.clone();
+        // Nobody waits for the cancel request, the task is detached
+        drop(rt::spawn(async move {
+            let _ = asc
+                .run(move |conn| {
+                    let pid = backend.pid();
+                    if *pid == 0 {
+                        return Ok(false);
+                    }
+                    diesel::select(sql::<Bool>(&format!("pg_cancel_backend({})", *pid)))
+                        .get_result::<bool>(conn)
+                })
+                .await;
+        }));
+    }
+}
+
+/// Like `AsyncConnection::run`, but cancels the query on the database when the returned future
+/// is dropped before it completes, e.g. because it lost a `select!` race.
+///
+/// The blocking work records the process id of its backend before running `f`, and dropping
+/// the future sends `pg_cancel_backend` for it through another connection of `asc`, so the
+/// connection is freed as soon as Postgres aborts the running statement. If the work has not
+/// started yet, `f` is skipped altogether.
+///
+/// This is Postgres-only and best-effort: the cancel request needs a second connection from
+/// `asc` and is not waited for. If `f` finishes before the request is sent, the request is
+/// skipped, so it only ever reaches a statement of `f`, never one of a later operation on the
+/// same connection.
+#[cfg(not(feature = "tokio-rt-threaded"))]
+pub async fn run_cancellable<R, Func, AsyncConn>(asc: &AsyncConn, f: Func) -> AsyncResult<R>
+where
+    R: 'static + Send,
+    Func: 'static + FnOnce(&PgConnection) -> QueryResult<R> + Send,
+    AsyncConn: 'static + Clone + Send + Sync + AsyncConnection<PgConnection>,
+{
+    let backend = Arc::new(Backend::default());
+    let mut guard = CancelOnDrop {
+        asc: asc.clone(),
+        backend: backend.clone(),
+        armed: true,
+    };
+    let result = asc.run(move |conn| backend.run(conn, f)).await;
+    guard.armed = false;
+    result.map(|value| value.expect("run_cancellable was cancelled while awaited"))
+}
+
+/// Like `AsyncConnection::run`, but cancels the query on the database when the returned future
+/// is dropped before it completes, e.g. because it lost a `select!` race.
+///
+/// The blocking work records the process id of its backend before running `f`, and dropping
+/// the future sends `pg_cancel_backend` for it through another connection of `asc`, so the
+/// connection is freed as soon as Postgres aborts the running statement. If the work has not
+/// started yet, `f` is skipped altogether.
+///
+/// This is Postgres-only and best-effort: the cancel request needs a second connection from
+/// `asc` and is not waited for. If `f` finishes before the request is sent, the request is
+/// skipped, so it only ever reaches a statement of `f`, never one of a later operation on the
+/// same connection. With `tokio-rt-threaded` the query blocks the awaiting task, so only an
+/// operation whose future is dropped before the query starts is cancelled.
+#[cfg(feature = "tokio-rt-threaded")]
+pub async fn run_cancellable<R, Func, AsyncConn>(asc: &AsyncConn, f: Func) -> AsyncResult<R>
+where
+    R: 'static + Send,
+    Func: FnOnce(&PgConnection) -> QueryResult<R> + Send,
+    AsyncConn: 'static + Clone + Send + Sync + AsyncConnection<PgConnection>,
+{
+    let backend = Arc::new(Backend::default());
+    let mut guard = CancelOnDrop {
+        asc: asc.clone(),
+        backend: backend.clone(),
+        armed: true,
+    };
+    let result = asc.run(move |conn| backend.run(conn, f)).await;
+    guard.armed = false;
+    result.map(|value| value.expect("run_cancellable was cancelled while awaited"))
+}
+
+/// Like `AsyncConnection::transaction`, but issues `SET CONSTRAINTS ALL DEFERRED` first, so
+/// that the constraints declared `DEFERRABLE` are only checked at commit, e.g. to insert rows
+/// in an order that violates a foreign key until the whole batch is in.
+///
+/// Constraints that are not `DEFERRABLE` are still checked after every statement. If a
+/// deferred constraint is violated at commit, the transaction is rolled back and fails with
+/// `AsyncError::Error`.
+#[cfg(not(feature = "tokio-rt-threaded"))]
+pub async fn transaction_deferred<R, Func, AsyncConn>(asc: &AsyncConn, f: Func) -> AsyncResult<R>
+where
+    R: 'static + Send,
+    Func: 'static + FnOnce(&PgConnection) -> QueryResult<R> + Send,
+    AsyncConn: AsyncConnection<PgConnection>,
+{
+    asc.transaction(move |conn| {
+        conn.batch_execute("SET CONSTRAINTS ALL DEFERRED")?;
+        f(conn)
+    })
+    .await
+}
+
+/// Like `AsyncConnection::transaction`, but issues `SET CONSTRAINTS ALL DEFERRED` first, so
+/// that the constraints declared `DEFERRABLE` are only checked at commit, e.g. to insert rows
+/// in an order that violates a foreign key until the whole batch is in.
+///
+/// Constraints that are not `DEFERRABLE` are still checked after every statement. If a
+/// deferred constraint is violated at commit, the transaction is rolled back and fails with
+/// `AsyncError::Error`.
+#[cfg(feature = "tokio-rt-threaded")]
+pub async fn transaction_deferred<R, Func, AsyncConn>(asc: &AsyncConn, f: Func) -> AsyncResult<R>
+where
+    R: 'static + Send,
+    Func: FnOnce(&PgConnection) -> QueryResult<R> + Send,
+    AsyncConn: AsyncConnection<PgConnection>,
+{
+    asc.transaction(move |conn| {
+        conn.batch_execute("SET CONSTRAINTS ALL DEFERRED")?;
+        f(conn)
+    })
+    .await
+}
+
+/// Like `AsyncConnection::transaction`, but issues `SET LOCAL statement_timeout` first, so that
+/// the server cancels any statement of `f` running longer than `timeout`, e.g. to cap a single
+/// analytics query without a pool-wide setting.
+///
+/// The setting is scoped to the transaction and resets when it ends. A statement exceeding the
+/// timeout fails with `AsyncError::Error`, and the transaction is rolled back.
+#[cfg(not(feature = "tokio-rt-threaded"))]
+pub async fn run_with_statement_timeout<R, Func, AsyncConn>(
+    asc: &AsyncConn,
+    timeout: Duration,
+    f: Func,
+) -> AsyncResult<R>
+where
+    R: 'static + Send,
+    Func: 'static + FnOnce(&PgConnection) -> QueryResult<R> + Send,
+    AsyncConn: AsyncConnection<PgConnection>,
+{
+    let statement = format!("SET LOCAL statement_timeout = {}", timeout.as_millis());
+    asc.transaction(move |conn| {
+        conn.batch_execute(&statement)?;
+        f(conn)
+    })
+    .await
+}
+
+/// Like `AsyncConnection::transaction`, but issues `SET LOCAL statement_timeout` first, so that
+/// the server cancels any statement of `f` running longer than `timeout`, e.g. to cap a single
+/// analytics query without a pool-wide setting.
+///
+/// The setting is scoped to the transaction and resets when it ends. A statement exceeding the
+/// timeout fails with `AsyncError::Error`, and the transaction is rolled back.
+#[cfg(feature = "tokio-rt-threaded")]
+pub async fn run_with_statement_timeout<R, Func, AsyncConn>(
+    asc: &AsyncConn,
+    timeout: Duration,
+    f: Func,
+) -> AsyncResult<R>
+where
+    R: 'static + Send,
+    Func: FnOnce(&PgConnection) -> QueryResult<R> + Send,
+    AsyncConn: AsyncConnection<PgConnection>,
+{
+    let statement = format!("SET LOCAL statement_timeout = {}", timeout.as_millis());
+    asc.transaction(move |conn| {
+        conn.batch_execute(&statement)?;
+        f(conn)
+    })
+    .await
+}
+
+// Cursors are named after a process-wide counter so that nested or concurrent ones on the same
+// connection never clash
+static NEXT_CURSOR: AtomicUsize = AtomicUsize::new(0);
+
+// `DECLARE <name> NO SCROLL CURSOR FOR <query>`
+struct Declare<'a, Q> {
+    name: &'a str,
+    query: &'a Q,
+}
+
+impl<'a, Q> QueryId for Declare<'a, Q> {
+    type QueryId = ();
+
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<'a, Q> QueryFragment<Pg> for Declare<'a, Q>
+where
+    Q: QueryFragment<Pg>,
+{
+    fn walk_ast(&self, mut out: AstPass<Pg>) -> QueryResult<()> {
+        out.unsafe_to_cache_prepared();
+        out.push_sql("DECLARE ");
+        out.push_identifier(self.name)?;
+        out.push_sql(" NO SCROLL CURSOR FOR ");
+        self.query.walk_ast(out.reborrow())
+    }
+}
+
+// `FETCH <batch> FROM <name>`, returning rows of the query the cursor was declared for
+struct Fetch<'a, ST> {
+    name: &'a str,
+    batch: usize,
+    sql_type: PhantomData<ST>,
+}
+
+impl<'a, ST> QueryId for Fetch<'a, ST> {
+    type QueryId = ();
+
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<'a, ST> Query for Fetch<'a, ST> {
+    type SqlType = ST;
+}
+
+impl<'a, ST> QueryFragment<Pg> for Fetch<'a, ST> {
+    fn walk_ast(&self, mut out: AstPass<Pg>) -> QueryResult<()> {
+        out.unsafe_to_cache_prepared();
+        out.push_sql(&format!("FETCH {} FROM ", self.batch));
+        out.push_identifier(self.name)
+    }
+}
+
+impl<'a, ST> RunQueryDsl<PgConnection> for Fetch<'a, ST> {}
+
+/// Loading rows through a Postgres server-side cursor.
+pub trait AsyncCursorDsl<AsyncConn>: AsQuery + Sized {
+    /// Load the rows of this query as a `Stream`, fetching `batch` rows at a time from a
+    /// server-side cursor so that the result set is never held in memory as a whole.
+    ///
+    /// The cursor lives in a transaction that holds on to its connection, and to a blocking
+    /// thread, until every row was fetched or the stream is dropped; at most one batch is
+    /// buffered ahead of the consumer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `batch` is 0.
+    fn cursor_stream_async<U>(self, batch: usize, asc: &AsyncConn) -> LoadStream<U>
+    where
+        U: 'static + Send + Queryable<Self::SqlType, Pg>,
+        Pg: HasSqlType<Self::SqlType>;
+}
+
+impl<T, AsyncConn> AsyncCursorDsl<AsyncConn> for T
+where
+    T: AsQuery,
+    T::Query: 'static + QueryFragment<Pg> + Send,
+    T::SqlType: 'static,
+    AsyncConn: 'static + Clone + Send + Sync + AsyncConnection<PgConnection>,
+{
+    fn cursor_stream_async<U>(self, batch: usize, asc: &AsyncConn) -> LoadStream<U>
+    where
+        U: 'static + Send + Queryable<Self::SqlType, Pg>,
+        Pg: HasSqlType<Self::SqlType>,
+    {
+        let (mut tx, stream) = LoadStream::channel(batch);
+        let query = self.as_query();
+        let asc = asc.clone();
+
+        // Detach the worker, it ends once every row is sent or the stream is dropped
+        drop(rt::spawn(async move {
+            let mut rows_tx = tx.clone();
+            let result = asc
+                .transaction(move |conn| {
+                    let name = format!(
+                        "tokio_diesel_cursor_{}",
+                        NEXT_CURSOR.fetch_add(1, Ordering::Relaxed)
+                    );
+                    conn.execute_returning_count(&Declare {
+                        name: &name,
+                        query: &query,
+                    })?;
+
+                    loop {
+                        let fetch = Fetch::<T::SqlType> {
+                            name: &name,
+                            batch,
+                            sql_type: PhantomData,
+                        };
+                        let rows = fetch.load::<U>(conn)?;
+                        let done = rows.len() < batch;
+                        for row in rows {
+                            if futures::executor::block_on(rows_tx.send(Ok(row))).is_err() {
+                                // The stream was dropped, committing closes the cursor
+                                return Ok(());
+                            }
+                        }
+                        if done {
+                            return Ok(());
+                        }
+                    }
+                })
+                .await;
+
+            if let Err(err) = result {
+                let _ = tx.send(Err(err)).await;
+            }
+        }));
+
+        stream
+    }
+}
+
+// `EXPLAIN <query>`, with `(ANALYZE, BUFFERS)` when `analyze` is set
+struct Explain<Q> {
+    query: Q,
+    analyze: bool,
+}
+
+impl<Q> QueryId for Explain<Q> {
+    type QueryId = ();
+
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<Q> Query for Explain<Q> {
+    type SqlType = Text;
+}
+
+impl<Q> QueryFragment<Pg> for Explain<Q>
+where
+    Q: QueryFragment<Pg>,
+{
+    fn walk_ast(&self, mut out: AstPass<Pg>) -> QueryResult<()> {
+        out.unsafe_to_cache_prepared();
+        if self.analyze {
+            out.push_sql("EXPLAIN (ANALYZE, BUFFERS) ");
+        } else {
+            out.push_sql("EXPLAIN ");
+        }
+        self.query.walk_ast(out.reborrow())
+    }
+}
+
+impl<Q> RunQueryDsl<PgConnection> for Explain<Q> {}
+
+/// Capturing the Postgres query plan of a query.
+#[async_trait]
+pub trait AsyncExplainDsl<AsyncConn>: AsQuery + Sized {
+    /// The plan Postgres chooses for this query, as the text `EXPLAIN` reports, one line of
+    /// the plan per line of the string. Bind parameters are sent along with the query, so the
+    /// plan is the one for the actual values.
+    ///
+    /// With `analyze`, the query is run as `EXPLAIN (ANALYZE, BUFFERS)`: it is executed, and
+    /// the plan includes the actual timings, row counts and buffer usage of each node.
+    async fn explain_async(self, analyze: bool, asc: &AsyncConn) -> AsyncResult<String>;
+}
+
+#[async_trait]
+impl<T, AsyncConn> AsyncExplainDsl<AsyncConn> for T
+where
+    T: AsQuery + Send,
+    T::Query: 'static + QueryFragment<Pg> + Send,
+    AsyncConn: Send + Sync + AsyncConnection<PgConnection>,
+{
+    async fn explain_async(self, analyze: bool, asc: &AsyncConn) -> AsyncResult<String> {
+        let explain = Explain {
+            query: self.as_query(),
+            analyze,
+        };
+        let lines = asc.run(move |conn| explain.load::<String>(conn)).await?;
+        Ok(lines.join("\n"))
+    }
+}
+
+/// Finish `conflict`, an insert with its conflict target picked by diesel's `on_conflict`,
+/// e.g. `insert_into(users).values(&rows).on_conflict((users::org, users::email))`, as
+/// `ON CONFLICT (target) DO UPDATE SET ...`, applying `changes` to the existing row. The values
+/// that were about to be inserted are available to `changes` through
+/// `diesel::pg::upsert::excluded`.
+///
+/// Returns the number of inserted or updated rows; `upsert_on_conflict_returning_async` loads
+/// them instead.
+#[cfg(not(feature = "tokio-rt-threaded"))]
+pub async fn upsert_on_conflict_async<T, U, Op, Ret, Target, Changes, AsyncConn>(
+    conflict: IncompleteOnConflict<InsertStatement<T, U, Op, Ret>, Target>,
+    changes: Changes,
+    asc: &AsyncConn,
+) -> AsyncResult<usize>
+where
+    T: 'static + Table + Send,
+    T::FromClause: QueryFragment<Pg>,
+    U: 'static + QueryFragment<Pg> + CanInsertInSingleQuery<Pg> + Send,
+    Op: 'static + QueryFragment<Pg> + Send,
+    Ret: 'static + QueryFragment<Pg> + Send,
+    Target: 'static + QueryFragment<Pg> + Send,
+    Changes: AsChangeset<Target = T>,
+    Changes::Changeset: 'static + QueryFragment<Pg> + Send,
+    AsyncConn: Send + Sync + AsyncConnection<PgConnection>,
+{
+    conflict.do_update().set(changes).execute_async(asc).await
+}
+
+/// Finish `conflict`, an insert with its conflict target picked by diesel's `on_conflict`,
+/// e.g. `insert_into(users).values(&rows).on_conflict((users::org, users::email))`, as
+/// `ON CONFLICT (target) DO UPDATE SET ...`, applying `changes` to the existing row. The values
+/// that were about to be inserted are available to `changes` through
+/// `diesel::pg::upsert::excluded`.
+///
+/// Returns the number of inserted or updated rows; `upsert_on_conflict_returning_async` loads
+/// them instead.
+#[cfg(feature = "tokio-rt-threaded")]
+pub async fn upsert_on_conflict_async<T, U, Op, Ret, Target, Changes, AsyncConn>(
+    conflict: IncompleteOnConflict<InsertStatement<T, U, Op, Ret>, Target>,
+    changes: Changes,
+    asc: &AsyncConn,
+) -> AsyncResult<usize>
+where
+    T: Table + Send,
+    T::FromClause: QueryFragment<Pg>,
+    U: QueryFragment<Pg> + CanInsertInSingleQuery<Pg> + Send,
+    Op: QueryFragment<Pg> + Send,
+    Ret: QueryFragment<Pg> + Send,
+    Target: QueryFragment<Pg> + Send,
+    Changes: AsChangeset<Target = T>,
+    Changes::Changeset: QueryFragment<Pg> + Send,
+    AsyncConn: Send + Sync + AsyncConnection<PgConnection>,
+{
+    conflict.do_update().set(changes).execute_async(asc).await
+}
+
+/// Like `upsert_on_conflict_async`, but returns the inserted or updated rows, as `RETURNING *`.
+#[cfg(not(feature = "tokio-rt-threaded"))]
+pub async fn upsert_on_conflict_returning_async<T, U, Op, Target, Changes, R, AsyncConn>(
+    conflict: IncompleteOnConflict<InsertStatement<T, U, Op>, Target>,
+    changes: Changes,
+    asc: &AsyncConn,
+) -> AsyncResult<Vec<R>>
+where
+    T: 'static + Table + Send,
+    T::FromClause: QueryFragment<Pg>,
+    T::AllColumns: QueryFragment<Pg> + SelectableExpression<T> + NonAggregate,
+    Pg: HasSqlType<<T::AllColumns as Expression>::SqlType>,
+    U: 'static + QueryFragment<Pg> + CanInsertInSingleQuery<Pg> + Send,
+    Op: 'static + QueryFragment<Pg> + Send,
+    Target: 'static + QueryFragment<Pg> + Send,
+    Changes: AsChangeset<Target = T>,
+    Changes::Changeset: 'static + QueryFragment<Pg> + Send,
+    R: 'static + Queryable<<T::AllColumns as Expression>::SqlType, Pg> + Send,
+    AsyncConn: Send + Sync + AsyncConnection<PgConnection>,
+{
+    conflict
+        .do_update()
+        .set(changes)
+        .execute_returning_async(asc)
+        .await
+}
+
+/// Like `upsert_on_conflict_async`, but returns the inserted or updated rows, as `RETURNING *`.
+#[cfg(feature = "tokio-rt-threaded")]
+pub async fn upsert_on_conflict_returning_async<T, U, Op, Target, Changes, R, AsyncConn>(
+    conflict: IncompleteOnConflict<InsertStatement<T, U, Op>, Target>,
+    changes: Changes,
+    asc: &AsyncConn,
+) -> AsyncResult<Vec<R>>
+where
+    T: Table + Send,
+    T::FromClause: QueryFragment<Pg>,
+    T::AllColumns: QueryFragment<Pg> + SelectableExpression<T> + NonAggregate,
+    Pg: HasSqlType<<T::AllColumns as Expression>::SqlType>,
+    U: QueryFragment<Pg> + CanInsertInSingleQuery<Pg> + Send,
+    Op: QueryFragment<Pg> + Send,
+    Target: QueryFragment<Pg> + Send,
+    Changes: AsChangeset<Target = T>,
+    Changes::Changeset: QueryFragment<Pg> + Send,
+    R: 'static + Queryable<<T::AllColumns as Expression>::SqlType, Pg> + Send,
+    AsyncConn: Send + Sync + AsyncConnection<PgConnection>,
+{
+    conflict
+        .do_update()
+        .set(changes)
+        .execute_returning_async(asc)
+        .await
+}
+
+/// Run `insert` as `INSERT ... ON CONFLICT DO NOTHING`, skipping the rows that conflict with
+/// an existing one on any unique constraint. Returns the number of rows actually inserted.
+#[cfg(not(feature = "tokio-rt-threaded"))]
+pub async fn insert_ignore_async<T, U, Op, Ret, AsyncConn>(
+    insert: InsertStatement<T, U, Op, Ret>,
+    asc: &AsyncConn,
+) -> AsyncResult<usize>
+where
+    T: 'static + Table + Send,
+    T::FromClause: QueryFragment<Pg>,
+    U: 'static + UndecoratedInsertRecord<T> + QueryFragment<Pg> + CanInsertInSingleQuery<Pg> + Send,
+    Op: 'static + QueryFragment<Pg> + Send,
+    Ret: 'static + QueryFragment<Pg> + Send,
+    AsyncConn: Send + Sync + AsyncConnection<PgConnection>,
+{
+    insert.on_conflict_do_nothing().execute_async(asc).await
+}
+
+/// Run `insert` as `INSERT ... ON CONFLICT DO NOTHING`, skipping the rows that conflict with
+/// an existing one on any unique constraint. Returns the number of rows actually inserted.
+#[cfg(feature = "tokio-rt-threaded")]
+pub async fn insert_ignore_async<T, U, Op, Ret, AsyncConn>(
+    insert: InsertStatement<T, U, Op, Ret>,
+    asc: &AsyncConn,
+) -> AsyncResult<usize>
+where
+    T: Table + Send,
+    T::FromClause: QueryFragment<Pg>,
+    U: UndecoratedInsertRecord<T> + QueryFragment<Pg> + CanInsertInSingleQuery<Pg> + Send,
+    Op: QueryFragment<Pg> + Send,
+    Ret: QueryFragment<Pg> + Send,
+    AsyncConn: Send + Sync + AsyncConnection<PgConnection>,
+{
+    insert.on_conflict_do_nothing().execute_async(asc).await
+}
+
+/// A fast estimate of the number of rows of `table_name`, read from `pg_class.reltuples`
+/// instead of counting them with `COUNT(*)`, e.g. for a dashboard on a huge table.
+///
+/// The estimate is only as current as the last `ANALYZE` or `VACUUM` of the table, which
+/// autovacuum runs from time to time; Postgres 14 and later report -1 for a table that has
+/// never been analyzed. `table_name` may be qualified with its schema and is otherwise looked
+/// up in the `search_path`; a table that does not exist fails the query.
+pub async fn count_estimate_async<AsyncConn>(table_name: &str, asc: &AsyncConn) -> AsyncResult<i64>
+where
+    AsyncConn: Send + Sync + AsyncConnection<PgConnection>,
+{
+    let table_name = table_name.to_string();
+    asc.run_read(move |conn| {
+        sql::<BigInt>("SELECT reltuples::bigint FROM pg_class WHERE oid = ")
+            .bind::<Text, _>(table_name)
+            .sql("::regclass")
+            .get_result(conn)
+    })
+    .await
+}
+
+/// A value of a row loaded by `query_dynamic_async`, or a bind parameter of its query.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DynamicValue {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Text(String),
+
+    // An array or a record, as JSON text
+    Json(String),
+}
+
+/// A row loaded by `query_dynamic_async`, with the name and value of each column.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DynamicRow {
+    columns: Vec<(String, DynamicValue)>,
+}
+
+impl DynamicRow {
+    /// The value of the column `name`, the first one if several columns have that name.
+    pub fn get(&self, name: &str) -> Option<&DynamicValue> {
+        self.columns
+            .iter()
+            .find(|(column, _)| column == name)
+            .map(|(_, value)| value)
+    }
+
+    /// The columns of the row, in the order they were selected.
+    pub fn columns(&self) -> &[(String, DynamicValue)] {
+        &self.columns
+    }
+}
+
+// Unpacks the rows of a query into one row per column, through `row_to_json`: the index of the
+// row, the name of the column, the JSON type of its value and the value as text
+struct Dynamic {
+    sql: String,
+    binds: Vec<DynamicValue>,
+}
+
+impl QueryId for Dynamic {
+    type QueryId = ();
+
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl Query for Dynamic {
+    type SqlType = (BigInt, Text, Text, Nullable<Text>);
+}
+
+impl QueryFragment<Pg> for Dynamic {
+    fn walk_ast(&self, mut out: AstPass<Pg>) -> QueryResult<()> {
+        out.unsafe_to_cache_prepared();
+        out.push_sql(
+            "SELECT t.n, e.key, json_typeof(e.value), e.value #>> '{}' \
+             FROM (SELECT row_number() OVER () AS n, row_to_json(q) AS j FROM (",
+        );
+        out.push_sql(&self.sql);
+        out.push_sql(
+            ") q) t, json_each(t.j) WITH ORDINALITY AS e(key, value, ordinality) \
+             ORDER BY t.n, e.ordinality",
+        );
+        // The placeholders are part of the SQL of the caller already
+        for bind in &self.binds {
+            match *bind {
+                DynamicValue::Null => {
+                    out.push_bind_param_value_only::<Nullable<Text>, Option<String>>(&None)?
+                }
+                DynamicValue::Bool(ref value) => {
+                    out.push_bind_param_value_only::<Bool, _>(value)?
+                }
+                DynamicValue::Int(ref value) => {
+                    out.push_bind_param_value_only::<BigInt, _>(value)?
+                }
+                DynamicValue::Float(ref value) => {
+                    out.push_bind_param_value_only::<Double, _>(value)?
+                }
+                DynamicValue::Text(ref value) | DynamicValue::Json(ref value) => {
+                    out.push_bind_param_value_only::<Text, _>(value)?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl RunQueryDsl<PgConnection> for Dynamic {}
+
+// Convert a value unpacked by `Dynamic` back from its JSON type
+fn dynamic_value(json_type: &str, value: Option<String>) -> DynamicValue {
+    let value = match value {
+        Some(value) => value,
+        None => return DynamicValue::Null,
+    };
+    match json_type {
+        "boolean" => DynamicValue::Bool(value == "true"),
+        "number" => value
+            .parse()
+            .map(DynamicValue::Int)
+            .ok()
+            .or_else(|| exact_float(&value).map(DynamicValue::Float))
+            .unwrap_or(DynamicValue::Text(value)),
+        "object" | "array" => DynamicValue::Json(value),
+        _ => DynamicValue::Text(value),
+    }
+}
+
+// Parse `number` as a float if that keeps every one of its digits, e.g. not for a `numeric`
+// with more of them than an `f64` holds, or out of its range
+fn exact_float(number: &str) -> Option<f64> {
+    fn digits(number: &str) -> String {
+        let mantissa = number.split(['e', 'E']).next().unwrap_or("");
+        let digits: String = mantissa.chars().filter(char::is_ascii_digit).collect();
+        digits
+            .trim_start_matches('0')
+            .trim_end_matches('0')
+            .to_string()
+    }
+
+    let float: f64 = number.parse().ok()?;
+    if float.is_finite() && digits(number) == digits(&format!("{:e}", float)) {
+        Some(float)
+    } else {
+        None
+    }
+}
+
+/// Run `sql`, with the parameters `$1`, `$2`, ... bound to `binds`, and load its rows without
+/// knowing their shape at compile time, e.g. for an SQL console.
+///
+/// `sql` must be a query that can be selected from, such as a `SELECT` or `VALUES`. Its values
+/// are converted through their JSON representation: numbers that an `i64` or `f64` cannot hold
+/// exactly, as well as dates, times and any other type without a JSON counterpart, are loaded
+/// as text. Text and JSON parameters are bound as `text`, so they may need a cast in `sql`.
+pub async fn query_dynamic_async<AsyncConn>(
+    sql: &str,
+    binds: Vec<DynamicValue>,
+    asc: &AsyncConn,
+) -> AsyncResult<Vec<DynamicRow>>
+where
+    AsyncConn: Send + Sync + AsyncConnection<PgConnection>,
+{
+    let query = Dynamic {
+        sql: sql.to_string(),
+        binds,
+    };
+    let values = asc
+        .run(move |conn| query.load::<(i64, String, String, Option<String>)>(conn))
+        .await?;
+
+    let mut rows: Vec<DynamicRow> = Vec::new();
+    let mut last = None;
+    for (n, column, json_type, value) in values {
+        if last != Some(n) {
+            rows.push(DynamicRow::default());
+            last = Some(n);
+        }
+        let row = rows.last_mut().expect("a row was just pushed");
+        row.columns.push((column, dynamic_value(&json_type, value)));
+    }
+    Ok(rows)
+}
@@ -0,0 +1,69 @@
+use crate::{AsyncConnection, AsyncResult};
+use diesel::{
+    query_dsl::{methods::ExecuteDsl, LoadQuery},
+    Connection, RunQueryDsl,
+};
+
+/// Load the rows of `query`, built with `diesel::sql_query` and any number of
+/// `.bind::<Type, _>(value)` calls, into a struct deriving `QueryableByName`, e.g. for a query
+/// the DSL cannot express.
+///
+/// Unlike `load_async`, the query always runs on the primary of a `SplitPool`, as raw SQL may
+/// write even when it returns rows.
+#[cfg(not(feature = "tokio-rt-threaded"))]
+pub async fn sql_query_async<U, Q, Conn, AsyncConn>(
+    query: Q,
+    asc: &AsyncConn,
+) -> AsyncResult<Vec<U>>
+where
+    U: 'static + Send,
+    Q: 'static + RunQueryDsl<Conn> + LoadQuery<Conn, U> + Send,
+    Conn: 'static + Connection,
+    AsyncConn: AsyncConnection<Conn>,
+{
+    asc.run(move |conn| query.load(conn)).await
+}
+
+/// Load the rows of `query`, built with `diesel::sql_query` and any number of
+/// `.bind::<Type, _>(value)` calls, into a struct deriving `QueryableByName`, e.g. for a query
+/// the DSL cannot express.
+///
+/// Unlike `load_async`, the query always runs on the primary of a `SplitPool`, as raw SQL may
+/// write even when it returns rows.
+#[cfg(feature = "tokio-rt-threaded")]
+pub async fn sql_query_async<U, Q, Conn, AsyncConn>(
+    query: Q,
+    asc: &AsyncConn,
+) -> AsyncResult<Vec<U>>
+where
+    U: 'static + Send,
+    Q: RunQueryDsl<Conn> + LoadQuery<Conn, U> + Send,
+    Conn: 'static + Connection,
+    AsyncConn: AsyncConnection<Conn>,
+{
+    asc.run(move |conn| query.load(conn)).await
+}
+
+/// Execute `query`, built with `diesel::sql_query` and any number of `.bind::<Type, _>(value)`
+/// calls, returning the number of affected rows.
+#[cfg(not(feature = "tokio-rt-threaded"))]
+pub async fn sql_execute_async<Q, Conn, AsyncConn>(query: Q, asc: &AsyncConn) -> AsyncResult<usize>
+where
+    Q: 'static + ExecuteDsl<Conn> + Send,
+    Conn: 'static + Connection,
+    AsyncConn: AsyncConnection<Conn>,
+{
+    asc.run(move |conn| ExecuteDsl::execute(query, conn)).await
+}
+
+/// Execute `query`, built with `diesel::sql_query` and any number of `.bind::<Type, _>(value)`
+/// calls, returning the number of affected rows.
+#[cfg(feature = "tokio-rt-threaded")]
+pub async fn sql_execute_async<Q, Conn, AsyncConn>(query: Q, asc: &AsyncConn) -> AsyncResult<usize>
+where
+    Q: ExecuteDsl<Conn> + Send,
+    Conn: 'static + Connection,
+    AsyncConn: AsyncConnection<Conn>,
+{
+    asc.run(move |conn| ExecuteDsl::execute(query, conn)).await
+}
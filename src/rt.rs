@@ -0,0 +1,155 @@
+use crate::{AsyncError, AsyncResult};
+use futures::future::Future;
+use std::time::Duration;
+#[cfg(feature = "metrics")]
+use std::time::Instant;
+
+// The few runtime facilities the crate needs, backed by tokio or, with the `async-std`
+// feature, by async-std
+
+#[cfg(not(feature = "async-std"))]
+mod imp {
+    use futures::future::Future;
+    use std::{panic, time::Duration};
+    use tokio::{task, time};
+
+    pub(crate) async fn spawn_blocking<F, R>(f: F) -> R
+    where
+        F: 'static + FnOnce() -> R + Send,
+        R: 'static + Send,
+    {
+        task::spawn_blocking(f).await.unwrap_or_else(resume_panic)
+    }
+
+    pub(crate) fn spawn<Fut>(fut: Fut) -> impl Future<Output = Fut::Output>
+    where
+        Fut: 'static + Future + Send,
+        Fut::Output: 'static + Send,
+    {
+        let handle = task::spawn(fut);
+        async move { handle.await.unwrap_or_else(resume_panic) }
+    }
+
+    pub(crate) async fn timeout<Fut: Future>(timeout: Duration, fut: Fut) -> Option<Fut::Output> {
+        time::timeout(timeout, fut).await.ok()
+    }
+
+    pub(crate) async fn sleep(duration: Duration) {
+        time::delay_for(duration).await
+    }
+
+    // Resume the panic of a spawned task on the awaiting task, keeping its original payload
+    fn resume_panic<R>(err: task::JoinError) -> R {
+        match err.try_into_panic() {
+            Ok(payload) => panic::resume_unwind(payload),
+            Err(err) => panic!("task has failed: {}", err),
+        }
+    }
+}
+
+#[cfg(feature = "async-std")]
+mod imp {
+    use async_std::{future, task};
+    use futures::future::Future;
+    use std::time::Duration;
+
+    pub(crate) async fn spawn_blocking<F, R>(f: F) -> R
+    where
+        F: 'static + FnOnce() -> R + Send,
+        R: 'static + Send,
+    {
+        task::spawn_blocking(f).await
+    }
+
+    pub(crate) fn spawn<Fut>(fut: Fut) -> impl Future<Output = Fut::Output>
+    where
+        Fut: 'static + Future + Send,
+        Fut::Output: 'static + Send,
+    {
+        task::spawn(fut)
+    }
+
+    pub(crate) async fn timeout<Fut: Future>(timeout: Duration, fut: Fut) -> Option<Fut::Output> {
+        future::timeout(timeout, fut).await.ok()
+    }
+
+    pub(crate) async fn sleep(duration: Duration) {
+        task::sleep(duration).await
+    }
+}
+
+pub(crate) use imp::sleep;
+
+// Run `fut` as a task of its own, which keeps running if the returned future is dropped. With
+// the `tracing` feature, the task runs in the current span.
+pub(crate) fn spawn<Fut>(fut: Fut) -> impl Future<Output = Fut::Output>
+where
+    Fut: 'static + Future + Send,
+    Fut::Output: 'static + Send,
+{
+    #[cfg(feature = "tracing")]
+    let fut = tracing::Instrument::instrument(fut, tracing::Span::current());
+
+    imp::spawn(fut)
+}
+
+// Run `f` on the blocking thread pool, resuming a panic of `f` on the awaiting task, see
+// `instrument_blocking`
+pub(crate) async fn spawn_blocking<F, R>(f: F) -> R
+where
+    F: 'static + FnOnce() -> R + Send,
+    R: 'static + Send,
+{
+    imp::spawn_blocking(instrument_blocking(f)).await
+}
+
+// Like `spawn_blocking`, but hands `f` as is to the blocking thread pool, for blocking work
+// that has already been instrumented
+pub(crate) async fn spawn_blocking_uninstrumented<F, R>(f: F) -> R
+where
+    F: 'static + FnOnce() -> R + Send,
+    R: 'static + Send,
+{
+    imp::spawn_blocking(f).await
+}
+
+// Prepare `f` to be run away from the awaiting task. With the `tracing` feature, `f` runs in
+// the current span, so the spans and events of the blocking work are correlated with the
+// caller. With the `metrics` feature, the time `f` spent queued before it started is recorded.
+pub(crate) fn instrument_blocking<F, R>(f: F) -> impl FnOnce() -> R + Send
+where
+    F: 'static + FnOnce() -> R + Send,
+    R: 'static + Send,
+{
+    #[cfg(feature = "tracing")]
+    let f = {
+        let span = tracing::Span::current();
+        move || span.in_scope(f)
+    };
+
+    #[cfg(feature = "metrics")]
+    let f = {
+        let queued = Instant::now();
+        move || {
+            metrics::histogram!("tokio_diesel_blocking_queue_seconds")
+                .record(queued.elapsed().as_secs_f64());
+            f()
+        }
+    };
+
+    f
+}
+
+// Run `f` on the current thread, blocking it, while the other tasks of the worker are handed
+// off to another one
+#[cfg(feature = "tokio-rt-threaded")]
+pub(crate) fn block_in_place<R>(f: impl FnOnce() -> R) -> R {
+    tokio::task::block_in_place(f)
+}
+
+// Wait for `fut` for at most `timeout`
+pub(crate) async fn timeout<Fut: Future>(timeout: Duration, fut: Fut) -> AsyncResult<Fut::Output> {
+    imp::timeout(timeout, fut)
+        .await
+        .ok_or(AsyncError::Timeout(timeout))
+}
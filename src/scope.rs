@@ -0,0 +1,35 @@
+use futures::future::Future;
+use std::{any::Any, sync::Arc};
+
+tokio::task_local! {
+    // The pools set by the `AsyncPool::scoped` calls around the current task, innermost last
+    static SCOPED: Vec<Arc<dyn Any + Send + Sync>>;
+}
+
+// Run `fut` with `pool` set for the pools of its type, on top of those already set
+pub(crate) async fn scope<P, Fut>(pool: P, fut: Fut) -> Fut::Output
+where
+    P: 'static + Send + Sync,
+    Fut: Future,
+{
+    let mut scoped = SCOPED.try_with(Clone::clone).unwrap_or_default();
+    scoped.push(Arc::new(pool));
+    SCOPED.scope(scoped, fut).await
+}
+
+// The innermost pool of type `P` set around the current task, if any
+pub(crate) fn current<P>() -> Option<P>
+where
+    P: 'static + Clone,
+{
+    SCOPED
+        .try_with(|scoped| {
+            scoped
+                .iter()
+                .rev()
+                .find_map(|pool| pool.downcast_ref::<P>())
+                .cloned()
+        })
+        .ok()
+        .flatten()
+}
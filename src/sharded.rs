@@ -0,0 +1,79 @@
+use crate::{AsyncConnection, AsyncError, AsyncResult};
+use diesel::{result::QueryResult, Connection};
+use std::{collections::HashMap, fmt, hash::Hash, sync::Arc};
+
+/// Pools to several databases, e.g. with tenants sharded across them, along with a routing
+/// function picking the shard of a key.
+///
+/// Every operation runs on a single shard: `shard` returns the pool of a key, on which queries
+/// and transactions run as usual, and `run_on_shard` is a shorthand for `run` on it.
+pub struct ShardedPool<K, P> {
+    shards: Arc<HashMap<K, P>>,
+    route: Arc<dyn Fn(&K) -> K + Send + Sync>,
+}
+
+impl<K, P> ShardedPool<K, P>
+where
+    K: Eq + Hash,
+{
+    /// Route every key to the pool of `shards` whose key `route` returns for it, e.g.
+    /// `|tenant| tenant % 2` with shards `0` and `1`.
+    pub fn new<F>(shards: HashMap<K, P>, route: F) -> Self
+    where
+        F: 'static + Fn(&K) -> K + Send + Sync,
+    {
+        ShardedPool {
+            shards: Arc::new(shards),
+            route: Arc::new(route),
+        }
+    }
+
+    /// The pool of the shard `key` is routed to. Fails with `AsyncError::NoShard` if there is
+    /// no pool for that shard.
+    pub fn shard(&self, key: &K) -> AsyncResult<&P> {
+        self.shards
+            .get(&(self.route)(key))
+            .ok_or(AsyncError::NoShard)
+    }
+
+    /// Run `f` on a connection of the shard `key` is routed to.
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    pub async fn run_on_shard<Conn, R, Func>(&self, key: &K, f: Func) -> AsyncResult<R>
+    where
+        Conn: 'static + Connection,
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+        P: AsyncConnection<Conn>,
+    {
+        self.shard(key)?.run(f).await
+    }
+
+    /// Run `f` on a connection of the shard `key` is routed to.
+    #[cfg(feature = "tokio-rt-threaded")]
+    pub async fn run_on_shard<Conn, R, Func>(&self, key: &K, f: Func) -> AsyncResult<R>
+    where
+        Conn: 'static + Connection,
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+        P: AsyncConnection<Conn>,
+    {
+        self.shard(key)?.run(f).await
+    }
+}
+
+impl<K, P> Clone for ShardedPool<K, P> {
+    fn clone(&self) -> Self {
+        ShardedPool {
+            shards: self.shards.clone(),
+            route: self.route.clone(),
+        }
+    }
+}
+
+impl<K, P> fmt::Debug for ShardedPool<K, P> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ShardedPool")
+            .field("shards", &self.shards.len())
+            .finish()
+    }
+}
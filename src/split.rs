@@ -0,0 +1,214 @@
+use crate::{AsyncConnection, AsyncResult, AsyncSimpleConnection, PoolState};
+use async_trait::async_trait;
+use diesel::{result::QueryResult, Connection};
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// A primary pool for writes along with replica pools for reads.
+///
+/// The methods of `AsyncRunQueryDsl` loading rows, like `load_async`, `get_result_async` or
+/// `count_async`, as well as `AsyncConnection::run_read`, run on the replicas in turn; every
+/// other operation, including all transactions, runs on the primary.
+///
+/// The routing goes by method, not by statement: an `INSERT ... RETURNING` run through
+/// `get_result_async` would be sent to a replica. Statements with a `RETURNING` clause must
+/// go through `execute_returning_async`, `update_returning_async` or
+/// `delete_returning_async`, which run on the primary.
+///
+/// A read that must see the latest writes, e.g. right after making them, can be sent to the
+/// primary by running it on `primary()` instead.
+pub struct SplitPool<P> {
+    primary: P,
+    replicas: Vec<P>,
+    next: Arc<AtomicUsize>,
+}
+
+impl<P> SplitPool<P> {
+    /// Route writes to `primary` and reads to `replicas`. Without replicas every operation
+    /// runs on the primary.
+    pub fn new(primary: P, replicas: Vec<P>) -> Self {
+        SplitPool {
+            primary,
+            replicas,
+            next: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// The primary pool.
+    pub fn primary(&self) -> &P {
+        &self.primary
+    }
+
+    /// The replica the next read runs on, or the primary if there are no replicas.
+    pub fn replica(&self) -> &P {
+        if self.replicas.is_empty() {
+            return &self.primary;
+        }
+        let next = self.next.fetch_add(1, Ordering::Relaxed);
+        &self.replicas[next % self.replicas.len()]
+    }
+}
+
+impl<P> Clone for SplitPool<P>
+where
+    P: Clone,
+{
+    fn clone(&self) -> Self {
+        SplitPool {
+            primary: self.primary.clone(),
+            replicas: self.replicas.clone(),
+            next: self.next.clone(),
+        }
+    }
+}
+
+impl<P> fmt::Debug for SplitPool<P> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SplitPool")
+            .field("replicas", &self.replicas.len())
+            .finish()
+    }
+}
+
+#[async_trait]
+impl<Conn, P> AsyncSimpleConnection<Conn> for SplitPool<P>
+where
+    Conn: 'static + Connection,
+    P: AsyncSimpleConnection<Conn> + Send + Sync,
+{
+    #[inline]
+    async fn batch_execute_async(&self, query: &str) -> AsyncResult<()> {
+        self.primary.batch_execute_async(query).await
+    }
+}
+
+#[async_trait]
+impl<Conn, P> AsyncConnection<Conn> for SplitPool<P>
+where
+    Conn: 'static + Connection,
+    P: AsyncConnection<Conn> + Send + Sync,
+{
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn run<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        self.primary.run(f).await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn run<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        self.primary.run(f).await
+    }
+
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn transaction<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        self.primary.transaction(f).await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn transaction<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        self.primary.transaction(f).await
+    }
+
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn run_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        self.primary.run_mut(f).await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn run_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        self.primary.run_mut(f).await
+    }
+
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn transaction_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        self.primary.transaction_mut(f).await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn transaction_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        self.primary.transaction_mut(f).await
+    }
+
+    #[inline]
+    async fn run_timeout<R, Func>(&self, timeout: Duration, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        self.primary.run_timeout(timeout, f).await
+    }
+
+    #[inline]
+    async fn transaction_timeout<R, Func>(&self, timeout: Duration, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        self.primary.transaction_timeout(timeout, f).await
+    }
+
+    #[inline]
+    async fn pool_state_async(&self) -> AsyncResult<PoolState> {
+        self.primary.pool_state_async().await
+    }
+
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn run_read<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        self.replica().run_read(f).await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn run_read<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        self.replica().run_read(f).await
+    }
+}
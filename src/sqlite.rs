@@ -0,0 +1,144 @@
+use crate::{rt, AsyncConnection, AsyncError, AsyncPoolBuilder, AsyncResult, BlockingExecutor};
+use diesel::{
+    dsl::sql,
+    query_dsl::methods::ExecuteDsl,
+    result::{DatabaseErrorKind, Error, QueryResult},
+    sql_types::BigInt,
+    sqlite::SqliteConnection,
+    RunQueryDsl,
+};
+use std::{sync::Arc, time::Duration};
+
+impl<E> AsyncPoolBuilder<SqliteConnection, E>
+where
+    E: BlockingExecutor,
+{
+    /// Let every connection wait up to `timeout` for a lock held by another connection, through
+    /// `PRAGMA busy_timeout`, instead of failing right away with `SQLITE_BUSY`.
+    pub fn busy_timeout(self, timeout: Duration) -> Self {
+        let statement = format!("PRAGMA busy_timeout = {}", timeout.as_millis());
+        self.on_acquire(&[&statement])
+    }
+}
+
+// SQLite reports `SQLITE_BUSY` and `SQLITE_LOCKED` through these messages, diesel 1.x has no
+// error kind for them
+fn is_busy(err: &AsyncError) -> bool {
+    match *err.innermost() {
+        AsyncError::Error(Error::DatabaseError(DatabaseErrorKind::__Unknown, ref info)) => {
+            let message = info.message();
+            message == "database is locked" || message == "database table is locked"
+        }
+        _ => false,
+    }
+}
+
+/// Like `AsyncConnection::transaction_retry`, but retries when SQLite reports that the
+/// database is locked by another connection (`SQLITE_BUSY`), e.g. when a `busy_timeout`
+/// elapsed or a read transaction could not be upgraded to a write one.
+///
+/// Pools of size 1 serialize all writes and never see this error; it only occurs with
+/// several connections to the same database file.
+#[cfg(not(feature = "tokio-rt-threaded"))]
+pub async fn transaction_retry_busy<R, Func, AsyncConn>(
+    asc: &AsyncConn,
+    retries: usize,
+    f: Func,
+) -> AsyncResult<R>
+where
+    R: 'static + Send,
+    Func: 'static + Fn(&SqliteConnection) -> QueryResult<R> + Send + Sync,
+    AsyncConn: AsyncConnection<SqliteConnection>,
+{
+    let f = Arc::new(f);
+    let mut backoff = Duration::from_millis(10);
+    let mut attempt = 0;
+    loop {
+        let f = f.clone();
+        match asc.transaction(move |conn| f(conn)).await {
+            Err(ref err) if attempt < retries && is_busy(err) => {
+                attempt += 1;
+                rt::sleep(backoff).await;
+                backoff *= 2;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Like `AsyncConnection::transaction_retry`, but retries when SQLite reports that the
+/// database is locked by another connection (`SQLITE_BUSY`), e.g. when a `busy_timeout`
+/// elapsed or a read transaction could not be upgraded to a write one.
+///
+/// Pools of size 1 serialize all writes and never see this error; it only occurs with
+/// several connections to the same database file.
+#[cfg(feature = "tokio-rt-threaded")]
+pub async fn transaction_retry_busy<R, Func, AsyncConn>(
+    asc: &AsyncConn,
+    retries: usize,
+    f: Func,
+) -> AsyncResult<R>
+where
+    R: 'static + Send,
+    Func: Fn(&SqliteConnection) -> QueryResult<R> + Send + Sync,
+    AsyncConn: AsyncConnection<SqliteConnection>,
+{
+    let f = Arc::new(f);
+    let mut backoff = Duration::from_millis(10);
+    let mut attempt = 0;
+    loop {
+        let f = f.clone();
+        match asc.transaction(move |conn| f(conn)).await {
+            Err(ref err) if attempt < retries && is_busy(err) => {
+                attempt += 1;
+                rt::sleep(backoff).await;
+                backoff *= 2;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Run `insert` and return the rowid of the row it inserted, read with `last_insert_rowid()`,
+/// e.g. the generated primary key on SQLite versions without `RETURNING`.
+///
+/// `last_insert_rowid()` is tracked per connection, so both statements run on the same
+/// connection in a single operation, and inserts on other connections of the pool do not
+/// affect the result. If several rows are inserted, the rowid of the last one is returned.
+#[cfg(not(feature = "tokio-rt-threaded"))]
+pub async fn insert_and_get_rowid_async<I, AsyncConn>(
+    insert: I,
+    asc: &AsyncConn,
+) -> AsyncResult<i64>
+where
+    I: 'static + ExecuteDsl<SqliteConnection> + Send,
+    AsyncConn: AsyncConnection<SqliteConnection>,
+{
+    asc.run(move |conn| {
+        ExecuteDsl::execute(insert, conn)?;
+        diesel::select(sql::<BigInt>("last_insert_rowid()")).get_result(conn)
+    })
+    .await
+}
+
+/// Run `insert` and return the rowid of the row it inserted, read with `last_insert_rowid()`,
+/// e.g. the generated primary key on SQLite versions without `RETURNING`.
+///
+/// `last_insert_rowid()` is tracked per connection, so both statements run on the same
+/// connection in a single operation, and inserts on other connections of the pool do not
+/// affect the result. If several rows are inserted, the rowid of the last one is returned.
+#[cfg(feature = "tokio-rt-threaded")]
+pub async fn insert_and_get_rowid_async<I, AsyncConn>(
+    insert: I,
+    asc: &AsyncConn,
+) -> AsyncResult<i64>
+where
+    I: ExecuteDsl<SqliteConnection> + Send,
+    AsyncConn: AsyncConnection<SqliteConnection>,
+{
+    asc.run(move |conn| {
+        ExecuteDsl::execute(insert, conn)?;
+        diesel::select(sql::<BigInt>("last_insert_rowid()")).get_result(conn)
+    })
+    .await
+}
@@ -0,0 +1,201 @@
+use crate::{rt, AsyncError, AsyncResult};
+use diesel::{
+    backend::Backend,
+    deserialize::{self, FromSqlRow, Queryable, QueryableByName},
+    result::{Error, QueryResult},
+    row::{NamedRow, Row},
+};
+use futures::{future::Future, stream::Stream};
+use std::{
+    any::Any,
+    cell::{Cell, RefCell},
+    marker::PhantomData,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll},
+};
+use tokio::sync::mpsc;
+
+/// A stream of rows loaded on the blocking thread pool.
+///
+/// Rows are fed through a bounded channel, so a slow consumer applies backpressure to the
+/// blocking work loading them. Dropping the stream stops any further rows from being loaded.
+///
+/// A row that fails to deserialize is yielded as an `Err` after the rows before it, and ends
+/// the stream: the rows after it are discarded.
+pub struct LoadStream<U> {
+    rx: mpsc::Receiver<AsyncResult<U>>,
+}
+
+impl<U> LoadStream<U>
+where
+    U: 'static + Send,
+{
+    pub(crate) fn spawn<F, Fut>(capacity: usize, load: F) -> Self
+    where
+        F: 'static + FnOnce(mpsc::Sender<AsyncResult<U>>) -> Fut + Send,
+        Fut: Future<Output = AsyncResult<()>> + Send,
+    {
+        let (mut tx, stream) = Self::channel(capacity);
+
+        // Detach the forwarding task, it ends once every row is sent or the stream is dropped
+        drop(rt::spawn(async move {
+            if let Err(err) = load(tx.clone()).await {
+                let _ = tx.send(Err(err)).await;
+            }
+        }));
+
+        stream
+    }
+
+    // A stream handing out the rows sent through the returned sender
+    pub(crate) fn channel(capacity: usize) -> (mpsc::Sender<AsyncResult<U>>, Self) {
+        let (tx, rx) = mpsc::channel(capacity);
+        (tx, LoadStream { rx })
+    }
+}
+
+impl<U> Stream for LoadStream<U> {
+    type Item = AsyncResult<U>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+/// A row loaded by `load_stream_async` or `load_with_capacity_async`. Deserializing it hands
+/// the row over right away, to the stream or to a `Vec` sized up front, so diesel never
+/// collects the rows itself: this only stands in for the row in the `Vec` diesel loads into,
+/// which holds nothing.
+#[derive(Debug)]
+pub struct Decoded<U>(PhantomData<fn() -> U>);
+
+impl<U> Decoded<U>
+where
+    U: 'static + Send,
+{
+    // Run `load` on this thread, sending the rows it deserializes through `tx` as it steps
+    // through the result set, blocking while the stream is full. A row that fails to
+    // deserialize is sent as the error ending the stream and aborts the load, as does a
+    // dropped stream; the error `load` then returns is not sent.
+    pub(crate) fn forward<F>(mut tx: mpsc::Sender<AsyncResult<U>>, load: F) -> QueryResult<()>
+    where
+        F: FnOnce() -> QueryResult<Vec<Decoded<U>>>,
+    {
+        let ended = Rc::new(Cell::new(false));
+        let ended_ = ended.clone();
+        let push_row: PushRow<U> = Box::new(move |row| {
+            let failed = row.is_err();
+            let row = row.map_err(|err| AsyncError::Error(Error::DeserializationError(err)));
+            if futures::executor::block_on(tx.send(row)).is_err() || failed {
+                // The stream was dropped, or ends with the row that failed
+                ended_.set(true);
+                return Err("the stream of rows ended".into());
+            }
+            Ok(())
+        });
+
+        match push_rows(push_row, load) {
+            Err(_) if ended.get() => Ok(()),
+            result => result.map(drop),
+        }
+    }
+
+    // Run `load` on this thread, pushing the rows it deserializes onto a `Vec` with room for
+    // `capacity` rows as it steps through the result set
+    pub(crate) fn collect<F>(capacity: usize, load: F) -> QueryResult<Vec<U>>
+    where
+        F: FnOnce() -> QueryResult<Vec<Decoded<U>>>,
+    {
+        let rows = Rc::new(RefCell::new(Vec::with_capacity(capacity)));
+        let rows_ = rows.clone();
+        let push_row: PushRow<U> = Box::new(move |row| {
+            rows_.borrow_mut().push(row?);
+            Ok(())
+        });
+
+        push_rows(push_row, load)?;
+        Ok(rows.take())
+    }
+
+    // Hand a row of the load running on this thread over
+    fn push(row: deserialize::Result<U>) -> deserialize::Result<()> {
+        PUSH_ROW.with(|current| {
+            let mut current = current.borrow_mut();
+            let push_row = current
+                .as_mut()
+                .and_then(|push_row| push_row.downcast_mut::<PushRow<U>>())
+                .expect("a `Decoded` row was loaded outside of the methods loading it");
+            push_row(row)
+        })
+    }
+}
+
+// Takes every row of a load as it is deserialized; an error aborts the load
+type PushRow<U> = Box<dyn FnMut(deserialize::Result<U>) -> deserialize::Result<()>>;
+
+thread_local! {
+    // The `PushRow` of the load running on this thread, if any
+    static PUSH_ROW: RefCell<Option<Box<dyn Any>>> = const { RefCell::new(None) };
+}
+
+// Run `load` with `push_row` taking the rows it deserializes on this thread
+fn push_rows<U, R>(push_row: PushRow<U>, load: impl FnOnce() -> R) -> R
+where
+    U: 'static,
+{
+    let _restore = Restore(PUSH_ROW.with(|current| current.replace(Some(Box::new(push_row)))));
+    load()
+}
+
+// Puts back the `PushRow` of an enclosing load once the current one is done, even if it
+// panicked
+struct Restore(Option<Box<dyn Any>>);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        PUSH_ROW.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+// The fields of a row of `Decoded`, which hands the row over once it is built
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct DecodedRow<U>(PhantomData<fn() -> U>);
+
+impl<ST, DB, U> FromSqlRow<ST, DB> for DecodedRow<U>
+where
+    DB: Backend,
+    U: 'static + Send + Queryable<ST, DB>,
+{
+    const FIELDS_NEEDED: usize = <U::Row as FromSqlRow<ST, DB>>::FIELDS_NEEDED;
+
+    fn build_from_row<T: Row<DB>>(row: &mut T) -> deserialize::Result<Self> {
+        Decoded::push(<U::Row as FromSqlRow<ST, DB>>::build_from_row(row).map(U::build))?;
+        Ok(DecodedRow(PhantomData))
+    }
+}
+
+impl<ST, DB, U> Queryable<ST, DB> for Decoded<U>
+where
+    DB: Backend,
+    U: 'static + Send + Queryable<ST, DB>,
+{
+    type Row = DecodedRow<U>;
+
+    fn build(_row: Self::Row) -> Self {
+        Decoded(PhantomData)
+    }
+}
+
+impl<DB, U> QueryableByName<DB> for Decoded<U>
+where
+    DB: Backend,
+    U: 'static + Send + QueryableByName<DB>,
+{
+    fn build<R: NamedRow<DB>>(row: &R) -> deserialize::Result<Self> {
+        Decoded::push(U::build(row))?;
+        Ok(Decoded(PhantomData))
+    }
+}
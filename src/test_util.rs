@@ -0,0 +1,179 @@
+use crate::{
+    rt, AsyncConnection, AsyncConnectionWrapper, AsyncError, AsyncResult, AsyncSimpleConnection,
+    PoolState,
+};
+use async_trait::async_trait;
+use diesel::{result::QueryResult, Connection};
+use std::time::Duration;
+
+/// A single connection inside a transaction that is never committed, for integration tests
+/// that should leave the database as they found it. This mirrors diesel's
+/// `Connection::test_transaction`.
+///
+/// Every operation runs on the same connection, so a test sees its own writes and nothing of
+/// other tests, and everything is rolled back once the last clone is dropped. Nested
+/// `transaction` calls become savepoints. This pins one database connection per test for as
+/// long as the test runs.
+///
+/// ```no_run
+/// # use diesel::{pg::PgConnection, RunQueryDsl};
+/// # use tokio_diesel::{AsyncConnection, TestTransaction};
+/// # async fn test() -> tokio_diesel::AsyncResult<()> {
+/// let conn = TestTransaction::<PgConnection>::begin("postgres://localhost/app_test").await?;
+/// conn.run(|conn| diesel::sql_query("INSERT INTO users (name) VALUES ('a')").execute(conn))
+///     .await?;
+/// // rolled back when `conn` is dropped, other tests never see the row
+/// # Ok(())
+/// # }
+/// ```
+pub struct TestTransaction<Conn> {
+    conn: AsyncConnectionWrapper<Conn>,
+}
+
+impl<Conn> TestTransaction<Conn>
+where
+    Conn: 'static + Connection,
+{
+    /// Establish a connection to `database_url` and begin the test transaction on it.
+    pub async fn begin(database_url: &str) -> AsyncResult<Self> {
+        let database_url = database_url.to_string();
+        rt::spawn_blocking(move || {
+            let conn = Conn::establish(&database_url)
+                .map_err(|err| AsyncError::Checkout(Box::new(err)))?;
+            Self::from_connection(conn)
+        })
+        .await
+    }
+
+    /// Begin the test transaction on an existing connection.
+    pub fn from_connection(conn: Conn) -> AsyncResult<Self> {
+        conn.begin_test_transaction()?;
+        Ok(TestTransaction {
+            conn: AsyncConnectionWrapper::new(conn),
+        })
+    }
+}
+
+impl<Conn> Clone for TestTransaction<Conn> {
+    fn clone(&self) -> Self {
+        TestTransaction {
+            conn: self.conn.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl<Conn> AsyncSimpleConnection<Conn> for TestTransaction<Conn>
+where
+    Conn: 'static + Connection,
+{
+    #[inline]
+    async fn batch_execute_async(&self, query: &str) -> AsyncResult<()> {
+        self.conn.batch_execute_async(query).await
+    }
+}
+
+#[async_trait]
+impl<Conn> AsyncConnection<Conn> for TestTransaction<Conn>
+where
+    Conn: 'static + Connection,
+{
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn run<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        self.conn.run(f).await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn run<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        self.conn.run(f).await
+    }
+
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn transaction<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        self.conn.transaction(f).await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn transaction<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        self.conn.transaction(f).await
+    }
+
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn run_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        self.conn.run_mut(f).await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn run_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        self.conn.run_mut(f).await
+    }
+
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn transaction_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        self.conn.transaction_mut(f).await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn transaction_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        self.conn.transaction_mut(f).await
+    }
+
+    #[inline]
+    async fn run_timeout<R, Func>(&self, timeout: Duration, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        self.conn.run_timeout(timeout, f).await
+    }
+
+    #[inline]
+    async fn transaction_timeout<R, Func>(&self, timeout: Duration, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        self.conn.transaction_timeout(timeout, f).await
+    }
+
+    #[inline]
+    async fn pool_state_async(&self) -> AsyncResult<PoolState> {
+        self.conn.pool_state_async().await
+    }
+}
@@ -0,0 +1,176 @@
+#[cfg(feature = "tracing")]
+use crate::rt;
+use crate::{AsyncError, AsyncResult};
+#[cfg(feature = "tracing")]
+use futures::channel::oneshot;
+use std::time::{Duration, Instant};
+
+// Span covering a single blocking database operation. Without the `tracing` feature
+// every method is a no-op.
+#[derive(Clone)]
+pub(crate) struct OpSpan {
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+}
+
+#[cfg(feature = "tracing")]
+impl OpSpan {
+    pub(crate) fn new(op: &'static str) -> Self {
+        OpSpan {
+            span: tracing::info_span!(
+                "tokio_diesel.run",
+                op,
+                checkout = tracing::field::Empty,
+                checkout_ms = tracing::field::Empty,
+                query_ms = tracing::field::Empty,
+            ),
+        }
+    }
+
+    pub(crate) fn in_scope<R>(&self, f: impl FnOnce() -> R) -> R {
+        self.span.in_scope(f)
+    }
+
+    pub(crate) fn checked_out(&self, start: Instant) {
+        self.span.record("checkout", true);
+        self.span.record("checkout_ms", elapsed_ms(start));
+    }
+
+    pub(crate) fn checkout_failed(&self, start: Instant, err: AsyncError) -> AsyncError {
+        self.span.record("checkout", false);
+        self.span.record("checkout_ms", elapsed_ms(start));
+        self.span
+            .in_scope(|| tracing::error!(error = %err, "failed to checkout a connection"));
+        err
+    }
+
+    pub(crate) fn finished<R>(&self, start: Instant, result: AsyncResult<R>) -> AsyncResult<R> {
+        self.span.record("query_ms", elapsed_ms(start));
+        if let Err(ref err) = result {
+            self.span
+                .in_scope(|| tracing::error!(error = %err, "query failed"));
+        }
+        result
+    }
+}
+
+// Run the work of `op` on a connection, warning if it takes longer than `threshold`
+#[cfg(feature = "tracing")]
+pub(crate) fn slow_query<R>(
+    threshold: Option<Duration>,
+    op: &'static str,
+    f: impl FnOnce() -> R,
+) -> R {
+    let threshold = match threshold {
+        Some(threshold) => threshold,
+        None => return f(),
+    };
+
+    let start = Instant::now();
+    let result = f();
+    let elapsed = start.elapsed();
+    if elapsed > threshold {
+        tracing::warn!(
+            op,
+            query_ms = elapsed.as_secs_f64() * 1000.0,
+            threshold_ms = threshold.as_secs_f64() * 1000.0,
+            "slow query"
+        );
+    }
+    result
+}
+
+// Watches the connection of an operation, warning once it has been held for longer than the
+// leak detection threshold. Created on the async side, where the timer can be spawned. Without
+// the `tracing` feature nothing is watched.
+pub(crate) struct LeakWatch {
+    #[cfg(feature = "tracing")]
+    checked_out: oneshot::Sender<()>,
+    #[cfg(feature = "tracing")]
+    returned: oneshot::Sender<()>,
+}
+
+#[cfg(feature = "tracing")]
+impl LeakWatch {
+    pub(crate) fn new(threshold: Option<Duration>, op: &'static str) -> Option<Self> {
+        let threshold = threshold?;
+        let (checked_out, checked_out_rx) = oneshot::channel();
+        let (returned, returned_rx) = oneshot::channel::<()>();
+
+        // The timer stops once the watch is dropped with the work, the task is detached
+        drop(rt::spawn(async move {
+            if checked_out_rx.await.is_err() {
+                return;
+            }
+            if rt::timeout(threshold, returned_rx).await.is_err() {
+                tracing::warn!(
+                    op,
+                    threshold_ms = threshold.as_secs_f64() * 1000.0,
+                    "connection held longer than the leak detection threshold"
+                );
+            }
+        }));
+        Some(LeakWatch {
+            checked_out,
+            returned,
+        })
+    }
+
+    // Start the timer, which stops once the returned guard is dropped
+    pub(crate) fn checked_out(self) -> oneshot::Sender<()> {
+        let _ = self.checked_out.send(());
+        self.returned
+    }
+}
+
+#[cfg(feature = "tracing")]
+fn elapsed_ms(start: Instant) -> f64 {
+    start.elapsed().as_secs_f64() * 1000.0
+}
+
+#[cfg(not(feature = "tracing"))]
+impl OpSpan {
+    #[inline]
+    pub(crate) fn new(_op: &'static str) -> Self {
+        OpSpan {}
+    }
+
+    #[inline]
+    pub(crate) fn in_scope<R>(&self, f: impl FnOnce() -> R) -> R {
+        f()
+    }
+
+    #[inline]
+    pub(crate) fn checked_out(&self, _start: Instant) {}
+
+    #[inline]
+    pub(crate) fn checkout_failed(&self, _start: Instant, err: AsyncError) -> AsyncError {
+        err
+    }
+
+    #[inline]
+    pub(crate) fn finished<R>(&self, _start: Instant, result: AsyncResult<R>) -> AsyncResult<R> {
+        result
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+impl LeakWatch {
+    #[inline]
+    pub(crate) fn new(threshold: Option<Duration>, _op: &'static str) -> Option<Self> {
+        threshold.map(|_| LeakWatch {})
+    }
+
+    #[inline]
+    pub(crate) fn checked_out(self) {}
+}
+
+#[cfg(not(feature = "tracing"))]
+#[inline]
+pub(crate) fn slow_query<R>(
+    _threshold: Option<Duration>,
+    _op: &'static str,
+    f: impl FnOnce() -> R,
+) -> R {
+    f()
+}
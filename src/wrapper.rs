@@ -0,0 +1,235 @@
+use crate::{
+    rollback_to, rt, trace::OpSpan, transaction_mut, AsyncConnection, AsyncError, AsyncResult,
+    AsyncSimpleConnection, PoolState,
+};
+use async_trait::async_trait;
+use diesel::{connection::TransactionManager, result::QueryResult, Connection};
+use std::{
+    sync::{Arc, Mutex, MutexGuard, TryLockError},
+    time::{Duration, Instant},
+};
+#[cfg(feature = "tokio-rt-threaded")]
+use tokio::task;
+
+/// A single connection usable through the async traits without a pool.
+///
+/// Access to the connection is serialized by a mutex: concurrent operations wait for each
+/// other on the blocking thread pool. This suits short-lived tools and scripts that do not
+/// need a pool.
+pub struct AsyncConnectionWrapper<Conn> {
+    conn: Arc<Mutex<Conn>>,
+    // The transaction depth of the connection when it was wrapped, e.g. 1 for the transaction
+    // of a `TestTransaction`
+    depth: u32,
+}
+
+impl<Conn> AsyncConnectionWrapper<Conn>
+where
+    Conn: 'static + Connection,
+{
+    /// Wrap `conn`. A transaction already open on it, e.g. a test transaction, is left open
+    /// for the operations to run in.
+    ///
+    /// If an operation panics, the transactions it left open on the connection are rolled back
+    /// before the next operation runs. Should that fail, the operation fails with the error of
+    /// the rollback instead of running within them.
+    pub fn new(conn: Conn) -> Self {
+        let depth = conn.transaction_manager().get_transaction_depth();
+        AsyncConnectionWrapper {
+            conn: Arc::new(Mutex::new(conn)),
+            depth,
+        }
+    }
+
+    // Lock the connection. The lock stays poisoned once an operation panicked, and every
+    // following lock rolls back what a panic may have left open, which is a no-op once done.
+    fn lock(&self) -> QueryResult<MutexGuard<'_, Conn>> {
+        let conn = match self.conn.lock() {
+            Ok(conn) => return Ok(conn),
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        rollback_to(&*conn, self.depth)?;
+        Ok(conn)
+    }
+
+    // Run `f` with exclusive access to the connection on the current thread
+    fn with_conn<R, Func>(&self, op: &'static str, f: Func) -> AsyncResult<R>
+    where
+        Func: FnOnce(&mut Conn) -> QueryResult<R>,
+    {
+        let span = OpSpan::new(op);
+        span.in_scope(|| {
+            let start = Instant::now();
+            let mut conn = self.lock().map_err(AsyncError::Error)?;
+            span.checked_out(start);
+
+            let start = Instant::now();
+            span.finished(start, f(&mut *conn).map_err(AsyncError::Error))
+        })
+    }
+}
+
+impl<Conn> Clone for AsyncConnectionWrapper<Conn> {
+    fn clone(&self) -> Self {
+        AsyncConnectionWrapper {
+            conn: self.conn.clone(),
+            depth: self.depth,
+        }
+    }
+}
+
+impl<Conn> From<Conn> for AsyncConnectionWrapper<Conn>
+where
+    Conn: 'static + Connection,
+{
+    fn from(conn: Conn) -> Self {
+        AsyncConnectionWrapper::new(conn)
+    }
+}
+
+#[async_trait]
+impl<Conn> AsyncSimpleConnection<Conn> for AsyncConnectionWrapper<Conn>
+where
+    Conn: 'static + Connection,
+{
+    #[inline]
+    async fn batch_execute_async(&self, query: &str) -> AsyncResult<()> {
+        let self_ = self.clone();
+        let query = query.to_string();
+        rt::spawn_blocking(move || {
+            self_.with_conn("batch_execute", |conn| conn.batch_execute(&query))
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl<Conn> AsyncConnection<Conn> for AsyncConnectionWrapper<Conn>
+where
+    Conn: 'static + Connection,
+{
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn run<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        let self_ = self.clone();
+        rt::spawn_blocking(move || self_.with_conn("run", |conn| f(conn))).await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn run<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        task::block_in_place(|| self.with_conn("run", |conn| f(conn)))
+    }
+
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn transaction<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        let self_ = self.clone();
+        rt::spawn_blocking(move || {
+            self_.with_conn("transaction", |conn| conn.transaction(|| f(conn)))
+        })
+        .await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn transaction<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        task::block_in_place(|| self.with_conn("transaction", |conn| conn.transaction(|| f(conn))))
+    }
+
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn run_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        let self_ = self.clone();
+        rt::spawn_blocking(move || self_.with_conn("run_mut", f)).await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn run_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        task::block_in_place(|| self.with_conn("run_mut", f))
+    }
+
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    #[inline]
+    async fn transaction_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        let self_ = self.clone();
+        rt::spawn_blocking(move || {
+            self_.with_conn("transaction_mut", |conn| transaction_mut(conn, f))
+        })
+        .await
+    }
+    #[cfg(feature = "tokio-rt-threaded")]
+    #[inline]
+    async fn transaction_mut<R, Func>(&self, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: FnOnce(&mut Conn) -> QueryResult<R> + Send,
+    {
+        task::block_in_place(|| self.with_conn("transaction_mut", |conn| transaction_mut(conn, f)))
+    }
+
+    #[inline]
+    async fn run_timeout<R, Func>(&self, timeout: Duration, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        let self_ = self.clone();
+        let handle = rt::spawn_blocking(move || self_.with_conn("run_timeout", |conn| f(conn)));
+        rt::timeout(timeout, handle).await?
+    }
+
+    #[inline]
+    async fn transaction_timeout<R, Func>(&self, timeout: Duration, f: Func) -> AsyncResult<R>
+    where
+        R: 'static + Send,
+        Func: 'static + FnOnce(&Conn) -> QueryResult<R> + Send,
+    {
+        let self_ = self.clone();
+        let handle = rt::spawn_blocking(move || {
+            self_.with_conn("transaction_timeout", |conn| conn.transaction(|| f(conn)))
+        });
+        rt::timeout(timeout, handle).await?
+    }
+
+    // The wrapped connection is the only one, idle unless an operation holds its lock; a
+    // poisoned lock is not held by anyone
+    #[inline]
+    async fn pool_state_async(&self) -> AsyncResult<PoolState> {
+        let idle = match self.conn.try_lock() {
+            Ok(_) | Err(TryLockError::Poisoned(_)) => true,
+            Err(TryLockError::WouldBlock) => false,
+        };
+        Ok(PoolState {
+            connections: 1,
+            idle_connections: idle as u32,
+        })
+    }
+}
@@ -0,0 +1,28 @@
+#![cfg(feature = "async-std")]
+
+#[macro_use]
+extern crate diesel;
+
+mod common;
+
+use common::users;
+use diesel::prelude::*;
+use tokio_diesel::*;
+
+#[test]
+fn operations_run_on_the_async_std_runtime() {
+    async_std::task::block_on(async {
+        let pool = common::sqlite_pool();
+
+        diesel::insert_into(users::table)
+            .values((users::id.eq(1), users::name.eq("ada")))
+            .execute_async(&pool)
+            .await
+            .unwrap();
+        let name = pool
+            .transaction(|conn| users::table.select(users::name).first::<String>(conn))
+            .await
+            .unwrap();
+        assert_eq!(name, "ada");
+    })
+}
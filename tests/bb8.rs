@@ -0,0 +1,61 @@
+#![cfg(feature = "bb8")]
+
+#[macro_use]
+extern crate diesel;
+
+mod common;
+
+use bb8_diesel::DieselConnectionManager;
+use common::users;
+use diesel::{prelude::*, sqlite::SqliteConnection};
+use std::time::Duration;
+use tokio_diesel::*;
+
+async fn pool() -> bb8::Pool<DieselConnectionManager<SqliteConnection>> {
+    let pool = bb8::Pool::builder()
+        .max_size(1)
+        .connection_timeout(Duration::from_secs(1))
+        .build(DieselConnectionManager::new(":memory:"))
+        .await
+        .unwrap();
+    pool.batch_execute_async("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT NOT NULL)")
+        .await
+        .unwrap();
+    pool
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn first_async_runs_on_a_bb8_pool() {
+    let pool = pool().await;
+    diesel::insert_into(users::table)
+        .values((users::id.eq(1), users::name.eq("ada")))
+        .execute_async(&pool)
+        .await
+        .unwrap();
+
+    let name: String = users::table
+        .select(users::name)
+        .first_async(&pool)
+        .await
+        .unwrap();
+    assert_eq!(name, "ada");
+}
+
+// With `tokio-rt-threaded` the operation blocks the awaiting task, so it cannot be dropped
+#[cfg(not(feature = "tokio-rt-threaded"))]
+#[tokio::test(threaded_scheduler)]
+async fn dropping_an_operation_returns_its_connection() {
+    let pool = pool().await;
+
+    for _ in 0..3 {
+        let slow = AsyncConnection::<SqliteConnection>::run(&pool, |_| {
+            std::thread::sleep(Duration::from_millis(50));
+            Ok(())
+        });
+        let dropped = tokio::time::timeout(Duration::from_millis(10), slow).await;
+        assert!(dropped.is_err());
+    }
+
+    // With the slot of the only connection leaked, this would time out on the checkout
+    pool.ping_async().await.unwrap();
+}
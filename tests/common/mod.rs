@@ -0,0 +1,190 @@
+// The schema and pools shared by the integration tests
+#![allow(dead_code, non_local_definitions)]
+
+use diesel::{
+    connection::{AnsiTransactionManager, Connection, SimpleConnection},
+    deserialize::{Queryable, QueryableByName},
+    pg::PgConnection,
+    query_builder::{AsQuery, QueryFragment, QueryId},
+    r2d2::{ConnectionManager, Pool},
+    result::{ConnectionError, ConnectionResult, DatabaseErrorKind, Error, QueryResult},
+    sql_types::HasSqlType,
+    sqlite::{Sqlite, SqliteConnection},
+};
+use std::{
+    env,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+table! {
+    users (id) {
+        id -> Integer,
+        name -> Text,
+    }
+}
+
+table! {
+    accounts (id) {
+        id -> Integer,
+        email -> Text,
+    }
+}
+
+#[derive(Debug, PartialEq, Queryable, QueryableByName, Identifiable, AsChangeset)]
+#[table_name = "users"]
+pub struct User {
+    pub id: i32,
+    pub name: String,
+}
+
+pub const USERS: &str = "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT NOT NULL)";
+
+// A pool of a single in-memory SQLite connection, holding an empty `users` table
+pub fn sqlite_pool() -> Pool<ConnectionManager<SqliteConnection>> {
+    let pool = Pool::builder()
+        .max_size(1)
+        .build(ConnectionManager::<SqliteConnection>::new(":memory:"))
+        .unwrap();
+    pool.get().unwrap().batch_execute(USERS).unwrap();
+    pool
+}
+
+// The statement inserting `count` rows into `users`, with the ids 1, 2, ... and the names
+// `user1`, `user2`, ...
+pub fn insert_users(count: usize) -> String {
+    format!(
+        "INSERT INTO users (id, name) \
+         WITH RECURSIVE n (i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < {}) \
+         SELECT i, 'user' || i FROM n",
+        count
+    )
+}
+
+// The Postgres database of the tests that are ignored by default
+pub fn database_url() -> String {
+    env::var("DATABASE_URL").expect("DATABASE_URL must point to a Postgres database")
+}
+
+// A pool of a single Postgres connection, holding an empty temporary `users` table that no
+// other test sees
+pub fn pg_pool() -> Pool<ConnectionManager<PgConnection>> {
+    let pool = Pool::builder()
+        .max_size(1)
+        .build(ConnectionManager::<PgConnection>::new(database_url()))
+        .unwrap();
+    pool.get()
+        .unwrap()
+        .batch_execute("CREATE TEMPORARY TABLE users (id SERIAL PRIMARY KEY, name TEXT NOT NULL)")
+        .unwrap();
+    pool
+}
+
+// The Postgres table of the tests that need several connections to see the same rows. Those tests
+// mark their rows with a unique email so that they do not see each other's.
+#[derive(Debug, PartialEq, Queryable, QueryableByName)]
+#[table_name = "accounts"]
+pub struct Account {
+    pub id: i32,
+    pub email: String,
+}
+
+pub const ACCOUNTS: &str =
+    "CREATE TABLE IF NOT EXISTS accounts (id SERIAL PRIMARY KEY, email TEXT NOT NULL UNIQUE)";
+
+// The statements run through every `TestConnection` of the test binary
+pub static STATEMENTS: AtomicUsize = AtomicUsize::new(0);
+
+// Until when every `TestConnection` of the test binary behaves as if the database were down
+static DOWN_UNTIL: Mutex<Option<Instant>> = Mutex::new(None);
+
+// Make every `TestConnection` fail its statements, and new ones fail to connect, for `duration`
+pub fn take_down(duration: Duration) {
+    *DOWN_UNTIL.lock().unwrap() = Some(Instant::now() + duration);
+}
+
+fn is_down() -> bool {
+    DOWN_UNTIL
+        .lock()
+        .unwrap()
+        .is_some_and(|until| Instant::now() < until)
+}
+
+// A connection of its own type, delegating to a `SqliteConnection`, that counts its statements
+// and fails them while the database is taken down
+pub struct TestConnection(SqliteConnection);
+
+impl TestConnection {
+    fn statement(&self) -> QueryResult<()> {
+        STATEMENTS.fetch_add(1, Ordering::SeqCst);
+        if is_down() {
+            let message = Box::new("the database is down".to_string());
+            return Err(Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                message,
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl SimpleConnection for TestConnection {
+    fn batch_execute(&self, query: &str) -> QueryResult<()> {
+        self.statement()?;
+        self.0.batch_execute(query)
+    }
+}
+
+impl Connection for TestConnection {
+    type Backend = Sqlite;
+    type TransactionManager = AnsiTransactionManager;
+
+    fn establish(database_url: &str) -> ConnectionResult<Self> {
+        if is_down() {
+            return Err(ConnectionError::BadConnection(
+                "the database is down".to_string(),
+            ));
+        }
+        SqliteConnection::establish(database_url).map(TestConnection)
+    }
+
+    fn execute(&self, query: &str) -> QueryResult<usize> {
+        self.statement()?;
+        self.0.execute(query)
+    }
+
+    fn query_by_index<T, U>(&self, source: T) -> QueryResult<Vec<U>>
+    where
+        T: AsQuery,
+        T::Query: QueryFragment<Sqlite> + QueryId,
+        Sqlite: HasSqlType<T::SqlType>,
+        U: Queryable<T::SqlType, Sqlite>,
+    {
+        self.statement()?;
+        self.0.query_by_index(source)
+    }
+
+    fn query_by_name<T, U>(&self, source: &T) -> QueryResult<Vec<U>>
+    where
+        T: QueryFragment<Sqlite> + QueryId,
+        U: QueryableByName<Sqlite>,
+    {
+        self.statement()?;
+        self.0.query_by_name(source)
+    }
+
+    fn execute_returning_count<T>(&self, source: &T) -> QueryResult<usize>
+    where
+        T: QueryFragment<Sqlite> + QueryId,
+    {
+        self.statement()?;
+        self.0.execute_returning_count(source)
+    }
+
+    fn transaction_manager(&self) -> &AnsiTransactionManager {
+        self.0.transaction_manager()
+    }
+}
@@ -0,0 +1,775 @@
+#[macro_use]
+extern crate diesel;
+
+mod common;
+
+use common::{accounts, users, User};
+use diesel::{
+    connection::SimpleConnection,
+    dsl::sql,
+    prelude::*,
+    r2d2::{ConnectionManager, Pool},
+    result::{DatabaseErrorKind, Error},
+    sql_types::{Integer, Text},
+    sqlite::SqliteConnection,
+};
+use futures::{
+    channel::oneshot,
+    future::{self, FutureExt},
+};
+use std::{
+    error::Error as StdError,
+    fmt,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use tokio_diesel::*;
+
+#[tokio::test(threaded_scheduler)]
+async fn run_mut_hands_a_mutable_connection_to_the_closure() {
+    let pool = common::sqlite_pool();
+
+    let inserted = pool
+        .run_mut(|conn| {
+            diesel::insert_into(users::table)
+                .values((users::id.eq(1), users::name.eq("ada")))
+                .execute(&*conn)
+        })
+        .await
+        .unwrap();
+    assert_eq!(inserted, 1);
+
+    pool.transaction_mut(|conn| {
+        diesel::insert_into(users::table)
+            .values((users::id.eq(2), users::name.eq("grace")))
+            .execute(&*conn)
+    })
+    .await
+    .unwrap();
+
+    let names: Vec<String> = users::table
+        .select(users::name)
+        .order(users::id)
+        .load_async(&pool)
+        .await
+        .unwrap();
+    assert_eq!(names, ["ada", "grace"]);
+}
+
+#[tokio::test(threaded_scheduler)]
+#[ignore = "needs Postgres at DATABASE_URL"]
+async fn run_timeout_gives_up_on_a_sleeping_query() {
+    let pool = common::pg_pool();
+
+    let start = Instant::now();
+    let result = pool
+        .run_timeout(Duration::from_millis(50), |conn| {
+            diesel::sql_query("SELECT pg_sleep(1)").execute(conn)
+        })
+        .await;
+    match result {
+        Err(AsyncError::Timeout(timeout)) => assert_eq!(timeout, Duration::from_millis(50)),
+        result => panic!("expected a timeout, got {:?}", result),
+    }
+    assert!(start.elapsed() < Duration::from_millis(500));
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn transaction_timeout_lets_the_blocking_work_finish() {
+    let pool = common::sqlite_pool();
+
+    let result = pool
+        .transaction_timeout(Duration::from_millis(50), |conn| {
+            thread::sleep(Duration::from_millis(200));
+            diesel::insert_into(users::table)
+                .values((users::id.eq(1), users::name.eq("ada")))
+                .execute(conn)
+        })
+        .await;
+    assert!(matches!(result, Err(AsyncError::Timeout(_))));
+
+    // The next operation waits for the connection until the transaction has committed
+    assert_eq!(users::table.count_async(&pool).await.unwrap(), 1);
+}
+
+// Fail the transaction the way a conflict between serializable transactions does
+const SERIALIZATION_FAILURE: &str = "DO $$ BEGIN \
+     RAISE EXCEPTION 'conflict' USING ERRCODE = 'serialization_failure'; \
+     END $$";
+
+#[tokio::test(threaded_scheduler)]
+#[ignore = "needs Postgres at DATABASE_URL"]
+async fn transaction_retry_retries_serialization_failures() {
+    let pool = common::pg_pool();
+    let attempts = Arc::new(AtomicUsize::new(0));
+
+    let attempts_ = attempts.clone();
+    let name = pool
+        .transaction_retry(3, move |conn| {
+            diesel::insert_into(users::table)
+                .values((users::id.eq(1), users::name.eq("ada")))
+                .execute(conn)?;
+            if attempts_.fetch_add(1, Ordering::SeqCst) < 2 {
+                conn.batch_execute(SERIALIZATION_FAILURE)?;
+            }
+            users::table.select(users::name).first::<String>(conn)
+        })
+        .await
+        .unwrap();
+    assert_eq!(name, "ada");
+    assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    assert_eq!(users::table.count_async(&pool).await.unwrap(), 1);
+
+    attempts.store(0, Ordering::SeqCst);
+    let attempts_ = attempts.clone();
+    let result = pool
+        .transaction_retry(1, move |conn| {
+            attempts_.fetch_add(1, Ordering::SeqCst);
+            conn.batch_execute(SERIALIZATION_FAILURE)
+        })
+        .await;
+    assert!(matches!(
+        result,
+        Err(AsyncError::Error(Error::DatabaseError(
+            DatabaseErrorKind::SerializationFailure,
+            _
+        )))
+    ));
+    assert_eq!(attempts.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test(threaded_scheduler)]
+#[ignore = "needs Postgres at DATABASE_URL"]
+async fn transaction_retry_retries_the_serialization_failures_of_a_labeled_handle() {
+    let pool = AsyncPool::with_concurrency(common::pg_pool(), 1).unwrap();
+    let handle = pool.handle().label("checkout");
+    let attempts = Arc::new(AtomicUsize::new(0));
+
+    let attempts_ = attempts.clone();
+    handle
+        .transaction_retry(3, move |conn| {
+            if attempts_.fetch_add(1, Ordering::SeqCst) < 2 {
+                conn.batch_execute(SERIALIZATION_FAILURE)?;
+            }
+            Ok(())
+        })
+        .await
+        .unwrap();
+    assert_eq!(attempts.load(Ordering::SeqCst), 3);
+
+    let err = handle
+        .transaction_retry(1, |conn| conn.batch_execute(SERIALIZATION_FAILURE))
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(err, AsyncError::Labeled("checkout", _)),
+        "{:?}",
+        err
+    );
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn pool_state_async_reports_the_idle_connections() {
+    let pool = common::sqlite_pool();
+
+    let state = pool.pool_state_async().await.unwrap();
+    assert!(state.connections >= state.idle_connections);
+    assert_eq!(state.idle_connections, 1);
+
+    let conn = pool.get().unwrap();
+    let state = pool.pool_state_async().await.unwrap();
+    assert!(state.connections >= state.idle_connections);
+    assert_eq!(state.idle_connections, 0);
+    drop(conn);
+}
+
+#[derive(Debug, PartialEq)]
+enum TransferError {
+    Database(String),
+    InsufficientFunds,
+}
+
+impl From<Error> for TransferError {
+    fn from(err: Error) -> Self {
+        TransferError::Database(err.to_string())
+    }
+}
+
+impl fmt::Display for TransferError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TransferError::Database(ref err) => write!(f, "database error: {}", err),
+            TransferError::InsufficientFunds => f.write_str("insufficient funds"),
+        }
+    }
+}
+
+impl StdError for TransferError {}
+
+#[tokio::test(threaded_scheduler)]
+async fn transaction_err_rolls_back_on_a_custom_error() {
+    let pool = common::sqlite_pool();
+
+    let result = pool
+        .transaction_err(|conn| {
+            diesel::insert_into(users::table)
+                .values((users::id.eq(1), users::name.eq("ada")))
+                .execute(conn)?;
+            Err::<(), _>(TransferError::InsufficientFunds)
+        })
+        .await;
+    match result {
+        Err(TransactionError::Error(err)) => assert_eq!(err, TransferError::InsufficientFunds),
+        result => panic!("expected the error of the closure, got {:?}", result),
+    }
+    assert_eq!(users::table.count_async(&pool).await.unwrap(), 0);
+}
+
+#[tokio::test(threaded_scheduler)]
+#[ignore = "needs Postgres at DATABASE_URL"]
+async fn read_only_transaction_rejects_writes() {
+    let pool = common::pg_pool();
+    // Temporary tables stay writable in a read-only transaction
+    pool.batch_execute_async("CREATE TABLE IF NOT EXISTS read_only_transaction (id INTEGER)")
+        .await
+        .unwrap();
+
+    let result = pool
+        .read_only_transaction(|conn| {
+            diesel::sql_query("INSERT INTO read_only_transaction (id) VALUES (1)").execute(conn)
+        })
+        .await;
+    match result {
+        Err(AsyncError::Error(Error::DatabaseError(_, info))) => {
+            assert!(info.message().contains("read-only transaction"))
+        }
+        result => panic!("expected the insert to be rejected, got {:?}", result),
+    }
+
+    let count = pool
+        .read_only_transaction(|conn| users::table.count().get_result::<i64>(conn))
+        .await
+        .unwrap();
+    assert_eq!(count, 0);
+}
+
+#[tokio::test(threaded_scheduler)]
+#[ignore = "needs Postgres at DATABASE_URL"]
+async fn transaction_with_isolation_sets_the_level() {
+    let pool = common::pg_pool();
+
+    for &(level, setting) in &[
+        (IsolationLevel::ReadCommitted, "read committed"),
+        (IsolationLevel::RepeatableRead, "repeatable read"),
+        (IsolationLevel::Serializable, "serializable"),
+    ] {
+        let current = pool
+            .transaction_with_isolation(level, |conn| {
+                diesel::select(sql::<Text>("current_setting('transaction_isolation')"))
+                    .get_result::<String>(conn)
+            })
+            .await
+            .unwrap();
+        assert_eq!(current, setting);
+    }
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn a_panic_in_run_resumes_with_its_payload() {
+    let pool = common::sqlite_pool();
+
+    let pool_ = pool.clone();
+    let err = tokio::spawn(async move {
+        pool_
+            .run(|_| -> QueryResult<()> { panic!("deserialization bug") })
+            .await
+    })
+    .await
+    .unwrap_err();
+    let payload = err.into_panic();
+    assert_eq!(payload.downcast_ref::<&str>(), Some(&"deserialization bug"));
+
+    assert_eq!(users::table.count_async(&pool).await.unwrap(), 0);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn error_predicates_match_database_errors() {
+    let pool = common::sqlite_pool();
+
+    let err = users::table
+        .select(users::name)
+        .first_async::<String>(&pool)
+        .await
+        .unwrap_err();
+    assert!(err.is_not_found());
+    assert!(!err.is_unique_violation());
+
+    let insert =
+        || diesel::insert_into(users::table).values((users::id.eq(1), users::name.eq("ada")));
+    insert().execute_async(&pool).await.unwrap();
+    let err = insert().execute_async(&pool).await.unwrap_err();
+    assert!(err.is_unique_violation());
+    assert!(!err.is_not_found());
+
+    let err: AsyncError = Error::NotFound.into();
+    assert!(err.is_not_found());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn ping_async_reports_whether_the_database_is_reachable() {
+    let pool = common::sqlite_pool();
+    pool.ping_async().await.unwrap();
+    pool.ping_timeout_async(Duration::from_secs(1))
+        .await
+        .unwrap();
+
+    let unreachable = Pool::builder()
+        .connection_timeout(Duration::from_millis(100))
+        .build_unchecked(ConnectionManager::<SqliteConnection>::new(
+            "/nonexistent/tokio_diesel.db",
+        ));
+    let result = unreachable.ping_async().await;
+    assert!(matches!(result, Err(AsyncError::Checkout(_))));
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn to_cloneable_keeps_the_kind_and_message() {
+    let pool = common::sqlite_pool();
+    let insert =
+        || diesel::insert_into(users::table).values((users::id.eq(1), users::name.eq("ada")));
+    insert().execute_async(&pool).await.unwrap();
+    let err = insert().execute_async(&pool).await.unwrap_err();
+
+    let cloneable = err.to_cloneable();
+    let cloned = cloneable.clone();
+    assert_eq!(cloned.to_string(), err.to_string());
+    assert_eq!(cloned.to_string(), cloneable.to_string());
+    assert!(matches!(
+        cloned,
+        CloneableError::Database(DatabaseErrorKind::UniqueViolation, _)
+    ));
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn a_nested_transaction_rolls_back_to_its_savepoint() {
+    let pool = common::sqlite_pool();
+
+    pool.transaction(|conn| {
+        diesel::insert_into(users::table)
+            .values((users::id.eq(1), users::name.eq("ada")))
+            .execute(conn)?;
+        let nested = conn.transaction::<(), _, _>(|| {
+            diesel::insert_into(users::table)
+                .values((users::id.eq(2), users::name.eq("grace")))
+                .execute(conn)?;
+            Err(Error::RollbackTransaction)
+        });
+        assert_eq!(nested, Err(Error::RollbackTransaction));
+        Ok(())
+    })
+    .await
+    .unwrap();
+
+    let ids: Vec<i32> = users::table
+        .select(users::id)
+        .load_async(&pool)
+        .await
+        .unwrap();
+    assert_eq!(ids, [1]);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn the_label_of_an_operation_is_part_of_its_error() {
+    let pool = common::sqlite_pool();
+
+    let err = pool
+        .run_labeled("rename_users", |conn| {
+            diesel::sql_query("UPDATE nothing SET name = 'ada'").execute(conn)
+        })
+        .await
+        .unwrap_err();
+    assert!(matches!(err, AsyncError::Labeled("rename_users", _)));
+    assert!(err.to_string().starts_with("rename_users: "));
+
+    let err = users::table
+        .find(1)
+        .get_result_labeled_async::<User>("find_user", &pool)
+        .await
+        .unwrap_err();
+    assert_eq!(err.to_string(), "find_user: NotFound");
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn a_guard_runs_every_operation_on_its_connection() {
+    // Every in-memory connection has a database of its own
+    let pool = Pool::builder()
+        .max_size(2)
+        .build(ConnectionManager::<SqliteConnection>::new(":memory:"))
+        .unwrap();
+
+    let guard = pool.acquire_async().await.unwrap();
+    guard
+        .run_on(|conn| conn.batch_execute("CREATE TEMPORARY TABLE scratch (value INTEGER)"))
+        .await
+        .unwrap();
+    guard
+        .run_on(|conn| diesel::sql_query("INSERT INTO scratch VALUES (1), (2)").execute(conn))
+        .await
+        .unwrap();
+    let sum = guard
+        .run_on(|conn| {
+            diesel::select(sql::<Integer>("(SELECT SUM(value) FROM scratch)"))
+                .get_result::<i32>(conn)
+        })
+        .await
+        .unwrap();
+    assert_eq!(sum, 3);
+
+    let state = pool.pool_state_async().await.unwrap();
+    assert_eq!(state.connections - state.idle_connections, 1);
+    drop(guard);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn batch_execute_labeled_async_reports_the_failing_statement() {
+    let pool = common::sqlite_pool();
+    let statements = [
+        "INSERT INTO users (id, name) VALUES (1, 'ada')",
+        "INSERT INTO users (id, name) VALUES (2, 'grace')",
+        "INSERT INTO users (id, name) VALUES (1, 'edsger')",
+        "INSERT INTO users (id, name) VALUES (3, 'barbara')",
+        "INSERT INTO users (id, name) VALUES (4, 'donald')",
+    ];
+
+    for &in_transaction in &[true, false] {
+        let err = pool
+            .batch_execute_labeled_async(&statements, in_transaction)
+            .await
+            .unwrap_err();
+        match err {
+            AsyncError::Statement(index, statement, err) => {
+                assert_eq!(index, 2);
+                assert_eq!(statement, statements[2]);
+                assert!(matches!(*err, AsyncError::Error(Error::DatabaseError(..))));
+            }
+            err => panic!("unexpected error: {:?}", err),
+        }
+
+        // Within a transaction the first two inserts are rolled back, outside of one they stay
+        let ids = users::table
+            .select(users::id)
+            .order(users::id)
+            .load_async::<i32>(&pool)
+            .await
+            .unwrap();
+        assert_eq!(ids, if in_transaction { vec![] } else { vec![1, 2] });
+    }
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn try_run_fails_fast_on_an_exhausted_pool() {
+    let pool = Pool::builder()
+        .max_size(1)
+        .connection_timeout(Duration::from_secs(5))
+        .build(ConnectionManager::<SqliteConnection>::new(":memory:"))
+        .unwrap();
+
+    let conn = pool.get().unwrap();
+    let start = Instant::now();
+    let result = pool.try_run(|_| Ok(())).await;
+    assert!(matches!(result, Err(AsyncError::NoConnectionAvailable)));
+    assert!(start.elapsed() < Duration::from_secs(1));
+
+    drop(conn);
+    pool.try_run(|_| Ok(())).await.unwrap();
+}
+
+#[tokio::test(threaded_scheduler)]
+#[ignore = "needs Postgres at DATABASE_URL"]
+async fn a_unique_violation_reports_its_constraint() {
+    let pool = common::pg_pool();
+    pool.batch_execute_async(common::ACCOUNTS).await.unwrap();
+    let email = format!("{}@example.com", uuid::Uuid::new_v4());
+    let insert = || diesel::insert_into(accounts::table).values(accounts::email.eq(email.clone()));
+
+    insert().execute_async(&pool).await.unwrap();
+    let err = insert().execute_async(&pool).await.unwrap_err();
+    let (kind, _) = err.as_database_error().unwrap();
+    assert!(matches!(kind, DatabaseErrorKind::UniqueViolation));
+    assert_eq!(err.constraint_name(), Some("accounts_email_key"));
+    assert_eq!(err.table_name(), Some("accounts"));
+
+    let err = users::table.first_async::<User>(&pool).await.unwrap_err();
+    assert!(err.as_database_error().is_none());
+    assert_eq!(err.constraint_name(), None);
+
+    diesel::delete(accounts::table.filter(accounts::email.eq(email)))
+        .execute_async(&pool)
+        .await
+        .unwrap();
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn after_commit_fires_only_once_the_transaction_committed() {
+    let pool = common::sqlite_pool();
+    let committed = Arc::new(AtomicUsize::new(0));
+    let insert = |id: i32| {
+        move |conn: &SqliteConnection| {
+            diesel::insert_into(users::table)
+                .values((users::id.eq(id), users::name.eq("ada")))
+                .execute(conn)
+        }
+    };
+
+    let committed_ = committed.clone();
+    pool.transaction_with_hooks(
+        insert(1),
+        |_| Ok(()),
+        move |_| {
+            committed_.fetch_add(1, Ordering::SeqCst);
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(committed.load(Ordering::SeqCst), 1);
+
+    // The closure fails on the duplicate id
+    let committed_ = committed.clone();
+    pool.transaction_with_hooks(
+        insert(1),
+        |_| Ok(()),
+        move |_| {
+            committed_.fetch_add(1, Ordering::SeqCst);
+        },
+    )
+    .await
+    .unwrap_err();
+
+    // `before_commit` vetoes the commit
+    let committed_ = committed.clone();
+    pool.transaction_with_hooks(
+        insert(2),
+        |_| Err(Error::RollbackTransaction),
+        move |_| {
+            committed_.fetch_add(1, Ordering::SeqCst);
+        },
+    )
+    .await
+    .unwrap_err();
+
+    assert_eq!(committed.load(Ordering::SeqCst), 1);
+    let ids = users::table
+        .select(users::id)
+        .load_async::<i32>(&pool)
+        .await
+        .unwrap();
+    assert_eq!(ids, [1]);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn a_scoped_transaction_rolls_back_while_returning_its_value() {
+    let pool = common::sqlite_pool();
+    let insert = |conn: &SqliteConnection| {
+        diesel::insert_into(users::table)
+            .values(users::name.eq("ada"))
+            .execute(conn)?;
+        users::table.count().get_result::<i64>(conn)
+    };
+
+    // A dry run
+    let count = pool
+        .transaction_scoped(move |conn, scope| {
+            let count = insert(conn)?;
+            scope.rollback();
+            assert!(scope.is_rolled_back());
+            Ok(count)
+        })
+        .await
+        .unwrap();
+    assert_eq!(count, 1);
+    assert_eq!(users::table.count_async(&pool).await.unwrap(), 0);
+
+    let count = pool
+        .transaction_scoped(move |conn, _| insert(conn))
+        .await
+        .unwrap();
+    assert_eq!(count, 1);
+    assert_eq!(users::table.count_async(&pool).await.unwrap(), 1);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn run_with_checkout_timeout_gives_up_on_an_exhausted_pool_in_time() {
+    let pool = Pool::builder()
+        .max_size(1)
+        .connection_timeout(Duration::from_secs(10))
+        .build(ConnectionManager::<SqliteConnection>::new(":memory:"))
+        .unwrap();
+
+    let conn = pool.get().unwrap();
+    let start = Instant::now();
+    let result = pool
+        .run_with_checkout_timeout(Duration::from_millis(50), |_| Ok(()))
+        .await;
+    assert!(matches!(result, Err(AsyncError::Checkout(_))));
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(50) && elapsed < Duration::from_secs(5));
+
+    drop(conn);
+    pool.run_with_checkout_timeout(Duration::from_millis(50), |_| Ok(()))
+        .await
+        .unwrap();
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn a_custom_connection_type_runs_through_the_async_traits() {
+    let pool = Pool::builder()
+        .max_size(1)
+        .build(ConnectionManager::<common::TestConnection>::new(":memory:"))
+        .unwrap();
+    pool.batch_execute_async(common::USERS).await.unwrap();
+    let before = common::STATEMENTS.load(Ordering::SeqCst);
+
+    diesel::insert_into(users::table)
+        .values(users::name.eq("ada"))
+        .execute_async(&pool)
+        .await
+        .unwrap();
+    let names = pool
+        .transaction(|conn| users::table.select(users::name).load::<String>(conn))
+        .await
+        .unwrap();
+    assert_eq!(names, ["ada"]);
+    assert!(common::STATEMENTS.load(Ordering::SeqCst) > before);
+
+    let pool = AsyncPool::with_concurrency(pool, 1).unwrap();
+    assert_eq!(users::table.count_async(&pool).await.unwrap(), 1);
+}
+
+// The errors of the source chain of `err`, starting with `err` itself
+fn source_chain<'a>(err: &'a (dyn StdError + 'static)) -> Vec<&'a (dyn StdError + 'static)> {
+    let mut chain = vec![err];
+    let mut err = err;
+    while let Some(source) = err.source() {
+        chain.push(source);
+        err = source;
+    }
+    chain
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn the_source_chain_leads_to_the_underlying_error() {
+    let pool = common::sqlite_pool();
+
+    let err = pool
+        .transaction_err(|_| Err::<(), _>(TransferError::InsufficientFunds))
+        .await
+        .unwrap_err();
+    let chain = source_chain(&err);
+    assert_eq!(chain.len(), 2);
+    assert_eq!(
+        chain[1].downcast_ref::<TransferError>(),
+        Some(&TransferError::InsufficientFunds)
+    );
+
+    let err = pool
+        .run_labeled("rename_users", |conn| {
+            diesel::sql_query("UPDATE no_such_table SET name = 'ada'").execute(conn)
+        })
+        .await
+        .unwrap_err();
+    let chain = source_chain(&err);
+    assert_eq!(chain.len(), 3);
+    assert!(matches!(
+        chain[1].downcast_ref::<AsyncError>(),
+        Some(AsyncError::Error(_))
+    ));
+    assert!(matches!(
+        chain[2].downcast_ref::<Error>(),
+        Some(Error::DatabaseError(..))
+    ));
+
+    // A failed checkout leads to the error of the pool
+    let unreachable = Pool::builder()
+        .max_size(1)
+        .connection_timeout(Duration::from_millis(100))
+        .build_unchecked(ConnectionManager::<SqliteConnection>::new(
+            "/nonexistent/tokio_diesel.db",
+        ));
+    let err = unreachable.run(|_| Ok(())).await.unwrap_err();
+    assert!(source_chain(&err)[1]
+        .downcast_ref::<diesel::r2d2::PoolError>()
+        .is_some());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn run_until_is_cancelled_before_but_not_after_the_work_finishes() {
+    let pool = common::sqlite_pool();
+    let ran = Arc::new(AtomicUsize::new(0));
+    let work = |ran: Arc<AtomicUsize>| {
+        move |_: &SqliteConnection| {
+            thread::sleep(Duration::from_millis(100));
+            Ok(ran.fetch_add(1, Ordering::SeqCst) + 1)
+        }
+    };
+
+    // Cancelled before the work starts, which is never run
+    let result = pool.run_until(future::ready(()), work(ran.clone())).await;
+    assert!(matches!(result, Err(AsyncError::Cancelled)));
+    assert_eq!(ran.load(Ordering::SeqCst), 0);
+
+    // Cancelled only once the work has finished
+    let (cancel, cancelled) = oneshot::channel::<()>();
+    let result = pool
+        .run_until(cancelled.map(|_| ()), work(ran.clone()))
+        .await;
+    assert_eq!(result.unwrap(), 1);
+    drop(cancel);
+}
+
+// With `tokio-rt-threaded` the work runs on the awaiting task, so it cannot be cancelled once
+// it started
+#[cfg(not(feature = "tokio-rt-threaded"))]
+#[tokio::test(threaded_scheduler)]
+async fn run_until_stops_waiting_for_running_work_that_still_completes() {
+    let pool = common::sqlite_pool();
+    let finished = Arc::new(AtomicUsize::new(0));
+
+    let finished_ = finished.clone();
+    let result = pool
+        .run_until(
+            tokio::time::delay_for(Duration::from_millis(20)),
+            move |_| {
+                thread::sleep(Duration::from_millis(100));
+                finished_.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            },
+        )
+        .await;
+    assert!(matches!(result, Err(AsyncError::Cancelled)));
+    assert_eq!(finished.load(Ordering::SeqCst), 0);
+
+    // The next run waits for the connection the cancelled work still holds
+    pool.run(|_| Ok(())).await.unwrap();
+    assert_eq!(finished.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test(threaded_scheduler)]
+#[ignore = "needs Postgres at DATABASE_URL"]
+async fn run_timed_measures_the_query_apart_from_the_checkout() {
+    let pool = common::pg_pool();
+
+    let (slept, timing) = pool
+        .run_timed(|conn| {
+            diesel::select(sql::<Text>("pg_sleep(0.1)::text")).get_result::<String>(conn)
+        })
+        .await
+        .unwrap();
+    assert_eq!(slept, "");
+    assert!(timing.query >= Duration::from_millis(100));
+    assert!(timing.query < Duration::from_secs(2));
+    assert!(timing.checkout < timing.query);
+}
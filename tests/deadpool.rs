@@ -0,0 +1,40 @@
+#![cfg(feature = "deadpool")]
+
+#[macro_use]
+extern crate diesel;
+
+mod common;
+
+use common::{users, User};
+use deadpool_diesel::{Manager, Pool, Runtime};
+use diesel::{prelude::*, sqlite::SqliteConnection};
+use tokio_diesel::*;
+
+// A pool of a single in-memory SQLite connection, holding an empty `users` table. The tests run
+// on tokio 0.2, so `interact` runs on the blocking thread pool of async-std.
+async fn pool() -> Pool<Manager<SqliteConnection>> {
+    let pool = Pool::builder(Manager::new(":memory:", Runtime::AsyncStd1))
+        .max_size(1)
+        .build()
+        .unwrap();
+    pool.batch_execute_async("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT NOT NULL)")
+        .await
+        .unwrap();
+    pool
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn rows_load_through_a_deadpool_pool() {
+    let pool = pool().await;
+    pool.batch_execute_async(&common::insert_users(3))
+        .await
+        .unwrap();
+
+    let users: Vec<User> = users::table
+        .order(users::id)
+        .load_async(&pool)
+        .await
+        .unwrap();
+    let names: Vec<_> = users.iter().map(|user| &*user.name).collect();
+    assert_eq!(names, ["user1", "user2", "user3"]);
+}
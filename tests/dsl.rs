@@ -0,0 +1,879 @@
+#[macro_use]
+extern crate diesel;
+
+mod common;
+
+use common::{users, User};
+use diesel::{
+    dsl::sql,
+    prelude::*,
+    result::Error,
+    sql_types::{BigInt, Double, Integer, Text},
+    sqlite::Sqlite,
+};
+use futures::stream::{StreamExt, TryStreamExt};
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::time::delay_for;
+use tokio_diesel::*;
+
+#[tokio::test(threaded_scheduler)]
+async fn load_stream_async_yields_every_row_in_order() {
+    let pool = common::sqlite_pool();
+    pool.batch_execute_async(&common::insert_users(10_000))
+        .await
+        .unwrap();
+
+    let ids: Vec<i32> = users::table
+        .select(users::id)
+        .order(users::id)
+        .load_stream_async::<i32>(64, &pool)
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(ids, (1..=10_000).collect::<Vec<_>>());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn load_stream_async_yields_the_rows_before_a_malformed_one() {
+    let pool = common::sqlite_pool();
+    pool.batch_execute_async(&common::insert_users(10))
+        .await
+        .unwrap();
+
+    // The fifth row is NULL, which cannot be decoded as an `i32`
+    let rows: Vec<_> = users::table
+        .select(sql::<Integer>("CASE WHEN id = 5 THEN NULL ELSE id END"))
+        .order(users::id)
+        .load_stream_async::<i32>(2, &pool)
+        .collect()
+        .await;
+    assert_eq!(rows.len(), 5);
+    let ids: Vec<_> = rows[..4].iter().map(|row| *row.as_ref().unwrap()).collect();
+    assert_eq!(ids, [1, 2, 3, 4]);
+    assert!(matches!(
+        rows[4],
+        Err(AsyncError::Error(Error::DeserializationError(_)))
+    ));
+}
+
+// The number of `Counted` built so far
+static COUNTED: AtomicUsize = AtomicUsize::new(0);
+
+// An id that counts how many of its kind were built
+struct Counted(i32);
+
+impl Queryable<Integer, Sqlite> for Counted {
+    type Row = i32;
+
+    fn build(id: i32) -> Self {
+        COUNTED.fetch_add(1, Ordering::SeqCst);
+        Counted(id)
+    }
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn load_stream_async_only_loads_the_rows_the_stream_has_room_for() {
+    let pool = common::sqlite_pool();
+    pool.batch_execute_async(&common::insert_users(10_000))
+        .await
+        .unwrap();
+
+    let mut rows = users::table
+        .select(users::id)
+        .order(users::id)
+        .load_stream_async::<Counted>(1, &pool);
+    assert_eq!(rows.next().await.unwrap().unwrap().0, 1);
+    delay_for(Duration::from_millis(50)).await;
+    // The row in the stream, and the one waiting for room
+    assert!(COUNTED.load(Ordering::SeqCst) <= 3);
+
+    // Dropping the stream stops the load and returns the connection
+    drop(rows);
+    assert_eq!(users::table.count_async(&pool).await.unwrap(), 10_000);
+    assert!(COUNTED.load(Ordering::SeqCst) <= 3);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn execute_async_runs_on_an_arc_of_a_pool() {
+    let pool = Arc::new(common::sqlite_pool());
+
+    let inserted = diesel::insert_into(users::table)
+        .values((users::id.eq(1), users::name.eq("ada")))
+        .execute_async(&pool)
+        .await
+        .unwrap();
+    assert_eq!(inserted, 1);
+
+    let updated = diesel::update(users::table)
+        .set(users::name.eq("grace"))
+        .execute_async(&*pool)
+        .await
+        .unwrap();
+    assert_eq!(updated, 1);
+
+    let count = pool
+        .run(|conn| users::table.count().get_result::<i64>(conn))
+        .await
+        .unwrap();
+    assert_eq!(count, 1);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn load_async_accepts_a_pool_an_arc_and_a_reference() {
+    let pool = common::sqlite_pool();
+    pool.batch_execute_async(&common::insert_users(3))
+        .await
+        .unwrap();
+    let ids = || users::table.select(users::id).order(users::id);
+
+    let by_pool = ids().load_async::<i32>(&pool).await.unwrap();
+    let by_reference = ids().load_async::<i32>(&&pool).await.unwrap();
+    let arc = Arc::new(pool.clone());
+    let by_arc = ids().load_async::<i32>(&arc).await.unwrap();
+    let async_pool = Arc::new(AsyncPool::with_concurrency(pool, 1).unwrap());
+    let by_async_pool = ids().load_async::<i32>(&async_pool).await.unwrap();
+
+    for ids in &[by_pool, by_reference, by_arc, by_async_pool] {
+        assert_eq!(*ids, [1, 2, 3]);
+    }
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn count_async_and_exists_async_on_empty_and_populated_tables() {
+    let pool = common::sqlite_pool();
+    assert_eq!(users::table.count_async(&pool).await.unwrap(), 0);
+    assert!(!users::table
+        .select(users::id)
+        .exists_async(&pool)
+        .await
+        .unwrap());
+
+    pool.batch_execute_async(&common::insert_users(3))
+        .await
+        .unwrap();
+    assert_eq!(users::table.count_async(&pool).await.unwrap(), 3);
+    assert!(users::table
+        .filter(users::id.eq(2))
+        .exists_async(&pool)
+        .await
+        .unwrap());
+    assert!(!users::table
+        .filter(users::id.eq(4))
+        .exists_async(&pool)
+        .await
+        .unwrap());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn insert_chunked_async_inserts_every_chunk() {
+    let pool = common::sqlite_pool();
+
+    let rows: Vec<_> = (1..=100_000)
+        .map(|id| (users::id.eq(id), users::name.eq(format!("user{}", id))))
+        .collect();
+    let inserted = insert_chunked_async(users::table, rows, 1000, &pool)
+        .await
+        .unwrap();
+    assert_eq!(inserted, 100_000);
+    assert_eq!(users::table.count_async(&pool).await.unwrap(), 100_000);
+
+    let none: Vec<_> = (1..1)
+        .map(|id| (users::id.eq(id), users::name.eq("")))
+        .collect();
+    let inserted = insert_chunked_async(users::table, none, 1000, &pool)
+        .await
+        .unwrap();
+    assert_eq!(inserted, 0);
+}
+
+#[tokio::test(threaded_scheduler)]
+#[ignore = "needs Postgres at DATABASE_URL"]
+async fn insert_chunked_async_stays_below_the_bind_parameter_limit() {
+    let pool = common::pg_pool();
+
+    // A single statement would bind 100k parameters, above the limit of 65535
+    let rows: Vec<_> = (1..=100_000)
+        .map(|id| users::name.eq(format!("user{}", id)))
+        .collect();
+    let inserted = insert_chunked_async(users::table, rows, 1000, &pool)
+        .await
+        .unwrap();
+    assert_eq!(inserted, 100_000);
+    assert_eq!(users::table.count_async(&pool).await.unwrap(), 100_000);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn get_result_opt_async_and_first_opt_async_map_missing_rows_to_none() {
+    let pool = common::sqlite_pool();
+    pool.batch_execute_async(&common::insert_users(1))
+        .await
+        .unwrap();
+
+    let name = users::table
+        .find(1)
+        .select(users::name)
+        .get_result_opt_async::<String>(&pool)
+        .await
+        .unwrap();
+    assert_eq!(name.as_deref(), Some("user1"));
+    let name = users::table
+        .find(2)
+        .select(users::name)
+        .get_result_opt_async::<String>(&pool)
+        .await
+        .unwrap();
+    assert_eq!(name, None);
+
+    let id = users::table
+        .select(users::id)
+        .first_opt_async::<i32>(&pool)
+        .await
+        .unwrap();
+    assert_eq!(id, Some(1));
+    let id = users::table
+        .select(users::id)
+        .filter(users::name.eq("nobody"))
+        .first_opt_async::<i32>(&pool)
+        .await
+        .unwrap();
+    assert_eq!(id, None);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn first_or_async_and_first_or_else_async_fall_back_to_the_default() {
+    let pool = common::sqlite_pool();
+    let name = || users::table.select(users::name).order(users::id);
+
+    let default = name()
+        .first_or_async("nobody".to_string(), &pool)
+        .await
+        .unwrap();
+    assert_eq!(default, "nobody");
+    let default = name()
+        .first_or_else_async(|| "nobody".to_string(), &pool)
+        .await
+        .unwrap();
+    assert_eq!(default, "nobody");
+
+    pool.batch_execute_async(&common::insert_users(2))
+        .await
+        .unwrap();
+    let found = name()
+        .first_or_async("nobody".to_string(), &pool)
+        .await
+        .unwrap();
+    assert_eq!(found, "user1");
+    let found = name()
+        .first_or_else_async(|| -> String { panic!("a row was found") }, &pool)
+        .await
+        .unwrap();
+    assert_eq!(found, "user1");
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn save_all_changes_async_saves_every_record_or_none() {
+    let pool = common::sqlite_pool();
+    pool.batch_execute_async(&common::insert_users(3))
+        .await
+        .unwrap();
+
+    let renamed: Vec<_> = [("ada", 3), ("grace", 1), ("edsger", 2)]
+        .iter()
+        .map(|&(name, id)| User {
+            id,
+            name: name.to_string(),
+        })
+        .collect();
+    let saved: Vec<User> = renamed.save_all_changes_async(&pool).await.unwrap();
+    let names: Vec<_> = saved.iter().map(|user| (user.id, &*user.name)).collect();
+    assert_eq!(names, [(3, "ada"), (1, "grace"), (2, "edsger")]);
+
+    // The missing record fails the transaction, so the first one is not renamed either
+    let renamed = vec![
+        User {
+            id: 1,
+            name: "barbara".to_string(),
+        },
+        User {
+            id: 4,
+            name: "nobody".to_string(),
+        },
+    ];
+    let result = renamed.save_all_changes_async::<User>(&pool).await;
+    assert!(result.unwrap_err().is_not_found());
+    let names: Vec<String> = users::table
+        .select(users::name)
+        .order(users::id)
+        .load_async(&pool)
+        .await
+        .unwrap();
+    assert_eq!(names, ["grace", "edsger", "ada"]);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn save_changes_parallel_async_saves_the_records_independently() {
+    let pool = common::sqlite_pool();
+    pool.batch_execute_async(&common::insert_users(20))
+        .await
+        .unwrap();
+    let renamed = |ids: std::ops::RangeInclusive<i32>| {
+        ids.map(|id| User {
+            id,
+            name: format!("renamed{}", id),
+        })
+        .collect::<Vec<_>>()
+    };
+
+    let saved: Vec<User> = renamed(1..=20)
+        .into_iter()
+        .rev()
+        .collect::<Vec<_>>()
+        .save_changes_parallel_async(&pool)
+        .await
+        .unwrap();
+    let ids: Vec<_> = saved.iter().map(|user| user.id).collect();
+    assert_eq!(ids, (1..=20).rev().collect::<Vec<_>>());
+    assert_eq!(saved[0].name, "renamed20");
+
+    // Unlike `save_all_changes_async`, the records around a missing one are saved regardless
+    pool.batch_execute_async("UPDATE users SET name = 'user' || id")
+        .await
+        .unwrap();
+    let result = renamed(19..=21)
+        .save_changes_parallel_async::<User>(&pool)
+        .await;
+    assert!(result.unwrap_err().is_not_found());
+    let names: Vec<String> = users::table
+        .select(users::name)
+        .filter(users::id.ge(18))
+        .order(users::id)
+        .load_async(&pool)
+        .await
+        .unwrap();
+    assert_eq!(names, ["user18", "renamed19", "renamed20"]);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn load_and_count_async_counts_every_row_of_a_partial_page() {
+    let pool = common::sqlite_pool();
+    pool.batch_execute_async(&common::insert_users(25))
+        .await
+        .unwrap();
+    let ids = || users::table.select(users::id).order(users::id);
+
+    let (page, total) = ids()
+        .paginate(3, 10)
+        .load_and_count_async::<i32, _, _>(&pool)
+        .await
+        .unwrap();
+    assert_eq!(page, (21..=25).collect::<Vec<_>>());
+    assert_eq!(total, 25);
+
+    // Page 0 is clamped to the first page
+    let (page, total) = ids()
+        .paginate(0, 10)
+        .load_and_count_async::<i32, _, _>(&pool)
+        .await
+        .unwrap();
+    assert_eq!(page, (1..=10).collect::<Vec<_>>());
+    assert_eq!(total, 25);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn load_indexed_async_keys_the_rows() {
+    let pool = common::sqlite_pool();
+    pool.batch_execute_async(&common::insert_users(3))
+        .await
+        .unwrap();
+
+    let by_id = users::table
+        .load_indexed_async(|user: &User| user.id, &pool)
+        .await
+        .unwrap();
+    assert_eq!(by_id.len(), 3);
+    assert_eq!(by_id[&2].name, "user2");
+
+    // The last of the rows with the same key wins
+    let by_parity = users::table
+        .order(users::id)
+        .load_indexed_async(|user: &User| user.id % 2, &pool)
+        .await
+        .unwrap();
+    assert_eq!(by_parity.len(), 2);
+    assert_eq!(by_parity[&1].id, 3);
+    assert_eq!(by_parity[&0].id, 2);
+}
+
+#[tokio::test(threaded_scheduler)]
+#[ignore = "needs Postgres at DATABASE_URL"]
+async fn execute_returning_async_and_insert_and_get_id_async_return_the_inserted_rows() {
+    let pool = common::pg_pool();
+
+    let ids: Vec<i32> = diesel::insert_into(users::table)
+        .values(users::name.eq("ada"))
+        .returning(users::id)
+        .execute_returning_async(&pool)
+        .await
+        .unwrap();
+    assert_eq!(ids, [1]);
+
+    let inserted: Vec<User> = diesel::insert_into(users::table)
+        .values(vec![users::name.eq("grace"), users::name.eq("edsger")])
+        .returning((users::id, users::name))
+        .execute_returning_async(&pool)
+        .await
+        .unwrap();
+    let names: Vec<_> = inserted.iter().map(|user| (user.id, &*user.name)).collect();
+    assert_eq!(names, [(2, "grace"), (3, "edsger")]);
+
+    let id: i32 = insert_and_get_id_async(
+        diesel::insert_into(users::table).values(users::name.eq("barbara")),
+        &pool,
+    )
+    .await
+    .unwrap();
+    assert_eq!(id, 4);
+}
+
+#[tokio::test(threaded_scheduler)]
+#[ignore = "needs Postgres at DATABASE_URL"]
+async fn update_returning_async_returns_the_updated_rows() {
+    let pool = common::pg_pool();
+    pool.batch_execute_async(&common::insert_users(5))
+        .await
+        .unwrap();
+
+    let mut updated: Vec<User> = diesel::update(users::table.filter(users::id.le(3)))
+        .set(users::name.eq(users::name.concat("!")))
+        .update_returning_async(&pool)
+        .await
+        .unwrap();
+    updated.sort_by_key(|user| user.id);
+    let names: Vec<_> = updated.iter().map(|user| &*user.name).collect();
+    assert_eq!(names, ["user1!", "user2!", "user3!"]);
+
+    let none: Vec<i32> = diesel::update(users::table.filter(users::id.gt(5)))
+        .set(users::name.eq("nobody"))
+        .returning(users::id)
+        .update_returning_async(&pool)
+        .await
+        .unwrap();
+    assert!(none.is_empty());
+}
+
+#[tokio::test(threaded_scheduler)]
+#[ignore = "needs Postgres at DATABASE_URL"]
+async fn delete_returning_async_returns_the_deleted_rows() {
+    let pool = common::pg_pool();
+    pool.batch_execute_async(&common::insert_users(5))
+        .await
+        .unwrap();
+
+    let mut deleted: Vec<User> = diesel::delete(users::table.filter(users::id.gt(3)))
+        .delete_returning_async(&pool)
+        .await
+        .unwrap();
+    deleted.sort_by_key(|user| user.id);
+    assert_eq!(
+        deleted,
+        [
+            User {
+                id: 4,
+                name: "user4".to_string()
+            },
+            User {
+                id: 5,
+                name: "user5".to_string()
+            },
+        ]
+    );
+
+    let ids = users::table
+        .select(users::id)
+        .order(users::id)
+        .load_async::<i32>(&pool)
+        .await
+        .unwrap();
+    assert_eq!(ids, [1, 2, 3]);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn optional_maps_a_missing_row_of_the_future_to_none() {
+    let pool = common::sqlite_pool();
+
+    let name = users::table
+        .find(1)
+        .select(users::name)
+        .get_result_async::<String>(&pool)
+        .optional()
+        .await
+        .unwrap();
+    assert_eq!(name, None);
+
+    pool.batch_execute_async(&common::insert_users(1))
+        .await
+        .unwrap();
+    let name = users::table
+        .find(1)
+        .select(users::name)
+        .get_result_async::<String>(&pool)
+        .optional()
+        .await
+        .unwrap();
+    assert_eq!(name.as_deref(), Some("user1"));
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn a_missing_row_is_reported_as_not_found() {
+    let pool = common::sqlite_pool();
+
+    let result = users::table.first_async::<User>(&pool).await;
+    assert!(matches!(result, Err(AsyncError::NotFound)));
+    let result = users::table.find(1).get_result_async::<User>(&pool).await;
+    assert!(matches!(result, Err(AsyncError::NotFound)));
+    // `diesel::prelude` has an `OptionalExtension` of its own
+    assert_eq!(
+        tokio_diesel::OptionalExtension::optional(result).unwrap(),
+        None
+    );
+
+    // Other errors are kept by `optional`
+    let result = diesel::sql_query("SELEC 1")
+        .execute_async(&pool)
+        .optional()
+        .await;
+    assert!(matches!(result, Err(AsyncError::Error(_))));
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn fold_async_sums_like_the_sql_aggregate() {
+    let pool = common::sqlite_pool();
+    pool.batch_execute_async(&common::insert_users(1000))
+        .await
+        .unwrap();
+
+    let folded = users::table
+        .select(users::id)
+        .fold_async(0i64, |sum, id: i32| sum + i64::from(id), &pool)
+        .await
+        .unwrap();
+    let summed: Option<i64> = users::table
+        .select(diesel::dsl::sum(users::id))
+        .get_result_async(&pool)
+        .await
+        .unwrap();
+    assert_eq!(Some(folded), summed);
+    assert_eq!(folded, 500_500);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn sum_async_and_avg_async_on_empty_and_populated_tables() {
+    let pool = common::sqlite_pool();
+    let sum = users::table
+        .sum_async::<_, i64>(users::id, &pool)
+        .await
+        .unwrap();
+    assert_eq!(sum, None);
+    let avg = users::table
+        .avg_async::<_, f64>(sql::<Double>("id"), &pool)
+        .await
+        .unwrap();
+    assert_eq!(avg, None);
+
+    pool.batch_execute_async(&common::insert_users(4))
+        .await
+        .unwrap();
+    let sum = users::table
+        .sum_async::<_, i64>(users::id, &pool)
+        .await
+        .unwrap();
+    assert_eq!(sum, Some(10));
+    let avg = users::table
+        .avg_async::<_, f64>(sql::<Double>("id"), &pool)
+        .await
+        .unwrap();
+    assert_eq!(avg, Some(2.5));
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn a_deferred_boxed_query_loads_with_dynamic_filters() {
+    let pool = common::sqlite_pool();
+    pool.batch_execute_async(&common::insert_users(10))
+        .await
+        .unwrap();
+
+    let filters = [
+        (None, None, (1..=10).collect::<Vec<_>>()),
+        (Some("user3"), None, vec![3]),
+        (None, Some(8), vec![8, 9, 10]),
+        (Some("user3"), Some(8), vec![]),
+    ];
+    for (name, min_id, expected) in filters.iter().cloned() {
+        let ids = defer(move || {
+            let mut query = users::table.select(users::id).order(users::id).into_boxed();
+            if let Some(name) = name {
+                query = query.filter(users::name.eq(name));
+            }
+            if let Some(min_id) = min_id {
+                query = query.filter(users::id.ge(min_id));
+            }
+            query
+        })
+        .load_async::<i32>(&pool)
+        .await
+        .unwrap();
+        assert_eq!(ids, expected);
+    }
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn grouped_aggregates_load_as_tuples_and_maps() {
+    let pool = common::sqlite_pool();
+    pool.batch_execute_async(
+        "INSERT INTO users (name) VALUES ('ada'), ('grace'), ('ada'), ('edsger'), ('ada')",
+    )
+    .await
+    .unwrap();
+    let counts = || {
+        users::table
+            .group_by(users::name)
+            .select((users::name, sql::<BigInt>("COUNT(*)")))
+            .order(users::name)
+    };
+
+    let rows = counts().load_async::<(String, i64)>(&pool).await.unwrap();
+    let rows: Vec<_> = rows.iter().map(|(name, count)| (&**name, *count)).collect();
+    assert_eq!(rows, [("ada", 3), ("edsger", 1), ("grace", 1)]);
+
+    let map: HashMap<String, i64> = counts().load_grouped_async(&pool).await.unwrap();
+    assert_eq!(map.len(), 3);
+    assert_eq!(map["ada"], 3);
+    assert_eq!(map["grace"], 1);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn load_with_capacity_async_loads_every_row_whatever_the_capacity() {
+    let pool = common::sqlite_pool();
+    pool.batch_execute_async(&common::insert_users(100))
+        .await
+        .unwrap();
+
+    for &capacity in &[0, 10, 100, 1000] {
+        let ids = users::table
+            .select(users::id)
+            .order(users::id)
+            .load_with_capacity_async::<i32>(capacity, &pool)
+            .await
+            .unwrap();
+        assert_eq!(ids, (1..=100).collect::<Vec<_>>());
+        // A large enough `Vec` is never reallocated
+        if capacity >= 100 {
+            assert_eq!(ids.capacity(), capacity);
+        }
+    }
+
+    let result = users::table
+        .select(sql::<Integer>("CASE WHEN id = 50 THEN NULL ELSE id END"))
+        .load_with_capacity_async::<i32>(100, &pool)
+        .await;
+    assert!(matches!(
+        result,
+        Err(AsyncError::Error(Error::DeserializationError(_)))
+    ));
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn spawned_writes_run_whether_or_not_their_handle_is_awaited() {
+    let pool = common::sqlite_pool();
+    pool.batch_execute_async(&common::insert_users(3))
+        .await
+        .unwrap();
+
+    let handle = diesel::update(users::table)
+        .set(users::name.eq("renamed"))
+        .spawn_execute_async(&pool);
+    assert_eq!(handle.await.unwrap(), 3);
+
+    drop(pool.spawn_transaction(|conn| diesel::delete(users::table.find(1)).execute(conn)));
+    while users::table.count_async(&pool).await.unwrap() == 3 {
+        delay_for(Duration::from_millis(1)).await;
+    }
+    let names = users::table
+        .select(users::name)
+        .load_async::<String>(&pool)
+        .await
+        .unwrap();
+    assert_eq!(names, ["renamed", "renamed"]);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn raw_sql_loads_into_a_struct_and_counts_the_updated_rows() {
+    let pool = common::sqlite_pool();
+    pool.batch_execute_async(&common::insert_users(5))
+        .await
+        .unwrap();
+
+    let users: Vec<User> = sql_query_async(
+        diesel::sql_query("SELECT id, name FROM users WHERE id > ? ORDER BY id")
+            .bind::<Integer, _>(3),
+        &pool,
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        users,
+        [
+            User {
+                id: 4,
+                name: "user4".to_string()
+            },
+            User {
+                id: 5,
+                name: "user5".to_string()
+            },
+        ]
+    );
+
+    let updated = sql_execute_async(
+        diesel::sql_query("UPDATE users SET name = ? WHERE id <= ?")
+            .bind::<Text, _>("renamed")
+            .bind::<Integer, _>(2),
+        &pool,
+    )
+    .await
+    .unwrap();
+    assert_eq!(updated, 2);
+    let renamed = users::table
+        .filter(users::name.eq("renamed"))
+        .count_async(&pool)
+        .await
+        .unwrap();
+    assert_eq!(renamed, 2);
+}
+
+// A user name validated on load, made of lowercase letters and digits only
+#[derive(Debug, PartialEq)]
+struct Username(String);
+
+impl TryFrom<User> for Username {
+    type Error = String;
+
+    fn try_from(user: User) -> Result<Self, String> {
+        if user
+            .name
+            .chars()
+            .all(|c| c.is_lowercase() || c.is_ascii_digit())
+        {
+            Ok(Username(user.name))
+        } else {
+            Err(format!("invalid user name {:?}", user.name))
+        }
+    }
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn load_into_async_fails_on_a_row_that_does_not_convert() {
+    let pool = common::sqlite_pool();
+    pool.batch_execute_async(&common::insert_users(3))
+        .await
+        .unwrap();
+
+    let names = users::table
+        .order(users::id)
+        .load_into_async::<User, Username>(&pool)
+        .await
+        .unwrap();
+    assert_eq!(names[0], Username("user1".to_string()));
+    assert_eq!(names.len(), 3);
+
+    diesel::update(users::table.find(2))
+        .set(users::name.eq("Grace"))
+        .execute_async(&pool)
+        .await
+        .unwrap();
+    let err = users::table
+        .load_into_async::<User, Username>(&pool)
+        .await
+        .unwrap_err();
+    match err {
+        AsyncError::Conversion(err) => assert_eq!(err.to_string(), "invalid user name \"Grace\""),
+        err => panic!("unexpected error: {:?}", err),
+    }
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn load_to_sink_async_forwards_every_row_to_the_sink() {
+    let pool = common::sqlite_pool();
+    pool.batch_execute_async(&common::insert_users(100))
+        .await
+        .unwrap();
+
+    let mut ids = Vec::new();
+    let forwarded = users::table
+        .select(users::id)
+        .order(users::id)
+        .load_to_sink_async::<i32, _>(&mut ids, &pool)
+        .await
+        .unwrap();
+    assert_eq!(forwarded, 100);
+    assert_eq!(ids, (1..=100).collect::<Vec<_>>());
+
+    let (sender, receiver) = futures::channel::mpsc::channel::<i32>(1);
+    drop(receiver);
+    let err = users::table
+        .select(users::id)
+        .load_to_sink_async::<i32, _>(sender, &pool)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, AsyncError::Sink(_)), "{:?}", err);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn run_async_discards_the_count_but_not_the_error() {
+    let pool = common::sqlite_pool();
+
+    diesel::insert_into(users::table)
+        .values((users::id.eq(1), users::name.eq("Ada")))
+        .run_async(&pool)
+        .await
+        .unwrap();
+    assert_eq!(users::table.count_async(&pool).await.unwrap(), 1);
+
+    let err = diesel::sql_query("INSERT INTO no_such_table VALUES (1)")
+        .run_async(&pool)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, AsyncError::Error(_)), "{:?}", err);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn load_unique_by_async_keeps_the_first_row_of_every_parent_of_a_join() {
+    let pool = common::sqlite_pool();
+    pool.batch_execute_async(&common::insert_users(3))
+        .await
+        .unwrap();
+    pool.batch_execute_async(
+        "CREATE TABLE posts (id INTEGER PRIMARY KEY, user_id INTEGER NOT NULL); \
+         INSERT INTO posts (user_id) VALUES (3), (1), (3), (1), (3)",
+    )
+    .await
+    .unwrap();
+
+    let users = diesel::sql_query(
+        "SELECT users.id, users.name FROM users JOIN posts ON posts.user_id = users.id \
+         ORDER BY posts.id",
+    )
+    .load_unique_by_async(|user: &User| user.id, &pool)
+    .await
+    .unwrap();
+    let ids: Vec<_> = users.iter().map(|user| user.id).collect();
+    assert_eq!(ids, [3, 1]);
+}
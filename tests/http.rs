@@ -0,0 +1,70 @@
+#![cfg(feature = "http")]
+
+#[macro_use]
+extern crate diesel;
+
+mod common;
+
+use common::users;
+use diesel::{
+    prelude::*,
+    result::{DatabaseErrorKind, Error},
+};
+use std::time::Duration;
+use tokio_diesel::*;
+
+fn database_error(kind: DatabaseErrorKind) -> AsyncError {
+    AsyncError::Error(Error::DatabaseError(kind, Box::new("rejected".to_string())))
+}
+
+#[test]
+fn errors_map_to_http_statuses() {
+    assert_eq!(AsyncError::NotFound.to_http_status(), 404);
+    assert_eq!(AsyncError::Error(Error::NotFound).to_http_status(), 404);
+    assert_eq!(
+        database_error(DatabaseErrorKind::UniqueViolation).to_http_status(),
+        409
+    );
+    assert_eq!(
+        database_error(DatabaseErrorKind::ForeignKeyViolation).to_http_status(),
+        409
+    );
+    assert_eq!(
+        database_error(DatabaseErrorKind::SerializationFailure).to_http_status(),
+        409
+    );
+    assert_eq!(
+        database_error(DatabaseErrorKind::UnableToSendCommand).to_http_status(),
+        500
+    );
+    assert_eq!(
+        AsyncError::Timeout(Duration::from_secs(1)).to_http_status(),
+        503
+    );
+    assert_eq!(AsyncError::ShuttingDown.to_http_status(), 503);
+    assert_eq!(AsyncError::NoConnectionAvailable.to_http_status(), 503);
+    assert_eq!(
+        AsyncError::Error(Error::RollbackTransaction).to_http_status(),
+        500
+    );
+
+    // The status of a labeled error is that of the error it wraps
+    let labeled = AsyncError::Labeled(
+        "insert_user",
+        Box::new(database_error(DatabaseErrorKind::UniqueViolation)),
+    );
+    assert_eq!(labeled.to_http_status(), 409);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn a_duplicate_row_maps_to_conflict() {
+    let pool = common::sqlite_pool();
+    let insert = || {
+        diesel::insert_into(users::table)
+            .values((users::id.eq(1), users::name.eq("ada")))
+            .execute_async(&pool)
+    };
+
+    insert().await.unwrap();
+    assert_eq!(insert().await.unwrap_err().to_http_status(), 409);
+}
@@ -0,0 +1,64 @@
+// With `tokio-rt-threaded` the work runs on the awaiting task instead of queueing for a blocking
+// thread, so there is no queue time to record
+#![cfg(all(feature = "metrics", not(feature = "tokio-rt-threaded")))]
+
+#[macro_use]
+extern crate diesel;
+
+mod common;
+
+use common::users;
+use metrics::{Counter, Gauge, Histogram, HistogramFn, Key, KeyName, Metadata, SharedString, Unit};
+use std::sync::{Arc, Mutex};
+use tokio_diesel::*;
+
+// The values recorded into the histogram of the blocking queue time
+#[derive(Default)]
+struct QueueSeconds(Mutex<Vec<f64>>);
+
+impl HistogramFn for QueueSeconds {
+    fn record(&self, value: f64) {
+        self.0.lock().unwrap().push(value);
+    }
+}
+
+// Hands out the histogram of the blocking queue time, installed as the global recorder since the
+// blocking work runs on threads of its own
+struct Recorder(Arc<QueueSeconds>);
+
+impl metrics::Recorder for Recorder {
+    fn describe_counter(&self, _key: KeyName, _unit: Option<Unit>, _description: SharedString) {}
+
+    fn describe_gauge(&self, _key: KeyName, _unit: Option<Unit>, _description: SharedString) {}
+
+    fn describe_histogram(&self, _key: KeyName, _unit: Option<Unit>, _description: SharedString) {}
+
+    fn register_counter(&self, _key: &Key, _metadata: &Metadata<'_>) -> Counter {
+        Counter::noop()
+    }
+
+    fn register_gauge(&self, _key: &Key, _metadata: &Metadata<'_>) -> Gauge {
+        Gauge::noop()
+    }
+
+    fn register_histogram(&self, key: &Key, _metadata: &Metadata<'_>) -> Histogram {
+        match key.name() {
+            "tokio_diesel_blocking_queue_seconds" => Histogram::from_arc(self.0.clone()),
+            _ => Histogram::noop(),
+        }
+    }
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn the_blocking_queue_time_is_recorded() {
+    let queued = Arc::new(QueueSeconds::default());
+    metrics::set_global_recorder(Recorder(queued.clone())).unwrap();
+    let pool = common::sqlite_pool();
+
+    users::table.count_async(&pool).await.unwrap();
+    users::table.count_async(&pool).await.unwrap();
+
+    let seconds = queued.0.lock().unwrap();
+    assert_eq!(seconds.len(), 2);
+    assert!(seconds.iter().all(|&seconds| seconds >= 0.0));
+}
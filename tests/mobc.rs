@@ -0,0 +1,37 @@
+#![cfg(feature = "mobc")]
+
+#[macro_use]
+extern crate diesel;
+
+mod common;
+
+use common::{users, User};
+use diesel::{prelude::*, sqlite::SqliteConnection};
+use tokio_diesel::*;
+
+// A pool of a single in-memory SQLite connection, holding an empty `users` table
+async fn pool() -> mobc::Pool<MobcManager<SqliteConnection>> {
+    let pool = mobc::Pool::builder()
+        .max_open(1)
+        .build(MobcManager::new(":memory:"));
+    pool.batch_execute_async("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT NOT NULL)")
+        .await
+        .unwrap();
+    pool
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn rows_load_through_a_mobc_pool() {
+    let pool = pool().await;
+    pool.batch_execute_async(&common::insert_users(3))
+        .await
+        .unwrap();
+
+    let users: Vec<User> = users::table
+        .order(users::id)
+        .load_async(&pool)
+        .await
+        .unwrap();
+    let names: Vec<_> = users.iter().map(|user| &*user.name).collect();
+    assert_eq!(names, ["user1", "user2", "user3"]);
+}
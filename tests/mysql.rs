@@ -0,0 +1,55 @@
+#![cfg(feature = "mysql")]
+
+#[macro_use]
+extern crate diesel;
+
+mod common;
+
+use common::users;
+use diesel::{
+    mysql::MysqlConnection,
+    prelude::*,
+    r2d2::{ConnectionManager, Pool},
+};
+use std::env;
+use tokio_diesel::*;
+
+// A pool of a single MySQL connection, holding an empty temporary `users` table that no other
+// test sees
+async fn pool() -> Pool<ConnectionManager<MysqlConnection>> {
+    let url =
+        env::var("MYSQL_DATABASE_URL").expect("MYSQL_DATABASE_URL must point to a MySQL database");
+    let pool = Pool::builder()
+        .max_size(1)
+        .build(ConnectionManager::<MysqlConnection>::new(url))
+        .unwrap();
+    pool.batch_execute_async(
+        "CREATE TEMPORARY TABLE users (id INTEGER PRIMARY KEY, name TEXT NOT NULL)",
+    )
+    .await
+    .unwrap();
+    pool
+}
+
+#[tokio::test(threaded_scheduler)]
+#[ignore = "needs MySQL at MYSQL_DATABASE_URL"]
+async fn upsert_async_updates_the_existing_row() {
+    let pool = pool().await;
+    let upsert = |name: &'static str| {
+        upsert_async(
+            diesel::insert_into(users::table).values((users::id.eq(1), users::name.eq(name))),
+            users::name.eq(name),
+            &pool,
+        )
+    };
+
+    assert_eq!(upsert("ada").await.unwrap(), 1);
+    assert_eq!(upsert("grace").await.unwrap(), 2);
+
+    let names: Vec<String> = users::table
+        .select(users::name)
+        .load_async(&pool)
+        .await
+        .unwrap();
+    assert_eq!(names, ["grace"]);
+}
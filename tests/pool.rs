@@ -0,0 +1,482 @@
+#[macro_use]
+extern crate diesel;
+
+mod common;
+
+use common::users;
+use diesel::{
+    dsl::sql,
+    pg::PgConnection,
+    prelude::*,
+    r2d2::{ConnectionManager, Pool},
+    sql_types::Text,
+    sqlite::SqliteConnection,
+};
+use futures::future::{BoxFuture, FutureExt};
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use tokio_diesel::*;
+
+// A pool of `size` in-memory SQLite connections, each with a database of its own
+fn sqlite_pool(size: u32) -> Pool<ConnectionManager<SqliteConnection>> {
+    Pool::builder()
+        .max_size(size)
+        .build(ConnectionManager::<SqliteConnection>::new(":memory:"))
+        .unwrap()
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn with_concurrency_bounds_the_blocking_tasks() {
+    let pool = AsyncPool::with_concurrency(sqlite_pool(4), 2).unwrap();
+    let running = Arc::new(AtomicUsize::new(0));
+    let most = Arc::new(AtomicUsize::new(0));
+
+    let tasks: Vec<_> = (0..6)
+        .map(|_| {
+            let (pool, running, most) = (pool.clone(), running.clone(), most.clone());
+            tokio::spawn(async move {
+                pool.run(move |_| {
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    most.fetch_max(now, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(50));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                    Ok(())
+                })
+                .await
+            })
+        })
+        .collect();
+    for task in tasks {
+        task.await.unwrap().unwrap();
+    }
+    assert!(most.load(Ordering::SeqCst) <= 2);
+
+    let result = AsyncPool::with_concurrency(sqlite_pool(1), 0);
+    assert!(matches!(result, Err(BuildError::InvalidConfig(_))));
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn the_builder_sets_up_the_pool_and_its_default_timeout() {
+    let pool = AsyncPool::<SqliteConnection>::builder()
+        .max_size(2)
+        .concurrency(1)
+        .timeout(Duration::from_millis(50))
+        .build(":memory:")
+        .unwrap();
+    assert_eq!(pool.pool().max_size(), 2);
+
+    pool.ping_async().await.unwrap();
+    let result = pool
+        .run(|_| {
+            thread::sleep(Duration::from_millis(200));
+            Ok(())
+        })
+        .await;
+    // With `tokio-rt-threaded` the work runs on the awaiting task and cannot be interrupted
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    assert!(matches!(result, Err(AsyncError::Timeout(_))));
+    #[cfg(feature = "tokio-rt-threaded")]
+    assert!(result.is_ok());
+
+    let result = AsyncPool::<SqliteConnection>::builder()
+        .max_size(0)
+        .build(":memory:");
+    assert!(matches!(result, Err(BuildError::InvalidConfig(_))));
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn on_checkout_is_called_once_per_run() {
+    let checkouts = Arc::new(Mutex::new(Vec::new()));
+    let checkouts_ = checkouts.clone();
+    let pool = AsyncPool::<SqliteConnection>::builder()
+        .max_size(1)
+        .on_checkout(move |info| checkouts_.lock().unwrap().push(info))
+        .build(":memory:")
+        .unwrap();
+
+    for _ in 0..3 {
+        pool.ping_async().await.unwrap();
+    }
+    let checkouts = checkouts.lock().unwrap();
+    let fresh: Vec<_> = checkouts.iter().map(|info| info.fresh).collect();
+    assert_eq!(fresh, [true, false, false]);
+    assert!(checkouts[2].age >= checkouts[1].age);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn shutdown_waits_for_the_runs_in_flight_and_rejects_later_ones() {
+    let pool = AsyncPool::with_concurrency(sqlite_pool(1), 1).unwrap();
+    let started = Arc::new(AtomicBool::new(false));
+    let finished = Arc::new(AtomicBool::new(false));
+
+    let (pool_, started_, finished_) = (pool.clone(), started.clone(), finished.clone());
+    let in_flight = tokio::spawn(async move {
+        pool_
+            .run(move |_| {
+                started_.store(true, Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(100));
+                finished_.store(true, Ordering::SeqCst);
+                Ok("done")
+            })
+            .await
+    });
+    while !started.load(Ordering::SeqCst) {
+        tokio::time::delay_for(Duration::from_millis(1)).await;
+    }
+
+    pool.shutdown(Duration::from_secs(1)).await.unwrap();
+    assert!(finished.load(Ordering::SeqCst));
+    assert_eq!(in_flight.await.unwrap().unwrap(), "done");
+    let result = pool.run(|_| Ok(())).await;
+    assert!(matches!(result, Err(AsyncError::ShuttingDown)));
+}
+
+#[tokio::test(threaded_scheduler)]
+#[ignore = "needs Postgres at DATABASE_URL"]
+async fn on_acquire_sets_up_every_new_connection() {
+    let pool = AsyncPool::<PgConnection>::builder()
+        .max_size(1)
+        .on_acquire(&["SET search_path TO tokio_diesel, public"])
+        .build(common::database_url())
+        .unwrap();
+
+    let search_path = pool
+        .run(|conn| {
+            diesel::select(sql::<Text>("current_setting('search_path')")).get_result::<String>(conn)
+        })
+        .await
+        .unwrap();
+    assert_eq!(search_path, "tokio_diesel, public");
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn a_failing_on_acquire_statement_fails_the_build() {
+    let result = AsyncPool::<SqliteConnection>::builder()
+        .max_size(1)
+        .connection_timeout(Duration::from_millis(100))
+        .on_acquire(&["SELEC 1"])
+        .build(":memory:");
+    assert!(matches!(result, Err(BuildError::Connection(_))));
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn warm_up_opens_the_connections_ahead_of_the_first_run() {
+    let pool = Pool::builder()
+        .max_size(4)
+        .min_idle(Some(0))
+        .build(ConnectionManager::<SqliteConnection>::new(":memory:"))
+        .unwrap();
+    let pool = AsyncPool::with_concurrency(pool, 4).unwrap();
+    assert_eq!(pool.pool_state_async().await.unwrap().connections, 0);
+
+    pool.warm_up(3).await.unwrap();
+    let state = pool.pool_state_async().await.unwrap();
+    assert!(state.connections >= 3);
+    assert_eq!(state.idle_connections, state.connections);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn a_split_pool_routes_reads_to_the_replicas_and_writes_to_the_primary() {
+    // Every pool has a database of its own, holding a user named after it
+    let pools: Vec<_> = ["primary", "replica1", "replica2"]
+        .iter()
+        .map(|name| {
+            let pool = common::sqlite_pool();
+            diesel::insert_into(users::table)
+                .values(users::name.eq(name))
+                .execute(&pool.get().unwrap())
+                .unwrap();
+            pool
+        })
+        .collect();
+    let mut pools = pools.into_iter();
+    let split = SplitPool::new(pools.next().unwrap(), pools.collect());
+    let name = || users::table.select(users::name);
+
+    let mut reads = Vec::new();
+    for _ in 0..4 {
+        reads.push(name().first_async::<String>(&split).await.unwrap());
+    }
+    assert_eq!(reads, ["replica1", "replica2", "replica1", "replica2"]);
+
+    // Every other method loading rows reads from the replicas as well
+    let first = |names: Vec<String>| names.into_iter().next().unwrap();
+    let reads = [
+        name().get_result_async::<String>(&split).await.unwrap(),
+        first(name().get_results_async(&split).await.unwrap()),
+        name()
+            .get_result_opt_async::<String>(&split)
+            .await
+            .unwrap()
+            .unwrap(),
+        first(name().load_labeled_async("names", &split).await.unwrap()),
+        name()
+            .get_result_labeled_async::<String>("name", &split)
+            .await
+            .unwrap(),
+    ];
+    assert_eq!(
+        reads,
+        ["replica1", "replica2", "replica1", "replica2", "replica1"]
+    );
+
+    diesel::insert_into(users::table)
+        .values(users::name.eq("written"))
+        .execute_async(&split)
+        .await
+        .unwrap();
+    let in_transaction = split
+        .transaction(|conn| users::table.count().get_result::<i64>(conn))
+        .await
+        .unwrap();
+    assert_eq!(in_transaction, 2);
+    let names = name().load_async::<String>(split.primary()).await.unwrap();
+    assert_eq!(names, ["primary", "written"]);
+    assert_eq!(users::table.count_async(&split).await.unwrap(), 1);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn an_expired_deadline_fails_before_running_the_operation() {
+    let pool = AsyncPool::with_concurrency(sqlite_pool(1), 1).unwrap();
+    let ran = Arc::new(AtomicBool::new(false));
+
+    let ran_ = ran.clone();
+    let expired = pool.with_deadline(Instant::now() - Duration::from_millis(1));
+    let result = expired
+        .run(move |_| {
+            ran_.store(true, Ordering::SeqCst);
+            Ok(())
+        })
+        .await;
+    assert!(matches!(result, Err(AsyncError::Timeout(_))));
+    assert!(!ran.load(Ordering::SeqCst));
+
+    let result = pool
+        .with_deadline(Instant::now() + Duration::from_millis(50))
+        .run(|_| {
+            thread::sleep(Duration::from_millis(200));
+            Ok(())
+        })
+        .await;
+    // With `tokio-rt-threaded` the work runs on the awaiting task and cannot be interrupted
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    assert!(matches!(result, Err(AsyncError::Timeout(_))));
+    #[cfg(feature = "tokio-rt-threaded")]
+    assert!(result.is_ok());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn instrumentation_sees_every_operation_start_and_finish() {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let events_ = events.clone();
+    let pool = AsyncPool::<SqliteConnection>::builder()
+        .max_size(1)
+        .instrumentation(move |event| {
+            let event = match *event {
+                InstrumentationEvent::Started { op } => (op, "started", false),
+                InstrumentationEvent::Finished { op, error, .. } => {
+                    (op, "finished", error.is_some())
+                }
+            };
+            events_.lock().unwrap().push(event);
+        })
+        .build(":memory:")
+        .unwrap();
+
+    pool.run(|_| Ok(())).await.unwrap();
+    pool.run(|conn| diesel::sql_query("SELEC 1").execute(conn))
+        .await
+        .unwrap_err();
+    pool.batch_execute_async(common::USERS).await.unwrap();
+
+    let events = events.lock().unwrap();
+    assert_eq!(events.len(), 6);
+    assert_eq!(
+        events[..4],
+        [
+            ("run", "started", false),
+            ("run", "finished", false),
+            ("run", "started", false),
+            ("run", "finished", true),
+        ]
+    );
+    assert_eq!(events[4].1, "started");
+    assert_eq!(events[5], (events[4].0, "finished", false));
+}
+
+// Hands the blocking work to the blocking thread pool of the runtime, counting it
+#[derive(Clone, Default)]
+struct CountingExecutor(Arc<AtomicUsize>);
+
+impl BlockingExecutor for CountingExecutor {
+    fn spawn_blocking<F, R>(&self, f: F) -> BoxFuture<'static, R>
+    where
+        F: 'static + FnOnce() -> R + Send,
+        R: 'static + Send,
+    {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        tokio::task::spawn_blocking(f)
+            .map(|result| result.unwrap())
+            .boxed()
+    }
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn a_custom_executor_runs_the_blocking_work() {
+    let executor = CountingExecutor::default();
+    let pool = AsyncPool::<SqliteConnection>::builder()
+        .max_size(1)
+        .executor(executor.clone())
+        .build(":memory:")
+        .unwrap();
+
+    pool.batch_execute_async(common::USERS).await.unwrap();
+    for _ in 0..3 {
+        users::table.count_async(&pool).await.unwrap();
+    }
+    pool.warm_up(1).await.unwrap();
+    // With `tokio-rt-threaded` the queries run on the awaiting task, only the batch and the
+    // warm-up go through the executor
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    assert_eq!(executor.0.load(Ordering::SeqCst), 5);
+    #[cfg(feature = "tokio-rt-threaded")]
+    assert_eq!(executor.0.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn on_new_connection_is_called_once_per_opened_connection() {
+    let opened = Arc::new(AtomicUsize::new(0));
+    let opened_ = opened.clone();
+    let pool = AsyncPool::<SqliteConnection>::builder()
+        .max_size(3)
+        .on_new_connection(move || {
+            opened_.fetch_add(1, Ordering::SeqCst);
+        })
+        .build(":memory:")
+        .unwrap();
+
+    pool.warm_up(3).await.unwrap();
+    for _ in 0..5 {
+        pool.ping_async().await.unwrap();
+    }
+    let connections = pool.pool_state_async().await.unwrap().connections;
+    assert_eq!(connections, 3);
+    assert_eq!(opened.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn retry_on_checkout_rides_out_a_database_restart() {
+    let build = |retries| {
+        AsyncPool::<common::TestConnection>::builder()
+            .max_size(1)
+            .connection_timeout(Duration::from_millis(100))
+            .retry_on_checkout(retries, Duration::from_millis(50))
+            .build(":memory:")
+            .unwrap()
+    };
+    let (pool, retrying) = (build(0), build(5));
+
+    // The idle connections fail their validity check and no new ones can be opened meanwhile
+    common::take_down(Duration::from_millis(300));
+    let result = pool.run(|_| Ok(())).await;
+    assert!(matches!(result, Err(AsyncError::Checkout(_))));
+    retrying.run(|_| Ok(())).await.unwrap();
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn handles_carry_their_own_timeout_and_label() {
+    let pool = AsyncPool::with_concurrency(sqlite_pool(2), 2).unwrap();
+    let impatient = pool.handle().timeout(Duration::from_millis(50));
+    let patient = pool
+        .handle()
+        .timeout(Duration::from_secs(5))
+        .label("patient");
+    let sleep = |_: &SqliteConnection| {
+        thread::sleep(Duration::from_millis(200));
+        Ok(())
+    };
+
+    let result = impatient.run(sleep).await;
+    // With `tokio-rt-threaded` the work runs on the awaiting task and cannot be interrupted
+    #[cfg(not(feature = "tokio-rt-threaded"))]
+    assert!(matches!(result, Err(AsyncError::Timeout(_))));
+    #[cfg(feature = "tokio-rt-threaded")]
+    assert!(result.is_ok());
+    patient.run(sleep).await.unwrap();
+
+    let err = patient
+        .run(|conn| diesel::sql_query("SELEC 1").execute(conn))
+        .await
+        .unwrap_err();
+    assert!(err.to_string().starts_with("patient: "));
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn building_against_an_unreachable_database_fails_with_a_connection_error() {
+    let result = AsyncPool::<PgConnection>::builder()
+        .max_size(1)
+        .connection_timeout(Duration::from_millis(200))
+        .build("postgres://postgres@127.0.0.1:1/unreachable");
+    let err = match result {
+        Err(err @ BuildError::Connection(_)) => err,
+        Err(err) => panic!("unexpected error: {}", err),
+        Ok(_) => panic!("built a pool for an unreachable database"),
+    };
+    assert!(
+        err.to_string()
+            .starts_with("failed to establish the initial connections: "),
+        "{}",
+        err
+    );
+
+    let result = AsyncPool::<SqliteConnection>::builder()
+        .concurrency(0)
+        .build(":memory:");
+    assert!(matches!(result, Err(BuildError::InvalidConfig(_))));
+}
+
+// The service code under test, which only knows of the pool it was given
+async fn add_user(pool: &AsyncPool<SqliteConnection>, name: &'static str) -> AsyncResult<usize> {
+    diesel::insert_into(users::table)
+        .values(users::name.eq(name))
+        .execute_async(pool)
+        .await
+}
+
+async fn user_names(pool: &AsyncPool<SqliteConnection>) -> AsyncResult<Vec<String>> {
+    users::table
+        .select(users::name)
+        .order(users::id)
+        .load_async(pool)
+        .await
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn scoped_redirects_the_operations_of_the_task_to_another_pool() {
+    let production = AsyncPool::with_concurrency(sqlite_pool(1), 1).unwrap();
+    let throwaway = AsyncPool::with_concurrency(sqlite_pool(1), 1).unwrap();
+    for pool in &[&production, &throwaway] {
+        pool.batch_execute_async(common::USERS).await.unwrap();
+    }
+
+    AsyncPool::scoped(throwaway.clone(), async {
+        add_user(&production, "Ada").await.unwrap();
+        // A task spawned within the scope is not redirected
+        let spawned = production.clone();
+        tokio::spawn(async move { add_user(&spawned, "Grace").await })
+            .await
+            .unwrap()
+            .unwrap();
+    })
+    .await;
+    add_user(&production, "Alan").await.unwrap();
+
+    assert_eq!(user_names(&throwaway).await.unwrap(), ["Ada"]);
+    assert_eq!(user_names(&production).await.unwrap(), ["Grace", "Alan"]);
+}
@@ -0,0 +1,484 @@
+#![cfg(feature = "postgres")]
+
+#[macro_use]
+extern crate diesel;
+
+mod common;
+
+use common::{accounts, users, Account, User};
+use diesel::{
+    connection::SimpleConnection,
+    pg::{upsert::excluded, PgConnection},
+    prelude::*,
+    r2d2::{ConnectionManager, Pool},
+    sql_types::{Integer, Text},
+};
+use futures::stream::TryStreamExt;
+use std::time::{Duration, Instant};
+use tokio_diesel::*;
+
+// A pool of `size` Postgres connections
+fn pg_pool(size: u32) -> Pool<ConnectionManager<PgConnection>> {
+    Pool::builder()
+        .max_size(size)
+        .build(ConnectionManager::<PgConnection>::new(
+            common::database_url(),
+        ))
+        .unwrap()
+}
+
+#[tokio::test(threaded_scheduler)]
+#[ignore = "needs Postgres at DATABASE_URL"]
+async fn run_cancellable_returns_the_result_of_a_completed_query() {
+    let pool = pg_pool(2);
+
+    let sum = run_cancellable(&pool, |conn| {
+        diesel::select(1.into_sql::<Integer>() + 1).get_result::<i32>(conn)
+    })
+    .await
+    .unwrap();
+    assert_eq!(sum, 2);
+}
+
+// With `tokio-rt-threaded` the query blocks the awaiting task, so it cannot be dropped
+#[cfg(not(feature = "tokio-rt-threaded"))]
+#[tokio::test(threaded_scheduler)]
+#[ignore = "needs Postgres at DATABASE_URL"]
+async fn dropping_run_cancellable_frees_the_connection() {
+    let pool = pg_pool(2);
+
+    let start = Instant::now();
+    let sleep = run_cancellable(&pool, |conn| {
+        diesel::sql_query("SELECT pg_sleep(10)").execute(conn)
+    });
+    tokio::time::timeout(Duration::from_millis(200), sleep)
+        .await
+        .unwrap_err();
+
+    while pool.state().idle_connections < 2 {
+        assert!(start.elapsed() < Duration::from_secs(5));
+        tokio::time::delay_for(Duration::from_millis(10)).await;
+    }
+    pool.ping_async().await.unwrap();
+}
+
+#[tokio::test(threaded_scheduler)]
+#[ignore = "needs Postgres at DATABASE_URL"]
+async fn concurrent_first_or_insert_async_callers_get_the_same_row() {
+    let pool = pg_pool(2);
+    pool.get().unwrap().batch_execute(common::ACCOUNTS).unwrap();
+    let email = format!("{}@example.com", uuid::Uuid::new_v4());
+
+    // Both callers find no row before either insert commits, so one of them loses the race
+    let callers: Vec<_> = (0..2)
+        .map(|_| {
+            let (pool, email) = (pool.clone(), email.clone());
+            tokio::spawn(async move {
+                let find = accounts::table.filter(accounts::email.eq(email.clone()));
+                let insert = diesel::sql_query(
+                    "INSERT INTO accounts (email) SELECT $1 FROM pg_sleep(0.2) \
+                     RETURNING id, email",
+                )
+                .bind::<Text, _>(email);
+                first_or_insert_async::<Account, _, _, _, _>(find, insert, &pool).await
+            })
+        })
+        .collect();
+    let mut accounts = Vec::new();
+    for caller in callers {
+        accounts.push(caller.await.unwrap().unwrap());
+    }
+    assert_eq!(accounts[0], accounts[1]);
+    assert_eq!(accounts[0].email, email);
+
+    let count = accounts::table
+        .filter(accounts::email.eq(email.clone()))
+        .count_async(&pool)
+        .await
+        .unwrap();
+    assert_eq!(count, 1);
+    diesel::delete(accounts::table.filter(accounts::email.eq(email)))
+        .execute_async(&pool)
+        .await
+        .unwrap();
+}
+
+#[tokio::test(threaded_scheduler)]
+#[ignore = "needs Postgres at DATABASE_URL"]
+async fn cursor_stream_async_yields_every_row_a_batch_at_a_time() {
+    let pool = common::pg_pool();
+    pool.batch_execute_async(&common::insert_users(50_000))
+        .await
+        .unwrap();
+
+    let ids: Vec<i32> = users::table
+        .select(users::id)
+        .order(users::id)
+        .cursor_stream_async::<i32>(500, &pool)
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(ids, (1..=50_000).collect::<Vec<_>>());
+
+    // The connection was handed back once the last batch was fetched
+    assert_eq!(users::table.count_async(&pool).await.unwrap(), 50_000);
+}
+
+#[tokio::test(threaded_scheduler)]
+#[ignore = "needs Postgres at DATABASE_URL"]
+async fn execute_many_async_runs_the_inserts_concurrently() {
+    let pool = pg_pool(4);
+    pool.get().unwrap().batch_execute(common::ACCOUNTS).unwrap();
+    let prefix = uuid::Uuid::new_v4().to_string();
+
+    // Every insert takes 200ms, so running them one after the other would take 2s
+    let inserts = (0..10)
+        .map(|i| {
+            boxed_execute(
+                diesel::sql_query("INSERT INTO accounts (email) SELECT $1 FROM pg_sleep(0.2)")
+                    .bind::<Text, _>(format!("{}-{}@example.com", prefix, i)),
+            )
+        })
+        .collect();
+    let start = Instant::now();
+    let results = execute_many_async(inserts, &pool).await;
+    assert!(start.elapsed() < Duration::from_millis(1500));
+    assert_eq!(results.len(), 10);
+    for result in results {
+        assert_eq!(result.unwrap(), 1);
+    }
+
+    let ours = || accounts::table.filter(accounts::email.like(format!("{}-%", prefix)));
+    assert_eq!(ours().count_async(&pool).await.unwrap(), 10);
+    diesel::delete(ours()).execute_async(&pool).await.unwrap();
+}
+
+#[tokio::test(threaded_scheduler)]
+#[ignore = "needs Postgres at DATABASE_URL"]
+async fn upsert_on_conflict_async_updates_the_existing_row() {
+    let pool = common::pg_pool();
+    pool.batch_execute_async(&common::insert_users(2))
+        .await
+        .unwrap();
+    let upsert = |id: i32, name: &str| {
+        diesel::insert_into(users::table)
+            .values((users::id.eq(id), users::name.eq(name.to_string())))
+            .on_conflict(users::id)
+    };
+
+    let count = upsert_on_conflict_async(
+        upsert(1, "ada"),
+        users::name.eq(excluded(users::name)),
+        &pool,
+    )
+    .await
+    .unwrap();
+    assert_eq!(count, 1);
+    let rows: Vec<User> = upsert_on_conflict_returning_async(
+        upsert(3, "grace"),
+        users::name.eq(excluded(users::name)),
+        &pool,
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        rows,
+        [User {
+            id: 3,
+            name: "grace".to_string()
+        }]
+    );
+
+    let names = users::table
+        .select(users::name)
+        .order(users::id)
+        .load_async::<String>(&pool)
+        .await
+        .unwrap();
+    assert_eq!(names, ["ada", "user2", "grace"]);
+}
+
+#[tokio::test(threaded_scheduler)]
+#[ignore = "needs Postgres at DATABASE_URL"]
+async fn insert_ignore_async_skips_the_conflicting_rows() {
+    let pool = common::pg_pool();
+    pool.batch_execute_async(&common::insert_users(2))
+        .await
+        .unwrap();
+
+    let insert = diesel::insert_into(users::table).values(vec![
+        (users::id.eq(2), users::name.eq("ada")),
+        (users::id.eq(3), users::name.eq("grace")),
+    ]);
+    assert_eq!(insert_ignore_async(insert, &pool).await.unwrap(), 1);
+
+    let names = users::table
+        .select(users::name)
+        .order(users::id)
+        .load_async::<String>(&pool)
+        .await
+        .unwrap();
+    assert_eq!(names, ["user1", "user2", "grace"]);
+}
+
+#[tokio::test(threaded_scheduler)]
+#[ignore = "needs Postgres at DATABASE_URL"]
+async fn count_estimate_async_is_close_to_the_count_after_analyze() {
+    let pool = common::pg_pool();
+    pool.batch_execute_async(&common::insert_users(10_000))
+        .await
+        .unwrap();
+    pool.batch_execute_async("ANALYZE users").await.unwrap();
+
+    let estimate = count_estimate_async("users", &pool).await.unwrap();
+    assert!(
+        (9_000..=11_000).contains(&estimate),
+        "estimate: {}",
+        estimate
+    );
+
+    let result = count_estimate_async("no_such_table", &pool).await;
+    assert!(matches!(result, Err(AsyncError::Error(_))));
+}
+
+#[tokio::test(threaded_scheduler)]
+#[ignore = "needs Postgres at DATABASE_URL"]
+async fn query_dynamic_async_loads_the_columns_by_name() {
+    let pool = pg_pool(1);
+
+    let rows = query_dynamic_async("SELECT 1 AS a, 'x' AS b", Vec::new(), &pool)
+        .await
+        .unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].get("a"), Some(&DynamicValue::Int(1)));
+    assert_eq!(rows[0].get("b"), Some(&DynamicValue::Text("x".to_string())));
+    let names: Vec<_> = rows[0].columns().iter().map(|(name, _)| &**name).collect();
+    assert_eq!(names, ["a", "b"]);
+
+    let rows = query_dynamic_async(
+        "SELECT $1::text AS name, $2 + 1 AS next, NULL AS nothing",
+        vec![DynamicValue::Text("ada".to_string()), DynamicValue::Int(41)],
+        &pool,
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        rows[0].get("name"),
+        Some(&DynamicValue::Text("ada".to_string()))
+    );
+    assert_eq!(rows[0].get("next"), Some(&DynamicValue::Int(42)));
+    assert_eq!(rows[0].get("nothing"), Some(&DynamicValue::Null));
+    assert_eq!(rows[0].get("missing"), None);
+}
+
+#[tokio::test(threaded_scheduler)]
+#[ignore = "needs Postgres at DATABASE_URL"]
+async fn explain_async_returns_the_plan_of_the_query() {
+    let pool = common::pg_pool();
+
+    let plan = users::table
+        .filter(users::name.eq("ada"))
+        .explain_async(false, &pool)
+        .await
+        .unwrap();
+    assert!(
+        plan.contains("Seq Scan") || plan.contains("Index Scan"),
+        "{}",
+        plan
+    );
+
+    let plan = users::table
+        .find(1)
+        .explain_async(true, &pool)
+        .await
+        .unwrap();
+    assert!(plan.contains("Scan"), "{}", plan);
+    assert!(plan.contains("actual time"), "{}", plan);
+}
+
+// A pool of a single connection to the database `name` on the server of `DATABASE_URL`
+fn pg_pool_of(name: &str) -> Pool<ConnectionManager<PgConnection>> {
+    let url = common::database_url();
+    let server = &url[..url.rfind('/').unwrap()];
+    let pool = Pool::builder()
+        .max_size(1)
+        .build(ConnectionManager::<PgConnection>::new(format!(
+            "{}/{}",
+            server, name
+        )))
+        .unwrap();
+    pool.get().unwrap().batch_execute(common::ACCOUNTS).unwrap();
+    pool
+}
+
+#[tokio::test(threaded_scheduler)]
+#[ignore = "needs Postgres at DATABASE_URL, with the databases shard_a and shard_b"]
+async fn a_sharded_pool_writes_to_the_database_of_the_key() {
+    let (shard_a, shard_b) = (pg_pool_of("shard_a"), pg_pool_of("shard_b"));
+    let shards = vec![(0, shard_a.clone()), (1, shard_b.clone())];
+    let pool = ShardedPool::new(shards.into_iter().collect(), |tenant: &i32| tenant % 3);
+    let email = format!("{}@example.com", uuid::Uuid::new_v4());
+    let count = |shard| {
+        accounts::table
+            .filter(accounts::email.eq(email.clone()))
+            .count_async(shard)
+    };
+
+    // Tenant 4 lives on shard 1
+    diesel::insert_into(accounts::table)
+        .values(accounts::email.eq(email.clone()))
+        .execute_async(pool.shard(&4).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(count(&shard_a).await.unwrap(), 0);
+    assert_eq!(count(&shard_b).await.unwrap(), 1);
+
+    let found = pool
+        .run_on_shard(&4, {
+            let email = email.clone();
+            move |conn: &PgConnection| {
+                accounts::table
+                    .filter(accounts::email.eq(email))
+                    .count()
+                    .get_result::<i64>(conn)
+            }
+        })
+        .await
+        .unwrap();
+    assert_eq!(found, 1);
+
+    // Tenant 5 is routed to shard 2, which does not exist
+    assert!(matches!(pool.shard(&5), Err(AsyncError::NoShard)));
+    let result = pool.run_on_shard(&5, |_: &PgConnection| Ok(())).await;
+    assert!(matches!(result, Err(AsyncError::NoShard)));
+
+    diesel::delete(accounts::table.filter(accounts::email.eq(email.clone())))
+        .execute_async(&shard_b)
+        .await
+        .unwrap();
+}
+
+#[tokio::test(threaded_scheduler)]
+#[ignore = "needs Postgres at DATABASE_URL"]
+async fn transaction_deferred_checks_a_deferrable_foreign_key_at_commit() {
+    let pool = common::pg_pool();
+    pool.batch_execute_async(
+        "CREATE TEMPORARY TABLE parents (id INTEGER PRIMARY KEY); \
+         CREATE TEMPORARY TABLE children (id INTEGER PRIMARY KEY, parent_id INTEGER NOT NULL \
+         REFERENCES parents DEFERRABLE INITIALLY IMMEDIATE)",
+    )
+    .await
+    .unwrap();
+    let child_first = |conn: &PgConnection| {
+        conn.batch_execute(
+            "INSERT INTO children VALUES (1, 1); \
+             INSERT INTO parents VALUES (1)",
+        )
+    };
+
+    // The child is inserted before its parent
+    pool.transaction(child_first).await.unwrap_err();
+    tokio_diesel::transaction_deferred(&pool, child_first)
+        .await
+        .unwrap();
+
+    // A child still missing its parent at commit rolls the transaction back
+    let err = tokio_diesel::transaction_deferred(&pool, |conn| {
+        conn.batch_execute("INSERT INTO children VALUES (2, 2)")
+    })
+    .await
+    .unwrap_err();
+    assert!(matches!(err, AsyncError::Error(_)), "{:?}", err);
+    let children: i64 = pool
+        .run(|conn| diesel::dsl::sql("SELECT count(*) FROM children").get_result(conn))
+        .await
+        .unwrap();
+    assert_eq!(children, 1);
+}
+
+#[tokio::test(threaded_scheduler)]
+#[ignore = "needs Postgres at DATABASE_URL"]
+async fn run_with_statement_timeout_cancels_a_slower_query_and_then_resets() {
+    let pool = common::pg_pool();
+    let sleep = |conn: &PgConnection| conn.batch_execute("SELECT pg_sleep(0.5)");
+
+    let started = Instant::now();
+    let err = tokio_diesel::run_with_statement_timeout(&pool, Duration::from_millis(100), sleep)
+        .await
+        .unwrap_err();
+    assert!(started.elapsed() < Duration::from_millis(400));
+    assert!(
+        err.to_string().contains("statement timeout"),
+        "unexpected error: {}",
+        err
+    );
+
+    tokio_diesel::run_with_statement_timeout(&pool, Duration::from_secs(5), sleep)
+        .await
+        .unwrap();
+    // The timeout does not outlive the transaction on the connection
+    let timeout: String = pool
+        .run(|conn| diesel::dsl::sql("SHOW statement_timeout").get_result(conn))
+        .await
+        .unwrap();
+    assert_eq!(timeout, "0");
+}
+
+// The guard only implements the async traits without `tokio-rt-threaded`
+#[cfg(not(feature = "tokio-rt-threaded"))]
+#[tokio::test(threaded_scheduler)]
+#[ignore = "needs Postgres at DATABASE_URL"]
+async fn dsl_calls_on_a_guard_share_its_session() {
+    let pool = pg_pool(2);
+    let application_name = || {
+        diesel::select(diesel::dsl::sql::<Text>(
+            "current_setting('application_name')",
+        ))
+    };
+
+    let guard = pool.acquire_async().await.unwrap();
+    diesel::sql_query("SET application_name = 'guarded'")
+        .execute_async(&guard)
+        .await
+        .unwrap();
+    let name: String = application_name().get_result_async(&guard).await.unwrap();
+    assert_eq!(name, "guarded");
+
+    // The pool hands out its other connection meanwhile, which has a session of its own
+    let name: String = application_name().get_result_async(&pool).await.unwrap();
+    assert_ne!(name, "guarded");
+    drop(guard);
+}
+
+#[tokio::test(threaded_scheduler)]
+#[ignore = "needs Postgres at DATABASE_URL"]
+async fn a_split_pool_runs_the_returning_methods_on_the_primary() {
+    // Each pool has a temporary `users` table of its own
+    let split = SplitPool::new(common::pg_pool(), vec![common::pg_pool()]);
+
+    let inserted: Vec<User> = diesel::insert_into(users::table)
+        .values(users::name.eq("ada"))
+        .execute_returning_async(&split)
+        .await
+        .unwrap();
+    let renamed: Vec<User> = diesel::update(users::table.find(inserted[0].id))
+        .set(users::name.eq("grace"))
+        .update_returning_async(&split)
+        .await
+        .unwrap();
+    assert_eq!(
+        renamed,
+        [User {
+            id: inserted[0].id,
+            name: "grace".to_string(),
+        }]
+    );
+
+    assert_eq!(users::table.count_async(split.primary()).await.unwrap(), 1);
+    assert_eq!(users::table.count_async(&split).await.unwrap(), 0);
+    let deleted: Vec<User> = diesel::delete(users::table)
+        .delete_returning_async(&split)
+        .await
+        .unwrap();
+    assert_eq!(deleted, renamed);
+}
@@ -0,0 +1,331 @@
+#![cfg(feature = "tracing")]
+
+#[macro_use]
+extern crate diesel;
+
+mod common;
+
+use common::users;
+use diesel::sqlite::SqliteConnection;
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, Once,
+    },
+    thread,
+    time::Duration,
+};
+use tokio_diesel::*;
+use tracing::{
+    field::{Field, Visit},
+    span, Event, Instrument, Level, Metadata, Subscriber,
+};
+use tracing_core::span::Current;
+
+// A span or an event, with its fields and the id of the span it belongs to
+#[derive(Debug, Clone)]
+struct Recorded {
+    metadata: &'static Metadata<'static>,
+    parent: Option<u64>,
+    fields: HashMap<&'static str, String>,
+}
+
+impl Recorded {
+    fn field(&self, name: &str) -> Option<&str> {
+        self.fields.get(name).map(String::as_str)
+    }
+}
+
+impl Visit for Recorded {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.fields.insert(field.name(), value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.fields.insert(field.name(), format!("{:?}", value));
+    }
+}
+
+// Records every span and event of the test binary, installed as the global subscriber since
+// the blocking work runs on threads of its own
+#[derive(Clone, Default)]
+struct Recorder {
+    next_id: Arc<AtomicU64>,
+    spans: Arc<Mutex<HashMap<u64, Recorded>>>,
+    events: Arc<Mutex<Vec<Recorded>>>,
+}
+
+thread_local! {
+    // The spans entered on this thread, innermost last
+    static ENTERED: RefCell<Vec<(u64, &'static Metadata<'static>)>> = const { RefCell::new(Vec::new()) };
+}
+
+fn recorder() -> Recorder {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| tracing::subscriber::set_global_default(Recorder::default()).unwrap());
+    tracing::dispatcher::get_default(|dispatch| {
+        dispatch.downcast_ref::<Recorder>().unwrap().clone()
+    })
+}
+
+impl Recorder {
+    // The spans whose parent is `parent`
+    fn children(&self, parent: &tracing::Span) -> Vec<(u64, Recorded)> {
+        let parent = parent.id().map(|id| id.into_u64());
+        let mut children: Vec<_> = self
+            .spans
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, span)| span.parent == parent)
+            .map(|(&id, span)| (id, span.clone()))
+            .collect();
+        children.sort_by_key(|&(id, _)| id);
+        children
+    }
+
+    // The events within the span `id`
+    fn events(&self, id: u64) -> Vec<Recorded> {
+        self.events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|event| event.parent == Some(id))
+            .cloned()
+            .collect()
+    }
+
+    fn current() -> Option<(u64, &'static Metadata<'static>)> {
+        ENTERED.with(|entered| entered.borrow().last().copied())
+    }
+}
+
+impl Subscriber for Recorder {
+    fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, attrs: &span::Attributes<'_>) -> span::Id {
+        let parent = match attrs.parent() {
+            Some(parent) => Some(parent.into_u64()),
+            None if attrs.is_contextual() => Recorder::current().map(|(id, _)| id),
+            None => None,
+        };
+        let mut span = Recorded {
+            metadata: attrs.metadata(),
+            parent,
+            fields: HashMap::new(),
+        };
+        attrs.record(&mut span);
+
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst) + 1;
+        self.spans.lock().unwrap().insert(id, span);
+        span::Id::from_u64(id)
+    }
+
+    fn record(&self, id: &span::Id, values: &span::Record<'_>) {
+        if let Some(span) = self.spans.lock().unwrap().get_mut(&id.into_u64()) {
+            values.record(span);
+        }
+    }
+
+    fn record_follows_from(&self, _span: &span::Id, _follows: &span::Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let parent = match event.parent() {
+            Some(parent) => Some(parent.into_u64()),
+            None if event.is_contextual() => Recorder::current().map(|(id, _)| id),
+            None => None,
+        };
+        let mut recorded = Recorded {
+            metadata: event.metadata(),
+            parent,
+            fields: HashMap::new(),
+        };
+        event.record(&mut recorded);
+        self.events.lock().unwrap().push(recorded);
+    }
+
+    fn enter(&self, id: &span::Id) {
+        let metadata = self.spans.lock().unwrap()[&id.into_u64()].metadata;
+        ENTERED.with(|entered| entered.borrow_mut().push((id.into_u64(), metadata)));
+    }
+
+    fn exit(&self, _id: &span::Id) {
+        ENTERED.with(|entered| entered.borrow_mut().pop());
+    }
+
+    fn current_span(&self) -> Current {
+        match Recorder::current() {
+            Some((id, metadata)) => Current::new(span::Id::from_u64(id), metadata),
+            None => Current::none(),
+        }
+    }
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn operations_run_in_a_span_recording_checkout_and_query() {
+    let recorder = recorder();
+    let pool = common::sqlite_pool();
+    let test = tracing::info_span!("operations_run_in_a_span");
+
+    users::table
+        .count_async(&pool)
+        .instrument(test.clone())
+        .await
+        .unwrap();
+    diesel::sql_query("SELEC 1")
+        .execute_async(&pool)
+        .instrument(test.clone())
+        .await
+        .unwrap_err();
+
+    let spans = recorder.children(&test);
+    assert_eq!(spans.len(), 2);
+    for (_, span) in &spans {
+        assert_eq!(span.metadata.name(), "tokio_diesel.run");
+        assert_eq!(span.field("op"), Some("run"));
+        assert_eq!(span.field("checkout"), Some("true"));
+        assert!(span.field("checkout_ms").is_some());
+        assert!(span.field("query_ms").is_some());
+    }
+
+    assert!(recorder.events(spans[0].0).is_empty());
+    let errors = recorder.events(spans[1].0);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(*errors[0].metadata.level(), Level::ERROR);
+    assert_eq!(errors[0].field("message"), Some("query failed"));
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn the_blocking_work_runs_within_the_span_of_the_caller() {
+    let recorder = recorder();
+    let pool = common::sqlite_pool();
+    let request = tracing::info_span!("request", id = 7);
+
+    pool.transaction(|_| {
+        tracing::info!("within the transaction");
+        Ok(())
+    })
+    .instrument(request.clone())
+    .await
+    .unwrap();
+
+    let spans = recorder.children(&request);
+    assert_eq!(spans.len(), 1);
+    let (id, span) = &spans[0];
+    assert_eq!(span.field("op"), Some("transaction"));
+    let events = recorder.events(*id);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].field("message"), Some("within the transaction"));
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn a_query_slower_than_the_threshold_is_warned_about() {
+    let recorder = recorder();
+    let pool = AsyncPool::<SqliteConnection>::builder()
+        .max_size(1)
+        .slow_query_threshold(Duration::from_millis(50))
+        .build(":memory:")
+        .unwrap();
+    let test = tracing::info_span!("slow_query");
+
+    pool.run(|_| Ok(())).instrument(test.clone()).await.unwrap();
+    pool.run(|_| {
+        thread::sleep(Duration::from_millis(100));
+        Ok(())
+    })
+    .instrument(test.clone())
+    .await
+    .unwrap();
+
+    let spans = recorder.children(&test);
+    assert_eq!(spans.len(), 2);
+    assert!(recorder.events(spans[0].0).is_empty());
+    let warnings = recorder.events(spans[1].0);
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(*warnings[0].metadata.level(), Level::WARN);
+    assert_eq!(warnings[0].field("message"), Some("slow query"));
+    assert_eq!(warnings[0].field("op"), Some("run"));
+    let query_ms: f64 = warnings[0].field("query_ms").unwrap().parse().unwrap();
+    assert!(query_ms >= 100.0);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn a_connection_held_past_the_threshold_is_warned_about() {
+    let recorder = recorder();
+    let pool = AsyncPool::<SqliteConnection>::builder()
+        .max_size(1)
+        .leak_detection_threshold(Duration::from_millis(50))
+        .build(":memory:")
+        .unwrap();
+    let leaks = || {
+        recorder
+            .events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|event| {
+                event.field("message")
+                    == Some("connection held longer than the leak detection threshold")
+            })
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+
+    pool.run(|_| Ok(())).await.unwrap();
+    assert!(leaks().is_empty());
+
+    pool.run(|_| {
+        thread::sleep(Duration::from_millis(200));
+        Ok(())
+    })
+    .await
+    .unwrap();
+    let leaks = leaks();
+    assert_eq!(leaks.len(), 1);
+    assert_eq!(*leaks[0].metadata.level(), Level::WARN);
+    assert_eq!(leaks[0].field("op"), Some("run"));
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn the_watchdog_fires_while_a_slow_transaction_closure_runs() {
+    let recorder = recorder();
+    let pool = AsyncPool::<SqliteConnection>::builder()
+        .max_size(1)
+        .leak_detection_threshold(Duration::from_millis(50))
+        .build(":memory:")
+        .unwrap();
+    let watchdog = {
+        let recorder = recorder.clone();
+        move || {
+            recorder
+                .events
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|event| {
+                    *event.metadata.level() == Level::WARN
+                        && event.field("op") == Some("transaction")
+                })
+                .count()
+        }
+    };
+
+    pool.transaction(|_| Ok(())).await.unwrap();
+    assert_eq!(watchdog(), 0);
+
+    let watchdog_ = watchdog.clone();
+    let fired_while_running = pool
+        .transaction(move |_| {
+            thread::sleep(Duration::from_millis(200));
+            Ok(watchdog_() == 1)
+        })
+        .await
+        .unwrap();
+    assert!(fired_while_running);
+    assert_eq!(watchdog(), 1);
+}
@@ -0,0 +1,134 @@
+#![cfg(feature = "sqlite")]
+
+#[macro_use]
+extern crate diesel;
+
+mod common;
+
+use common::users;
+use diesel::{prelude::*, sqlite::SqliteConnection};
+use std::{
+    env, fs,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+use tokio_diesel::*;
+
+// A database file shared by the connections of a pool, removed once dropped
+struct DatabaseFile(PathBuf);
+
+impl DatabaseFile {
+    fn new() -> Self {
+        let path = env::temp_dir().join(format!("tokio_diesel_{}.db", uuid::Uuid::new_v4()));
+        DatabaseFile(path)
+    }
+
+    fn url(&self) -> String {
+        self.0.to_str().unwrap().to_string()
+    }
+}
+
+impl Drop for DatabaseFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+// Insert the rows `first` and `first + 1` with a pause in between, noting each of them in
+// `inserted`
+fn write_two(conn: &SqliteConnection, first: i32, inserted: &Mutex<Vec<i32>>) -> QueryResult<()> {
+    for id in first..first + 2 {
+        diesel::insert_into(users::table)
+            .values((users::id.eq(id), users::name.eq("writer")))
+            .execute(conn)?;
+        inserted.lock().unwrap().push(id);
+        thread::sleep(Duration::from_millis(50));
+    }
+    Ok(())
+}
+
+// Whether the writers of `write_two` took turns rather than interleaving their rows
+fn in_turns(inserted: &Mutex<Vec<i32>>) -> bool {
+    let inserted = inserted.lock().unwrap();
+    *inserted == [1, 2, 3, 4] || *inserted == [3, 4, 1, 2]
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn concurrent_writers_wait_for_each_other_with_a_busy_timeout() {
+    let file = DatabaseFile::new();
+    let pool = AsyncPool::<SqliteConnection>::builder()
+        .max_size(2)
+        .busy_timeout(Duration::from_secs(5))
+        .build(file.url())
+        .unwrap();
+    pool.batch_execute_async(common::USERS).await.unwrap();
+
+    let inserted = Arc::new(Mutex::new(Vec::new()));
+    let writers: Vec<_> = [1, 3]
+        .iter()
+        .map(|&first| {
+            let (pool, inserted) = (pool.clone(), inserted.clone());
+            tokio::spawn(async move {
+                pool.transaction(move |conn| write_two(conn, first, &inserted))
+                    .await
+            })
+        })
+        .collect();
+    for writer in writers {
+        writer.await.unwrap().unwrap();
+    }
+    assert!(in_turns(&inserted));
+    assert_eq!(users::table.count_async(&pool).await.unwrap(), 4);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn transaction_retry_busy_retries_a_locked_database() {
+    let file = DatabaseFile::new();
+    let pool = AsyncPool::<SqliteConnection>::builder()
+        .max_size(2)
+        .build(file.url())
+        .unwrap();
+    pool.batch_execute_async(common::USERS).await.unwrap();
+
+    let inserted = Arc::new(Mutex::new(Vec::new()));
+    let writers: Vec<_> = [1, 3]
+        .iter()
+        .map(|&first| {
+            let (pool, inserted) = (pool.clone(), inserted.clone());
+            tokio::spawn(async move {
+                transaction_retry_busy(&pool, 10, move |conn| write_two(conn, first, &inserted))
+                    .await
+            })
+        })
+        .collect();
+    for writer in writers {
+        writer.await.unwrap().unwrap();
+    }
+    assert!(in_turns(&inserted));
+    assert_eq!(users::table.count_async(&pool).await.unwrap(), 4);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn insert_and_get_rowid_async_returns_the_id_of_the_new_row() {
+    let pool = common::sqlite_pool();
+    pool.batch_execute_async(&common::insert_users(3))
+        .await
+        .unwrap();
+
+    let rowid = insert_and_get_rowid_async(
+        diesel::insert_into(users::table).values(users::name.eq("ada")),
+        &pool,
+    )
+    .await
+    .unwrap();
+    assert_eq!(rowid, 4);
+    let name = users::table
+        .find(rowid as i32)
+        .select(users::name)
+        .first_async::<String>(&pool)
+        .await
+        .unwrap();
+    assert_eq!(name, "ada");
+}
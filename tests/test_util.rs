@@ -0,0 +1,37 @@
+#![cfg(feature = "test-util")]
+
+#[macro_use]
+extern crate diesel;
+
+mod common;
+
+use common::accounts;
+use diesel::{connection::SimpleConnection, pg::PgConnection, prelude::*};
+use tokio_diesel::*;
+
+#[tokio::test(threaded_scheduler)]
+#[ignore = "needs Postgres at DATABASE_URL"]
+async fn test_transactions_do_not_see_each_others_writes() {
+    let pool = common::pg_pool();
+    pool.get().unwrap().batch_execute(common::ACCOUNTS).unwrap();
+    let email = format!("{}@example.com", uuid::Uuid::new_v4());
+    let find = || accounts::table.filter(accounts::email.eq(email.clone()));
+
+    let first = TestTransaction::<PgConnection>::begin(&common::database_url())
+        .await
+        .unwrap();
+    let second = TestTransaction::<PgConnection>::begin(&common::database_url())
+        .await
+        .unwrap();
+    diesel::insert_into(accounts::table)
+        .values(accounts::email.eq(email.clone()))
+        .execute_async(&first)
+        .await
+        .unwrap();
+    assert_eq!(find().count_async(&first).await.unwrap(), 1);
+    assert_eq!(find().count_async(&second).await.unwrap(), 0);
+
+    drop(first);
+    assert_eq!(find().count_async(&second).await.unwrap(), 0);
+    assert_eq!(find().count_async(&pool).await.unwrap(), 0);
+}
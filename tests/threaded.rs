@@ -0,0 +1,68 @@
+#![cfg(feature = "tokio-rt-threaded")]
+
+#[macro_use]
+extern crate diesel;
+
+mod common;
+
+use common::users;
+use diesel::{prelude::*, result::Error};
+use std::collections::HashMap;
+use tokio_diesel::*;
+
+#[tokio::test(threaded_scheduler)]
+async fn transaction_blocking_updates_borrowed_state_between_queries() {
+    let pool = common::sqlite_pool();
+    pool.batch_execute_async(&common::insert_users(3))
+        .await
+        .unwrap();
+    let mut cache = HashMap::new();
+
+    pool.transaction_blocking(|conn| {
+        for id in 1..=3 {
+            let name: String = users::table.find(id).select(users::name).first(conn)?;
+            let renamed = name.to_uppercase();
+            diesel::update(users::table.find(id))
+                .set(users::name.eq(&renamed))
+                .execute(conn)?;
+            cache.insert(id, renamed);
+        }
+        Ok(())
+    })
+    .await
+    .unwrap();
+    assert_eq!(cache[&2], "USER2");
+
+    // A rolled back transaction leaves the database alone, but not the state it borrowed
+    let result = pool
+        .transaction_blocking(|conn| {
+            diesel::delete(users::table).execute(conn)?;
+            cache.clear();
+            Err::<(), _>(Error::RollbackTransaction)
+        })
+        .await;
+    assert!(result.is_err());
+    assert!(cache.is_empty());
+
+    let names = users::table
+        .select(users::name)
+        .order(users::id)
+        .load_async::<String>(&pool)
+        .await
+        .unwrap();
+    assert_eq!(names, ["USER1", "USER2", "USER3"]);
+}
+
+#[test]
+fn operations_run_in_place_outside_of_a_runtime() {
+    let pool = common::sqlite_pool();
+    let count = futures::executor::block_on(users::table.count_async(&pool)).unwrap();
+    assert_eq!(count, 0);
+}
+
+#[tokio::test]
+#[should_panic(expected = "can call blocking only when running on the multi-threaded runtime")]
+async fn operations_panic_on_the_basic_scheduler() {
+    let pool = common::sqlite_pool();
+    let _ = users::table.count_async(&pool).await;
+}
@@ -0,0 +1,66 @@
+#[macro_use]
+extern crate diesel;
+
+mod common;
+
+use common::users;
+use diesel::{connection::SimpleConnection, prelude::*, sqlite::SqliteConnection};
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+use tokio_diesel::*;
+
+fn wrapper() -> AsyncConnectionWrapper<SqliteConnection> {
+    let conn = SqliteConnection::establish(":memory:").unwrap();
+    conn.batch_execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT NOT NULL)")
+        .unwrap();
+    AsyncConnectionWrapper::new(conn)
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn concurrent_execute_async_calls_take_turns_on_the_connection() {
+    let conn = wrapper();
+
+    let inserts: Vec<_> = (1..=2)
+        .map(|id| {
+            let conn = conn.clone();
+            tokio::spawn(async move {
+                diesel::insert_into(users::table)
+                    .values((users::id.eq(id), users::name.eq(format!("user{}", id))))
+                    .execute_async(&conn)
+                    .await
+            })
+        })
+        .collect();
+    for insert in inserts {
+        assert_eq!(insert.await.unwrap().unwrap(), 1);
+    }
+    assert_eq!(users::table.count_async(&conn).await.unwrap(), 2);
+
+    let running = Arc::new(AtomicUsize::new(0));
+    let most = Arc::new(AtomicUsize::new(0));
+    let runs: Vec<_> = (0..2)
+        .map(|_| {
+            let (conn, running, most) = (conn.clone(), running.clone(), most.clone());
+            tokio::spawn(async move {
+                conn.run(move |_| {
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    most.fetch_max(now, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(50));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                    Ok(())
+                })
+                .await
+            })
+        })
+        .collect();
+    for run in runs {
+        run.await.unwrap().unwrap();
+    }
+    assert_eq!(most.load(Ordering::SeqCst), 1);
+}